@@ -0,0 +1,105 @@
+#ifndef COV2HTML_H
+#define COV2HTML_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a `cov2html_generate` call
+ */
+typedef enum Cov2HtmlStatus {
+  /**
+   * The report was written
+   */
+  COV2HTML_STATUS_OK = 0,
+  /**
+   * A required path was NULL or not valid UTF-8
+   */
+  COV2HTML_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * The coverage file could not be read or, in strict mode, had a malformed line
+   */
+  COV2HTML_STATUS_READ_COVERAGE = 2,
+  /**
+   * In strict mode, source files of the coverage data could not be read
+   */
+  COV2HTML_STATUS_MISSING_SOURCES = 3,
+  /**
+   * Writing the report failed
+   */
+  COV2HTML_STATUS_WRITE_REPORT = 4,
+  /**
+   * Report generation panicked
+   */
+  COV2HTML_STATUS_PANIC = 5,
+  /**
+   * In strict mode, sources differed from the checksums recorded with the
+   * coverage data
+   */
+  COV2HTML_STATUS_CHECKSUM_MISMATCH = 6,
+} Cov2HtmlStatus;
+
+/**
+ * Report settings; start from `cov2html_default_options()`
+ */
+typedef struct Cov2HtmlOptions {
+  /**
+   * Title shown in the page title and headings, or NULL for the default
+   */
+  const char *title;
+  /**
+   * Fail on malformed coverage lines and missing or unreadable sources
+   */
+  bool strict;
+  /**
+   * Worker threads used to read sources and render pages; 0 uses one per
+   * logical CPU
+   */
+  size_t jobs;
+  /**
+   * Write per-file data chunks next to the report and load them on demand
+   */
+  bool split_data;
+  /**
+   * Emit CSS, JavaScript and data as external files
+   */
+  bool external_assets;
+  /**
+   * Pre-render every file into static HTML pages that work without JavaScript
+   */
+  bool no_js;
+} Cov2HtmlOptions;
+
+/**
+ * Options matching the defaults of `cov2html report`
+ */
+struct Cov2HtmlOptions cov2html_default_options(void);
+
+/**
+ * Generates a report of `coverage_path` with sources from `source_dir` into
+ * `out_dir`. `options` may be NULL to use the defaults. On failure the
+ * reason is available from `cov2html_last_error()`.
+ *
+ * # Safety
+ *
+ * The paths must be NULL-terminated strings, and `options` must be NULL or
+ * point to a valid `Cov2HtmlOptions` whose `title` is NULL or a
+ * NULL-terminated string.
+ */
+enum Cov2HtmlStatus cov2html_generate(const char *coverage_path,
+                                      const char *source_dir,
+                                      const char *out_dir,
+                                      const struct Cov2HtmlOptions *options);
+
+/**
+ * Message describing why the last failed call on this thread failed, or
+ * NULL. The string is valid until the next call on this thread.
+ */
+const char *cov2html_last_error(void);
+
+#endif /* COV2HTML_H */
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::fs;
+#[cfg(feature = "html")]
+use std::io::Write;
+#[cfg(feature = "async")]
+use std::panic;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use log::info;
+
+use crate::coverage::{
+    detect_build_prefixes, focus_coverage, focus_path, generate_report, parse_coverage_file_with_warnings, remap_coverage, remap_path, CoverageSummary, CoverageThresholds, ExclusionPreset, PathMapping,
+    PhaseStart, PhaseStats, ReportOptions,
+};
+use crate::error::{Cov2HtmlError, Warning};
+use crate::executable::{Denominator, KernelConfig, LineTables};
+#[cfg(feature = "html")]
+use crate::html::write_html_report;
+use crate::lines::LineSet;
+use crate::model::CoverageReport;
+use crate::parser::{normalize_separators, parse_checksums, Checksums, InputFormat};
+use crate::progress::{Progress, ProgressReporter};
+use crate::renderer::ReportRenderer;
+use crate::source::SourceTree;
+
+/// Where the coverage of a report comes from
+#[derive(Debug, Clone)]
+enum CoverageInput {
+    /// A coverage file, parsed and remapped when the report is generated
+    File(String),
+    /// Already parsed coverage data
+    Map(HashMap<String, LineSet>),
+}
+
+/// Coverage data with the time spent parsing it, the warnings raised and
+/// the checksums recorded along with it
+type LoadedCoverage = (HashMap<String, LineSet>, Option<PhaseStats>, Vec<Warning>, Checksums);
+
+/// Configures and generates an HTML coverage report.
+///
+/// ```no_run
+/// use cov2html::ReportBuilder;
+///
+/// let summary = ReportBuilder::new()
+///     .coverage("cov.txt")
+///     .source_root("linux")
+///     .title("ext4 coverage")
+///     .output("report")
+///     .generate()?;
+/// println!("{:.2}% covered", summary.coverage_pct());
+/// # Ok::<(), cov2html::error::Cov2HtmlError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReportBuilder {
+    coverage: Option<CoverageInput>,
+    source_roots: Vec<String>,
+    source_tree: Option<Arc<dyn SourceTree>>,
+    output: Option<String>,
+    options: ReportOptions,
+}
+
+impl ReportBuilder {
+    /// Starts a report with the default options
+    pub fn new() -> ReportBuilder {
+        ReportBuilder::default()
+    }
+
+    /// Reads coverage from a `path:line[:count]` file
+    pub fn coverage(mut self, coverage_file: &str) -> Self {
+        self.coverage = Some(CoverageInput::File(coverage_file.to_string()));
+        self
+    }
+
+    /// Uses already parsed coverage data; path mappings and the subtree root
+    /// are not applied to it
+    pub fn coverage_map(mut self, coverage_map: HashMap<String, LineSet>) -> Self {
+        self.coverage = Some(CoverageInput::Map(coverage_map));
+        self
+    }
+
+    /// Uses the covered lines of a structured coverage report
+    pub fn coverage_report(self, report: &CoverageReport) -> Self {
+        self.coverage_map(report.to_map())
+    }
+
+    /// Adds a source root; roots are searched in the order they were added
+    pub fn source_root(mut self, root: &str) -> Self {
+        self.source_roots.push(root.to_string());
+        self
+    }
+
+    /// Adds several source roots
+    pub fn source_roots<I: IntoIterator<Item = S>, S: Into<String>>(mut self, roots: I) -> Self {
+        self.source_roots.extend(roots.into_iter().map(Into::into));
+        self
+    }
+
+    /// Reads sources from a tree instead of the source roots, e.g. from
+    /// memory with `MemorySources`
+    pub fn source_tree(mut self, sources: impl SourceTree + 'static) -> Self {
+        self.source_tree = Some(Arc::new(sources));
+        self
+    }
+
+    /// Directory the report is written to
+    pub fn output(mut self, work_dir: &str) -> Self {
+        self.output = Some(work_dir.to_string());
+        self
+    }
+
+    /// Title shown in the page title and headings
+    pub fn title(mut self, title: &str) -> Self {
+        self.options.title = title.to_string();
+        self
+    }
+
+    /// Cutoffs used to color coverage percentages
+    pub fn thresholds(mut self, thresholds: CoverageThresholds) -> Self {
+        self.options.thresholds = thresholds;
+        self
+    }
+
+    /// Rewrites coverage paths before sources are looked up; the first
+    /// matching mapping wins
+    pub fn path_mapping(mut self, mapping: PathMapping) -> Self {
+        self.options.path_mappings.push(mapping);
+        self
+    }
+
+    /// Strips the build directory of absolute coverage paths, detected from
+    /// the sources; on by default
+    pub fn detect_build_prefix(mut self, detect: bool) -> Self {
+        self.options.detect_build_prefix = detect;
+        self
+    }
+
+    /// Matches coverage paths to sources without regard to letter case
+    pub fn ignore_case_paths(mut self, ignore_case: bool) -> Self {
+        self.options.ignore_case_paths = ignore_case;
+        self
+    }
+
+    /// Reports only on the files below a directory, relative to it
+    pub fn root(mut self, root: &str) -> Self {
+        self.options.root = Some(normalize_separators(root).trim_matches('/').to_string()).filter(|root| !root.is_empty());
+        self
+    }
+
+    /// Highlights lines gained and lost against an earlier coverage file
+    pub fn baseline(mut self, baseline_file: &str) -> Self {
+        self.options.baseline = Some(baseline_file.to_string());
+        self
+    }
+
+    /// Shows a coverage goal for a directory (`""` for the whole tree)
+    pub fn goal(mut self, directory: &str, percent: f64) -> Self {
+        self.options.goals.push((directory.trim_end_matches('/').to_string(), percent));
+        self
+    }
+
+    /// Also counts never covered source files matching a glob
+    pub fn include_uncovered(mut self, glob: &str) -> Self {
+        self.options.include_uncovered.push(glob.to_string());
+        self
+    }
+
+    /// Leaves out the files matched by a built-in preset
+    pub fn exclude(mut self, preset: ExclusionPreset) -> Self {
+        self.options.exclusions.push(preset);
+        self
+    }
+
+    /// Lines each file's coverage is taken over; the executable ones by default
+    pub fn denominator(mut self, denominator: Denominator) -> Self {
+        self.options.denominator = denominator;
+        self
+    }
+
+    /// Lines with instructions per file, as read from the DWARF of the
+    /// binary the coverage came from; with the `dwarf` denominator, the
+    /// files listed are counted over them
+    pub fn line_tables(mut self, line_tables: LineTables) -> Self {
+        self.options.line_tables = line_tables;
+        self
+    }
+
+    /// Kernel `.config` whose unset options mark the `#ifdef CONFIG_X`
+    /// blocks that are not compiled
+    pub fn kernel_config(mut self, config: KernelConfig) -> Self {
+        self.options.kernel_config = Some(config);
+        self
+    }
+
+    /// Format of the coverage file and the baseline; detected by default
+    pub fn input_format(mut self, format: InputFormat) -> Self {
+        self.options.input_format = format;
+        self
+    }
+
+    /// Output formats to write the report in, replacing the default HTML
+    pub fn emit(mut self, renderers: &[&'static dyn ReportRenderer]) -> Self {
+        self.options.renderers = renderers.to_vec();
+        self
+    }
+
+    /// Reads `report.html`, `report.css` and `report.js` templates from a
+    /// directory, falling back to the built-in ones for missing files
+    pub fn template_dir(mut self, dir: &str) -> Self {
+        self.options.template_dir = Some(dir.to_string());
+        self
+    }
+
+    /// Caches rendered HTML files in a directory, so that regenerating the
+    /// report only renders the files whose source or coverage changed
+    pub fn cache_dir(mut self, dir: &str) -> Self {
+        self.options.cache_dir = Some(dir.to_string());
+        self
+    }
+
+    /// Fails on malformed coverage lines and missing or unreadable sources
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Worker threads used to read sources and render pages; 0 uses one per logical CPU
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.options.jobs = jobs;
+        self
+    }
+
+    /// Calls `callback` as files are discovered, processed and written, e.g.
+    /// to drive a progress bar; it may be called from worker threads
+    pub fn progress(mut self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.options.progress = ProgressReporter::new(callback);
+        self
+    }
+
+    /// Sends the progress of report generation over a channel
+    pub fn progress_channel(mut self, sender: Sender<Progress>) -> Self {
+        self.options.progress = ProgressReporter::channel(sender);
+        self
+    }
+
+    /// Stops report generation with `Cov2HtmlError::Cancelled` once `cancel`
+    /// is set, e.g. from another thread; the previous report is left as it was
+    pub fn cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    /// Replaces all report options at once, e.g. for settings without a
+    /// dedicated method
+    pub fn options(mut self, options: ReportOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Gives access to every report option
+    pub fn options_mut(&mut self) -> &mut ReportOptions {
+        &mut self.options
+    }
+
+    /// Generates the report and returns the coverage totals it shows, along
+    /// with the files written and the warnings raised on the way
+    pub fn generate(mut self) -> Result<CoverageSummary, Cov2HtmlError> {
+        let work_dir = self.output.clone().ok_or(Cov2HtmlError::MissingSetting("output directory"))?;
+        let (coverage_map, parse_phase, parse_warnings, checksums) = self.load_coverage()?;
+        self.set_coverage_modified();
+        self.options.checksums.extend(checksums);
+
+        let mut summary = generate_report(&coverage_map, self.sources(), &work_dir, &self.options)?;
+        summary.phases.splice(0..0, parse_phase);
+        summary.warnings.splice(0..0, parse_warnings);
+
+        Ok(summary)
+    }
+
+    /// Like `generate`, running on tokio's blocking thread pool so reading
+    /// sources and writing the report never block the async workers
+    #[cfg(feature = "async")]
+    pub async fn generate_async(self) -> Result<CoverageSummary, Cov2HtmlError> {
+        tokio::task::spawn_blocking(move || self.generate())
+            .await
+            .unwrap_or_else(|e| panic::resume_unwind(e.into_panic()))
+    }
+
+    /// Writes the report as a single self-contained HTML page to `out`
+    /// instead of the output directory; see `write_html_report` for the
+    /// options that do not apply
+    #[cfg(feature = "html")]
+    pub fn write_html(mut self, out: &mut dyn Write) -> Result<CoverageSummary, Cov2HtmlError> {
+        let (coverage_map, parse_phase, parse_warnings, checksums) = self.load_coverage()?;
+        self.set_coverage_modified();
+        self.options.checksums.extend(checksums);
+
+        let mut summary = write_html_report(&coverage_map, self.sources(), &self.options, out)?;
+        summary.phases.splice(0..0, parse_phase);
+        summary.warnings.splice(0..0, parse_warnings);
+
+        Ok(summary)
+    }
+
+    /// The source tree, or else the source roots, sources are read from
+    fn sources(&self) -> &dyn SourceTree {
+        match &self.source_tree {
+            Some(sources) => sources.as_ref(),
+            None => &self.source_roots,
+        }
+    }
+
+    /// Takes the time the coverage was recorded from the coverage file,
+    /// unless it was set
+    fn set_coverage_modified(&mut self) {
+        if self.options.coverage_modified.is_none()
+            && let Some(CoverageInput::File(coverage_file)) = &self.coverage
+        {
+            self.options.coverage_modified = fs::metadata(coverage_file).and_then(|metadata| metadata.modified()).ok();
+        }
+    }
+
+    /// Parses, remaps and focuses the coverage file, if one was given, and
+    /// the checksum records in it with the same paths
+    fn load_coverage(&self) -> Result<LoadedCoverage, Cov2HtmlError> {
+        let options = &self.options;
+        let mut parse_warnings = Vec::new();
+
+        match self.coverage.as_ref().ok_or(Cov2HtmlError::MissingSetting("coverage"))? {
+            CoverageInput::File(coverage_file) => {
+                let start = PhaseStart::now();
+                let coverage_map = parse_coverage_file_with_warnings(coverage_file, options.input_format, options.strict, &mut parse_warnings)
+                    .map_err(|source| Cov2HtmlError::ReadCoverage { path: coverage_file.clone(), source })?;
+                let checksums = parse_checksums(coverage_file)
+                    .map_err(|source| Cov2HtmlError::ReadCoverage { path: coverage_file.clone(), source })?;
+                let mut coverage_map = remap_coverage(coverage_map, &options.path_mappings);
+                let prefixes = match options.detect_build_prefix {
+                    true => detect_build_prefixes(&coverage_map.keys().map(String::as_str).collect::<Vec<_>>(), self.sources(), options.jobs),
+                    false => Vec::new(),
+                };
+                coverage_map = remap_coverage(coverage_map, &prefixes);
+                if let Some(root) = &options.root {
+                    coverage_map = focus_coverage(coverage_map, root);
+                }
+                let checksums = checksums.into_iter()
+                    .filter_map(|(path, sum)| {
+                        let path = remap_path(&remap_path(&path, &options.path_mappings), &prefixes);
+                        match &options.root {
+                            Some(root) => focus_path(&path, root).map(|path| (path, sum)),
+                            None => Some((path, sum)),
+                        }
+                    })
+                    .collect();
+                info!("Parsed coverage data for {} files", coverage_map.len());
+                Ok((coverage_map, Some(PhaseStats::finish("parse", start)), parse_warnings, checksums))
+            }
+            CoverageInput::Map(coverage_map) => Ok((coverage_map.clone(), None, parse_warnings, Checksums::new())),
+        }
+    }
+}
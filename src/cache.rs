@@ -0,0 +1,430 @@
+//! Per-file fragments of earlier reports and what was learned about their
+//! sources, kept in a cache directory so that regenerating a report only
+//! reads and renders the files whose source or coverage changed.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use log::debug;
+
+use crate::coverage::{ExclusionPreset, Language};
+use crate::executable::{line_counts, non_code_lines, uncounted_lines, Denominator, KernelConfig};
+use crate::lines::LineSet;
+
+/// File in the cache directory listing the sources known from earlier runs
+const SOURCES_FILE: &str = "sources";
+
+/// 64-bit FNV-1a, which unlike the std hasher gives the same keys in every
+/// run and build
+#[derive(Clone)]
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// What a report needs from a source file besides rendering it
+#[derive(Debug, Clone)]
+pub(crate) struct SourceInfo {
+    /// FNV-1a hash of the contents
+    pub(crate) hash: u64,
+    /// Number of lines
+    pub(crate) lines: usize,
+    /// Blank, comment and preprocessor lines and code the preprocessor
+    /// leaves out, left out of its total
+    pub(crate) non_code: LineSet,
+    /// Lines disabled by the preprocessor or excluded by marker comments,
+    /// which never count and are part of `non_code` too
+    pub(crate) uncounted: LineSet,
+    /// Preset excluding the file by its contents
+    pub(crate) excluded: Option<ExclusionPreset>,
+}
+
+impl SourceInfo {
+    /// Hashes and counts the contents of a source in `language`
+    pub(crate) fn new(content: &str, language: Language, denominator: Denominator, config: Option<&KernelConfig>, exclusions: &[ExclusionPreset]) -> SourceInfo {
+        let mut hasher = Fnv::default();
+        hasher.write(content.as_bytes());
+        SourceInfo {
+            hash: hasher.finish(),
+            lines: content.lines().count(),
+            non_code: non_code_lines(content.as_bytes(), language, denominator, config),
+            uncounted: uncounted_lines(content.as_bytes(), language, config),
+            excluded: exclusions.iter().copied().find(|preset| preset.excludes_source(content.as_bytes())),
+        }
+    }
+
+    /// Covered lines and lines counted toward the total of the source: all
+    /// but the non-code ones, though covered lines count unless uncounted
+    pub(crate) fn line_counts(&self, covered_lines: &LineSet) -> (usize, usize) {
+        line_counts(self.lines, &self.non_code, &self.uncounted, covered_lines)
+    }
+}
+
+/// Sources by full path, with the modification time they were seen at
+type SourceIndex = HashMap<String, (SystemTime, SourceInfo)>;
+
+/// Rendered fragments keyed by a hash of everything they were rendered
+/// from. A fragment is the text returned for a file plus the files written
+/// for it, which are hard-linked (or copied) into the cache and back out.
+///
+/// The cache also remembers the hash and line count of every source by its
+/// modification time, so a file whose fragment is reused is not read at all.
+pub(crate) struct FragmentCache {
+    dir: PathBuf,
+    /// Keys of the fragments used by this report; the others are removed
+    /// once it is written
+    used: Mutex<HashSet<String>>,
+    hits: AtomicUsize,
+    /// Sources known from earlier runs
+    known_sources: SourceIndex,
+    /// Sources seen by this report, saved for the next one
+    sources: Mutex<SourceIndex>,
+    /// How the lines of the sources were told apart, the kernel
+    /// configuration and the presets their exclusions were checked against
+    counting: String,
+}
+
+impl FragmentCache {
+    /// Opens the cache directory, creating it if needed. Sources are only
+    /// taken from earlier runs that counted lines for the same denominator
+    /// and kernel configuration and checked the same exclusion presets.
+    pub(crate) fn open(dir: &str, denominator: Denominator, config: Option<&KernelConfig>, exclusions: &[ExclusionPreset]) -> io::Result<FragmentCache> {
+        fs::create_dir_all(dir)?;
+        let exclusions = exclusions.iter().map(|preset| preset.name()).collect::<Vec<&str>>().join(",");
+        let config = config.map_or("-".to_string(), |config| {
+            let mut hasher = Fnv::default();
+            config.hash(&mut hasher);
+            format!("{:016x}", hasher.finish())
+        });
+        let counting = format!("{} {} {}", denominator.method(), config, exclusions);
+        let known_sources = fs::read_to_string(Path::new(dir).join(SOURCES_FILE))
+            .map(|index| parse_source_index(&index, &counting))
+            .unwrap_or_default();
+
+        Ok(FragmentCache {
+            dir: PathBuf::from(dir),
+            used: Mutex::new(HashSet::new()),
+            hits: AtomicUsize::new(0),
+            known_sources,
+            sources: Mutex::new(HashMap::new()),
+            counting,
+        })
+    }
+
+    /// What is known about a source that has not changed since an earlier
+    /// run, without reading it
+    pub(crate) fn source(&self, full_path: &str, modified: Option<SystemTime>) -> Option<SourceInfo> {
+        let (known_modified, info) = self.known_sources.get(full_path)?;
+        (Some(*known_modified) == modified).then(|| {
+            lock(&self.sources).insert(full_path.to_string(), (*known_modified, info.clone()));
+            info.clone()
+        })
+    }
+
+    /// Remembers a source read by this report; sources without a
+    /// modification time cannot be revalidated and are not remembered
+    pub(crate) fn add_source(&self, full_path: &str, modified: Option<SystemTime>, info: SourceInfo) {
+        if let Some(modified) = modified {
+            lock(&self.sources).insert(full_path.to_string(), (modified, info));
+        }
+    }
+
+    /// Returns what was rendered for the same `key` in an earlier report,
+    /// restoring the `outputs` written with it
+    pub(crate) fn reuse(&self, key: u64, outputs: &[String]) -> Option<String> {
+        let name = format!("{:016x}", key);
+        let text = fs::read_to_string(self.dir.join(&name)).ok()?;
+        outputs.iter().enumerate()
+            .try_for_each(|(index, output)| link_or_copy(&self.dir.join(format!("{}.{}", name, index)), Path::new(output)))
+            .ok()?;
+
+        lock(&self.used).insert(name);
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(text)
+    }
+
+    /// Returns what `render` returned for the same `key` in an earlier
+    /// report, restoring the `outputs` it wrote, or renders the fragment and
+    /// caches it
+    pub(crate) fn render(&self, key: u64, outputs: &[String], render: impl FnOnce() -> io::Result<String>) -> io::Result<String> {
+        if let Some(text) = self.reuse(key, outputs) {
+            return Ok(text);
+        }
+
+        // A restored output may still be linked to the cache; never write through it
+        for output in outputs {
+            let _ = fs::remove_file(output);
+        }
+        let text = render()?;
+
+        // The report does not depend on the cache, so failing to fill it
+        // only costs the next run a render
+        let name = format!("{:016x}", key);
+        let stored = outputs.iter().enumerate()
+            .try_for_each(|(index, output)| link_or_copy(Path::new(output), &self.dir.join(format!("{}.{}", name, index))))
+            .and_then(|()| {
+                let partial = self.dir.join(format!("{}.tmp", name));
+                fs::write(&partial, &text)?;
+                fs::rename(&partial, self.dir.join(&name))
+            });
+        match stored {
+            Ok(()) => {
+                lock(&self.used).insert(name);
+            }
+            Err(e) => debug!("Could not cache {}: {}", outputs.first().map_or("fragment", String::as_str), e),
+        }
+
+        Ok(text)
+    }
+
+    /// Saves the sources seen, removes the fragments this report did not
+    /// use and returns the number of files that were not rendered again
+    pub(crate) fn finish(self) -> io::Result<usize> {
+        write_source_index(&self.dir, &self.counting, self.sources.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()))?;
+
+        let used = self.used.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let key = name.split('.').next().unwrap_or("");
+            if (key != SOURCES_FILE && !used.contains(key)) || name.ends_with(".tmp") {
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(self.hits.into_inner())
+    }
+
+    /// Saves the sources seen along with those known from earlier runs,
+    /// leaving the fragments alone, for runs that only count lines
+    pub(crate) fn finish_sources(self) -> io::Result<()> {
+        let mut sources = self.known_sources;
+        sources.extend(self.sources.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        write_source_index(&self.dir, &self.counting, sources)
+    }
+}
+
+/// Writes the sources file read back by `parse_source_index`
+fn write_source_index(dir: &Path, counting: &str, sources: SourceIndex) -> io::Result<()> {
+    let mut index = format!("cov2html-sources 4 {}\n", counting);
+    for (full_path, (modified, info)) in sources {
+        let since_epoch = modified.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        index.push_str(&format!(
+            "{:016x} {} {} {} {} {} {} {}\n",
+            info.hash, info.lines, format_ranges(&info.non_code), format_ranges(&info.uncounted), info.excluded.map_or("-", |preset| preset.name()),
+            since_epoch.as_secs(), since_epoch.subsec_nanos(), full_path
+        ));
+    }
+    let partial = dir.join(format!("{}.tmp", SOURCES_FILE));
+    fs::write(&partial, index)?;
+    fs::rename(&partial, dir.join(SOURCES_FILE))
+}
+
+/// Reads the sources file of an earlier run; it is ignored if it is not
+/// understood or was written for another denominator, kernel configuration
+/// or other exclusion presets
+fn parse_source_index(index: &str, counting: &str) -> SourceIndex {
+    let mut lines = index.lines();
+    if lines.next() != Some(format!("cov2html-sources 4 {}", counting).as_str()) {
+        return SourceIndex::new();
+    }
+
+    lines.filter_map(|line| {
+        let mut fields = line.splitn(8, ' ');
+        let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let lines = fields.next()?.parse().ok()?;
+        let non_code = parse_ranges(fields.next()?)?;
+        let uncounted = parse_ranges(fields.next()?)?;
+        let excluded = match fields.next()? {
+            "-" => None,
+            name => Some(name.parse().ok()?),
+        };
+        let secs = fields.next()?.parse().ok()?;
+        let nanos = fields.next()?.parse().ok()?;
+        let modified = SystemTime::UNIX_EPOCH + Duration::new(secs, nanos);
+        Some((fields.next()?.to_string(), (modified, SourceInfo { hash, lines, non_code, uncounted, excluded })))
+    }).collect()
+}
+
+/// Writes lines as comma-separated `start-end` ranges, or `-` for none
+fn format_ranges(lines: &LineSet) -> String {
+    if lines.is_empty() {
+        return "-".to_string();
+    }
+    lines.ranges()
+        .map(|range| match range.start() == range.end() {
+            true => range.start().to_string(),
+            false => format!("{}-{}", range.start(), range.end()),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Reads lines written by `format_ranges`
+fn parse_ranges(field: &str) -> Option<LineSet> {
+    Some(field.split(',')
+        .filter(|range| *range != "-")
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            Some(start.parse::<u32>().ok()?..=end.parse::<u32>().ok()?)
+        })
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Hard-links `from` to `to`, replacing `to`, or copies it where links are
+/// not supported
+fn link_or_copy(from: &Path, to: &Path) -> io::Result<()> {
+    let _ = fs::remove_file(to);
+    fs::hard_link(from, to).or_else(|_| fs::copy(from, to).map(|_| ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory under the system's temporary one, unique to a test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cov2html-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn ranges_round_trip() {
+        for lines in [LineSet::new(), [7].into_iter().collect(), [1, 2, 3, 5, 9, 10, u32::MAX].into_iter().collect()] {
+            assert_eq!(parse_ranges(&format_ranges(&lines)), Some(lines));
+        }
+        assert_eq!(format_ranges(&[1, 2, 3, 5].into_iter().collect()), "1-3,5");
+        assert_eq!(parse_ranges("1-x"), None);
+    }
+
+    #[test]
+    fn source_index_round_trips() {
+        let dir = scratch_dir("source-index");
+        let modified = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let info = SourceInfo::new("int x;\n\n// COV_EXCL_LINE\n", Language::C, Denominator::Executable, None, &[]);
+        let sources = SourceIndex::from([("/src/with space.c".to_string(), (modified, info.clone()))]);
+        write_source_index(&dir, "lexical - ", sources).unwrap();
+
+        let index = fs::read_to_string(dir.join(SOURCES_FILE)).unwrap();
+        let parsed = parse_source_index(&index, "lexical - ");
+        let (parsed_modified, parsed_info) = &parsed["/src/with space.c"];
+        assert_eq!(*parsed_modified, modified);
+        assert_eq!((parsed_info.hash, parsed_info.lines), (info.hash, info.lines));
+        assert_eq!(parsed_info.non_code, info.non_code);
+        assert_eq!(parsed_info.uncounted, info.uncounted);
+        assert_eq!(parsed_info.excluded, info.excluded);
+        // Sources counted another way are not reused
+        assert!(parse_source_index(&index, "all - ").is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Renders `key` into `output` through the cache, counting the renders
+    fn render(cache: &FragmentCache, key: u64, output: &Path, renders: &AtomicUsize) -> String {
+        let outputs = [output.to_string_lossy().into_owned()];
+        cache.render(key, &outputs, || {
+            renders.fetch_add(1, Ordering::Relaxed);
+            fs::write(output, format!("page {}", key))?;
+            Ok(format!("entry {}", key))
+        }).unwrap()
+    }
+
+    #[test]
+    fn unchanged_fragments_are_reused() {
+        let dir = scratch_dir("reuse");
+        let cache_dir = dir.join("cache").to_string_lossy().into_owned();
+        let output = dir.join("page.html");
+        let renders = AtomicUsize::new(0);
+
+        let cache = FragmentCache::open(&cache_dir, Denominator::Executable, None, &[]).unwrap();
+        assert_eq!(render(&cache, 1, &output, &renders), "entry 1");
+        assert_eq!(cache.finish().unwrap(), 0);
+
+        // The next run gets the text and the page back without rendering
+        fs::remove_file(&output).unwrap();
+        let cache = FragmentCache::open(&cache_dir, Denominator::Executable, None, &[]).unwrap();
+        assert_eq!(render(&cache, 1, &output, &renders), "entry 1");
+        assert_eq!(fs::read_to_string(&output).unwrap(), "page 1");
+        assert_eq!(renders.load(Ordering::Relaxed), 1);
+        // Any other key, as a changed source, coverage, baseline or line
+        // table gives, renders again
+        assert_eq!(render(&cache, 2, &output, &renders), "entry 2");
+        assert_eq!(renders.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.finish().unwrap(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn finish_removes_only_unused_fragments() {
+        let dir = scratch_dir("finish");
+        let cache_dir = dir.join("cache");
+        let renders = AtomicUsize::new(0);
+
+        let cache = FragmentCache::open(&cache_dir.to_string_lossy(), Denominator::Executable, None, &[]).unwrap();
+        render(&cache, 1, &dir.join("a.html"), &renders);
+        render(&cache, 2, &dir.join("b.html"), &renders);
+        cache.finish().unwrap();
+
+        let cache = FragmentCache::open(&cache_dir.to_string_lossy(), Denominator::Executable, None, &[]).unwrap();
+        render(&cache, 1, &dir.join("a.html"), &renders);
+        fs::write(cache_dir.join("0000000000000003.tmp"), "partial").unwrap();
+        cache.finish().unwrap();
+
+        let mut left: Vec<String> = fs::read_dir(&cache_dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["0000000000000001", "0000000000000001.0", SOURCES_FILE]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sources_are_known_until_modified() {
+        let dir = scratch_dir("sources");
+        let cache_dir = dir.to_string_lossy().into_owned();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let info = SourceInfo::new("int x;\n", Language::C, Denominator::Executable, None, &[]);
+
+        let cache = FragmentCache::open(&cache_dir, Denominator::Executable, None, &[]).unwrap();
+        cache.add_source("/src/a.c", Some(modified), info.clone());
+        cache.finish_sources().unwrap();
+
+        let cache = FragmentCache::open(&cache_dir, Denominator::Executable, None, &[]).unwrap();
+        assert_eq!(cache.source("/src/a.c", Some(modified)).map(|source| source.hash), Some(info.hash));
+        assert!(cache.source("/src/a.c", Some(modified + Duration::from_secs(1))).is_none());
+        assert!(cache.source("/src/a.c", None).is_none());
+        // Another denominator counts lines differently, so nothing is known
+        let cache = FragmentCache::open(&cache_dir, Denominator::All, None, &[]).unwrap();
+        assert!(cache.source("/src/a.c", Some(modified)).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+//! Combining coverage runs: merging, intersecting and subtracting them,
+//! diffing two of them, and writing the results back to disk.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::coverage::{json_string, parallel_map, remap_coverage_counts, OutputFormat, PathMapping};
+use crate::error::Cov2HtmlError;
+use crate::lines::LineSet;
+use crate::parser::{parse_coverage_counts, CoverageCounts};
+
+/// Merges several sets of hit counts, summing the counts of lines covered by more than one input
+pub fn merge_coverage_counts(coverage_counts: &[CoverageCounts]) -> CoverageCounts {
+    let mut merged: CoverageCounts = HashMap::new();
+    
+    for counts in coverage_counts {
+        for (path, lines) in counts {
+            let merged_lines = merged.entry(path.clone()).or_default();
+            for (line, count) in lines {
+                *merged_lines.entry(*line).or_insert(0) += count;
+            }
+        }
+    }
+    
+    merged
+}
+
+/// Number of inputs each worker merges on its own before the partial
+/// results are merged pairwise
+const MERGE_CHUNK: usize = 16;
+
+/// Merges several sets of hit counts like `merge_coverage_counts`, on up to
+/// `jobs` worker threads (one per logical CPU when `jobs` is 0). Chunks of
+/// the inputs are merged first; the partial results are then merged in
+/// pairs, a level of the tree at a time, each into the larger of the two.
+pub fn merge_coverage_counts_parallel(coverage_counts: &[CoverageCounts], jobs: usize) -> CoverageCounts {
+    let chunks: Vec<&[CoverageCounts]> = coverage_counts.chunks(MERGE_CHUNK).collect();
+    let mut level = parallel_map(&chunks, jobs, |chunk| merge_coverage_counts(chunk));
+    
+    while level.len() > 1 {
+        let mut merged = level.into_iter();
+        let mut pairs = Vec::new();
+        while let Some(first) = merged.next() {
+            pairs.push(std::sync::Mutex::new((first, merged.next().unwrap_or_default())));
+        }
+        level = parallel_map(&pairs, jobs, |pair| {
+            let (first, second) = std::mem::take(&mut *pair.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+            merge_count_maps(first, second)
+        });
+    }
+    
+    level.pop().unwrap_or_default()
+}
+
+/// Merges two sets of hit counts, moving the smaller into the larger
+fn merge_count_maps(mut merged: CoverageCounts, mut other: CoverageCounts) -> CoverageCounts {
+    if merged.len() < other.len() {
+        std::mem::swap(&mut merged, &mut other);
+    }
+    for (path, lines) in other {
+        match merged.get_mut(&path) {
+            Some(merged_lines) => {
+                for (line, count) in lines {
+                    *merged_lines.entry(line).or_insert(0) += count;
+                }
+            }
+            None => {
+                merged.insert(path, lines);
+            }
+        }
+    }
+    
+    merged
+}
+
+/// Reads the hit counts of several coverage files on up to `jobs` worker
+/// threads and applies the path mappings, keeping the inputs in order
+pub fn read_coverage_counts(inputs: &[String], strict: bool, mappings: &[PathMapping], jobs: usize) -> Result<Vec<CoverageCounts>, Cov2HtmlError> {
+    parallel_map(inputs, jobs, |input| {
+        parse_coverage_counts(input, strict)
+            .map(|counts| remap_coverage_counts(counts, mappings))
+            .map_err(|source| Cov2HtmlError::ReadCoverage { path: input.clone(), source })
+    }).into_iter().collect()
+}
+
+/// Counts, for each input, the covered lines that no other input covers
+pub fn unique_line_contributions(coverage_counts: &[CoverageCounts]) -> Vec<usize> {
+    // Number of inputs covering each line
+    let mut covering: HashMap<(&str, u32), usize> = HashMap::new();
+    for counts in coverage_counts {
+        for (path, lines) in counts {
+            for line in lines.keys() {
+                *covering.entry((path.as_str(), *line)).or_insert(0) += 1;
+            }
+        }
+    }
+    
+    coverage_counts
+        .iter()
+        .map(|counts| {
+            counts
+                .iter()
+                .flat_map(|(path, lines)| lines.keys().map(move |line| (path.as_str(), *line)))
+                .filter(|key| covering[key] == 1)
+                .count()
+        })
+        .collect()
+}
+
+/// Merges several coverage maps into one containing every covered line
+pub fn merge_coverage(coverage_maps: &[HashMap<String, LineSet>]) -> HashMap<String, LineSet> {
+    let mut merged: HashMap<String, LineSet> = HashMap::new();
+    
+    for coverage_map in coverage_maps {
+        for (path, lines) in coverage_map {
+            merged.entry(path.clone()).or_default().union_with(lines);
+        }
+    }
+    
+    merged
+}
+
+/// Keeps only the lines covered by every one of the given coverage maps
+pub fn intersect_coverage(coverage_maps: &[HashMap<String, LineSet>]) -> HashMap<String, LineSet> {
+    let Some((first, rest)) = coverage_maps.split_first() else {
+        return HashMap::new();
+    };
+    
+    let mut result = HashMap::new();
+    for (path, lines) in first {
+        let mut common = lines.clone();
+        for other in rest {
+            match other.get(path) {
+                Some(other_lines) => common = common.intersection(other_lines),
+                None => common.clear(),
+            }
+        }
+        
+        if !common.is_empty() {
+            result.insert(path.clone(), common);
+        }
+    }
+    
+    result
+}
+
+/// Removes every line covered by any of `others` from `base`
+pub fn subtract_coverage(base: &HashMap<String, LineSet>, others: &[HashMap<String, LineSet>]) -> HashMap<String, LineSet> {
+    let mut result = HashMap::new();
+    for (path, lines) in base {
+        let mut remaining = lines.clone();
+        for other in others {
+            if let Some(other_lines) = other.get(path) {
+                remaining = remaining.difference(other_lines);
+            }
+        }
+        
+        if !remaining.is_empty() {
+            result.insert(path.clone(), remaining);
+        }
+    }
+    
+    result
+}
+
+/// Writes coverage data to a file in the given format, sorted by path and line
+pub fn write_coverage_file(coverage_map: &HashMap<String, LineSet>, file_path: &str, format: OutputFormat) -> io::Result<()> {
+    let counts: CoverageCounts = coverage_map
+        .iter()
+        .map(|(path, lines)| (path.clone(), lines.iter().map(|line| (line, 1)).collect()))
+        .collect();
+    
+    write_coverage_counts(&counts, file_path, format, false)
+}
+
+/// Writes hit counts to a file in the given format, sorted by path and line.
+/// With `include_counts`, text records become `path:line:count` and JSON maps
+/// each line to its count; LCOV always carries the counts.
+pub fn write_coverage_counts(coverage_counts: &CoverageCounts, file_path: &str, format: OutputFormat, include_counts: bool) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(file_path)?);
+    
+    let mut paths: Vec<&String> = coverage_counts.keys().collect();
+    paths.sort();
+    
+    if format == OutputFormat::Json {
+        file.write_all(b"{\n")?;
+    }
+    
+    for (i, path) in paths.iter().enumerate() {
+        let mut lines: Vec<(u32, u64)> = coverage_counts[*path].iter().map(|(line, count)| (*line, *count)).collect();
+        lines.sort_unstable();
+        
+        match format {
+            OutputFormat::Text => {
+                for (line, count) in lines {
+                    if include_counts {
+                        writeln!(file, "{}:{}:{}", path, line, count)?;
+                    } else {
+                        writeln!(file, "{}:{}", path, line)?;
+                    }
+                }
+            }
+            OutputFormat::Lcov => {
+                writeln!(file, "SF:{}", path)?;
+                for (line, count) in &lines {
+                    writeln!(file, "DA:{},{}", line, count)?;
+                }
+                writeln!(file, "LF:{}", lines.len())?;
+                writeln!(file, "LH:{}", lines.iter().filter(|(_, count)| *count > 0).count())?;
+                writeln!(file, "end_of_record")?;
+            }
+            OutputFormat::Json => {
+                let lines_json = if include_counts {
+                    let entries = lines.iter()
+                        .map(|(line, count)| format!("\"{}\": {}", line, count))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    format!("{{{}}}", entries)
+                } else {
+                    let entries = lines.iter()
+                        .map(|(line, _)| line.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    format!("[{}]", entries)
+                };
+                writeln!(
+                    file,
+                    "  {}: {}{}",
+                    json_string(path), lines_json, if i + 1 < paths.len() { "," } else { "" }
+                )?;
+            }
+        }
+    }
+    
+    if format == OutputFormat::Json {
+        file.write_all(b"}\n")?;
+    }
+    
+    file.flush()
+}
+
+/// Lines gained and lost in one file between two coverage runs
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    /// File path as recorded in the coverage data
+    pub path: String,
+    /// Lines covered by the new run but not by the old one, sorted
+    pub gained: Vec<u32>,
+    /// Lines covered by the old run but not by the new one, sorted
+    pub lost: Vec<u32>,
+}
+
+/// Differences between two coverage runs, sorted by path; files without
+/// changes are left out
+#[derive(Debug, Clone, Default)]
+pub struct CoverageDiff {
+    pub files: Vec<FileDiff>,
+}
+
+impl CoverageDiff {
+    /// Total number of lines gained
+    pub fn gained(&self) -> usize {
+        self.files.iter().map(|file| file.gained.len()).sum()
+    }
+    
+    /// Total number of lines lost
+    pub fn lost(&self) -> usize {
+        self.files.iter().map(|file| file.lost.len()).sum()
+    }
+}
+
+/// Computes the lines gained and lost between an old and a new coverage run
+pub fn diff_coverage(old: &HashMap<String, LineSet>, new: &HashMap<String, LineSet>) -> CoverageDiff {
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    
+    let empty = LineSet::new();
+    let mut diff = CoverageDiff::default();
+    
+    for path in paths {
+        let old_lines = old.get(path).unwrap_or(&empty);
+        let new_lines = new.get(path).unwrap_or(&empty);
+        
+        let gained: Vec<u32> = new_lines.difference(old_lines).iter().collect();
+        let lost: Vec<u32> = old_lines.difference(new_lines).iter().collect();
+        if gained.is_empty() && lost.is_empty() {
+            continue;
+        }
+        
+        diff.files.push(FileDiff { path: path.clone(), gained, lost });
+    }
+    
+    diff
+}
+
+/// Writes a coverage diff as `+path:line` / `-path:line` records or as JSON.
+/// LCOV has no way to tell gained lines from lost ones, so it is rejected.
+pub fn write_coverage_diff(diff: &CoverageDiff, out: &mut dyn Write, format: OutputFormat) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            out.write_all(b"{\n")?;
+            for (i, file) in diff.files.iter().enumerate() {
+                let join = |lines: &[u32]| lines.iter().map(|line| line.to_string()).collect::<Vec<String>>().join(", ");
+                writeln!(
+                    out,
+                    "  {}: {{\"gained\": [{}], \"lost\": [{}]}}{}",
+                    json_string(&file.path), join(&file.gained), join(&file.lost),
+                    if i + 1 < diff.files.len() { "," } else { "" }
+                )?;
+            }
+            out.write_all(b"}\n")?;
+        }
+        OutputFormat::Lcov => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a coverage diff cannot be written as LCOV; use text or json"));
+        }
+        OutputFormat::Text => {
+            for file in &diff.files {
+                for line in &file.gained {
+                    writeln!(out, "+{}:{}", file.path, line)?;
+                }
+                for line in &file.lost {
+                    writeln!(out, "-{}:{}", file.path, line)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lcov_records_count_their_lines() {
+        let path = std::env::temp_dir().join(format!("cov2html-test-{}-lcov-counts.info", std::process::id()));
+        let coverage_counts = CoverageCounts::from([("src/a.c".to_string(), HashMap::from([(3, 2), (1, 0)]))]);
+
+        write_coverage_counts(&coverage_counts, &path.to_string_lossy(), OutputFormat::Lcov, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "SF:src/a.c\nDA:1,0\nDA:3,2\nLF:2\nLH:1\nend_of_record\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn diffs_are_not_written_as_lcov() {
+        let old = HashMap::from([("a.c".to_string(), [1, 2].into_iter().collect())]);
+        let new = HashMap::from([("a.c".to_string(), [2, 3].into_iter().collect())]);
+        let diff = diff_coverage(&old, &new);
+
+        let mut out = Vec::new();
+        write_coverage_diff(&diff, &mut out, OutputFormat::Text).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "+a.c:3\n-a.c:1\n");
+        let error = write_coverage_diff(&diff, &mut Vec::new(), OutputFormat::Lcov).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
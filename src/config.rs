@@ -0,0 +1,134 @@
+use std::fs;
+use std::io;
+
+/// Settings read from a cov2html configuration file.
+///
+/// The file uses a small TOML-like syntax: `[section]` headers followed by
+/// `key = value` lines, with `#` starting a comment. Keys may be quoted.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Minimum coverage percentage per file glob, from the `[file_thresholds]`
+    /// section; the first matching glob wins
+    pub file_thresholds: Vec<(String, f64)>,
+    /// Coverage goal percentage per directory, from the `[goals]` section
+    pub goals: Vec<(String, f64)>,
+}
+
+impl Config {
+    /// Reads and parses a configuration file
+    pub fn load(path: &str) -> io::Result<Config> {
+        let text = fs::read_to_string(path)?;
+        Config::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+    }
+
+    /// Parses configuration text, reporting the first error with its line number
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut section = String::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("line {}: expected 'key = value'", line_number))?;
+            let key = unquote(key.trim());
+            let value = value.trim();
+
+            match section.as_str() {
+                "file_thresholds" => {
+                    let percent = parse_percent(value)
+                        .map_err(|e| format!("line {}: {}", line_number, e))?;
+                    config.file_thresholds.push((key.to_string(), percent));
+                }
+                "goals" => {
+                    let percent = parse_percent(value)
+                        .map_err(|e| format!("line {}: {}", line_number, e))?;
+                    config.goals.push((key.trim_end_matches('/').to_string(), percent));
+                }
+                "" => return Err(format!("line {}: unknown key '{}'", line_number, key)),
+                _ => return Err(format!("line {}: unknown section '{}'", line_number, section)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the minimum coverage required for a file: the first matching
+    /// glob override, or `default` when no glob matches
+    pub fn file_threshold(&self, path: &str, default: Option<f64>) -> Option<f64> {
+        self.file_thresholds.iter()
+            .find(|(pattern, _)| glob_match(pattern, path))
+            .map(|(_, percent)| *percent)
+            .or(default)
+    }
+}
+
+/// Matches a path against a glob where `*` matches within one path
+/// component, `**` matches across components and `?` matches one character
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_match_from(&pattern, &path)
+}
+
+fn glob_match_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            // `**/` may also match no directories at all
+            let rest = &pattern[2..];
+            if rest.first() == Some(&'/') && glob_match_from(&rest[1..], path) {
+                return true;
+            }
+            (0..=path.len()).any(|skip| glob_match_from(rest, &path[skip..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            let component_len = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=component_len).any(|skip| glob_match_from(rest, &path[skip..]))
+        }
+        Some('?') => {
+            matches!(path.first(), Some(c) if *c != '/') && glob_match_from(&pattern[1..], &path[1..])
+        }
+        Some(c) => path.first() == Some(c) && glob_match_from(&pattern[1..], &path[1..]),
+    }
+}
+
+/// Removes a `#` comment that is not inside a quoted string
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+/// Removes surrounding double quotes from a key or value
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')).unwrap_or(s)
+}
+
+/// Parses a coverage percentage between 0 and 100
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent = unquote(value).parse::<f64>()
+        .map_err(|_| format!("invalid percentage '{}'", value))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("percentage {} is not between 0 and 100", percent));
+    }
+
+    Ok(percent)
+}
@@ -1,717 +1,1591 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{self, BufRead, Write};
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::Instant;
 
-/// Generate a report from a coverage file
-pub fn generate_report_from_file(coverage_file: &str, kernel_src_dir: &str, work_dir: &str) -> io::Result<String> {
-    // Create the work directory if it doesn't exist
-    if !Path::new(work_dir).exists() {
-        fs::create_dir_all(work_dir)?;
+use log::{error, info, warn};
+
+#[cfg(feature = "html")]
+use crate::cache::{FragmentCache, SourceInfo};
+use crate::checksum::sha256_hex;
+use crate::config::glob_match;
+use crate::error::{Cov2HtmlError, Warning};
+use crate::executable::{line_counts, non_code_lines, non_instrumented_lines, uncounted_lines, Denominator, KernelConfig, LineTables};
+use crate::lines::LineSet;
+use crate::parser::{normalize_separators, Checksums, InputFormat};
+use crate::progress::{Progress, ProgressReporter};
+use crate::renderer::{RenderContext, ReportRenderer, DEFAULT_RENDERER};
+use crate::source::SourceTree;
+
+pub use crate::parser::{
+    parse_coverage_counts, parse_coverage_counts_with_warnings, parse_coverage_file, parse_coverage_file_with_warnings, CoverageCounts,
+};
+pub use crate::combine::{
+    diff_coverage, intersect_coverage, merge_coverage, merge_coverage_counts, merge_coverage_counts_parallel, read_coverage_counts, subtract_coverage,
+    unique_line_contributions, write_coverage_counts, write_coverage_diff, write_coverage_file, CoverageDiff, FileDiff,
+};
+pub use crate::validate::{validate_coverage_file, CoverageProblem, CoverageValidation};
+#[cfg(feature = "html")]
+pub use crate::html::{generate_combined_html, write_diff_html, write_html_report, write_viewer_html};
+
+/// Source language of a file, detected from its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Language {
+    C,
+    Header,
+    Rust,
+    Assembly,
+    Makefile,
+    DeviceTree,
+    Other,
+}
+
+impl Language {
+    /// Detects the language of a file from its extension or well-known name
+    pub fn detect(path: &str) -> Language {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        
+        if name == "Makefile" || name == "Kbuild" || name.starts_with("Makefile.") {
+            return Language::Makefile;
+        }
+        
+        match name.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("c") => Language::C,
+            Some("h") => Language::Header,
+            Some("rs") => Language::Rust,
+            Some("S") | Some("s") | Some("asm") => Language::Assembly,
+            Some("mk") => Language::Makefile,
+            Some("dts") | Some("dtsi") => Language::DeviceTree,
+            _ => Language::Other,
+        }
     }
     
-    // Parse the coverage file
-    let coverage_map = parse_coverage_file(coverage_file)?;
-    println!("Parsed coverage data for {} files", coverage_map.len());
-    
-    // Generate the HTML report
-    generate_combined_html(&coverage_map, kernel_src_dir, work_dir);
-    let html_path = format!("{}/coverage_report.html", work_dir);
-    println!("Generated combined HTML coverage report at {}", html_path);
+    /// Identifier used for the language in the generated HTML and JavaScript
+    pub fn id(&self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Header => "header",
+            Language::Rust => "rust",
+            Language::Assembly => "asm",
+            Language::Makefile => "makefile",
+            Language::DeviceTree => "dts",
+            Language::Other => "other",
+        }
+    }
     
-    Ok(html_path)
+    /// Human-readable name of the language
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::C => "C",
+            Language::Header => "C headers",
+            Language::Rust => "Rust",
+            Language::Assembly => "Assembly",
+            Language::Makefile => "Makefile",
+            Language::DeviceTree => "Devicetree",
+            Language::Other => "Other",
+        }
+    }
 }
 
-/// Parse the coverage file into a map of file paths to covered line numbers
-pub fn parse_coverage_file(file_path: &str) -> io::Result<HashMap<String, HashSet<u32>>> {
-    let file = File::open(file_path)?;
-    let reader = io::BufReader::new(file);
-    let mut coverage_map = HashMap::new();
-    
-    for line in reader.lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            continue;
+/// Coverage percentages at which a file is shown as good or medium
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverageThresholds {
+    /// Minimum percentage for the "good" class
+    pub good: f64,
+    /// Minimum percentage for the "medium" class; anything lower is "bad"
+    pub medium: f64,
+}
+
+impl Default for CoverageThresholds {
+    fn default() -> Self {
+        CoverageThresholds {
+            good: 80.0,
+            medium: 50.0,
         }
+    }
+}
+
+impl FromStr for CoverageThresholds {
+    type Err = String;
+    
+    /// Parses thresholds written as `GOOD,MEDIUM`, e.g. `90,70`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (good, medium) = s.split_once(',')
+            .ok_or_else(|| format!("expected GOOD,MEDIUM but got '{}'", s))?;
+        let good = good.trim().parse::<f64>()
+            .map_err(|_| format!("invalid good threshold '{}'", good))?;
+        let medium = medium.trim().parse::<f64>()
+            .map_err(|_| format!("invalid medium threshold '{}'", medium))?;
         
-        // Split the line into path and line number
-        let parts: Vec<&str> = line.split(':').collect();
-        if parts.len() != 2 {
-            eprintln!("Warning: Invalid format in line: {}", line);
-            continue;
+        if !(0.0..=100.0).contains(&good) || !(0.0..=100.0).contains(&medium) {
+            return Err("thresholds must be between 0 and 100".to_string());
+        }
+        if medium > good {
+            return Err(format!("medium threshold {} is above good threshold {}", medium, good));
         }
         
-        let full_path = parts[0];
-        let line_number = match parts[1].trim().parse::<u32>() {
-            Ok(num) => num,
-            Err(_) => {
-                eprintln!("Warning: Invalid line number: {}", parts[1]);
-                continue;
-            }
-        };
-        
-        // Extract the relative path from the full path
-        let rel_path = full_path.to_string();
+        Ok(CoverageThresholds { good, medium })
+    }
+}
+
+/// Rewrites coverage paths starting with `from` to start with `to` instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathMapping {
+    /// Path prefix as recorded in the coverage data
+    pub from: String,
+    /// Replacement prefix relative to the source tree
+    pub to: String,
+}
+
+impl FromStr for PathMapping {
+    type Err = String;
+    
+    /// Parses a mapping written as `OLD=NEW`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s.split_once('=')
+            .ok_or_else(|| format!("expected OLD=NEW but got '{}'", s))?;
+        if from.is_empty() {
+            return Err("the OLD prefix of a path mapping must not be empty".to_string());
+        }
         
-        // Add to the coverage map
-        coverage_map
-            .entry(rel_path)
-            .or_insert_with(HashSet::new)
-            .insert(line_number);
+        // Recorded paths are normalized to `/`, so prefixes written with `\` still match
+        Ok(PathMapping { from: normalize_separators(from).into_owned(), to: normalize_separators(to).into_owned() })
+    }
+}
+
+/// Applies the first mapping whose prefix matches the path, if any
+pub fn remap_path(path: &str, mappings: &[PathMapping]) -> String {
+    for mapping in mappings {
+        if let Some(rest) = path.strip_prefix(&mapping.from) {
+            return format!("{}{}", mapping.to, rest);
+        }
     }
     
-    Ok(coverage_map)
+    path.to_string()
 }
 
-/// Generates a single combined HTML coverage report from coverage data
-pub fn generate_combined_html(coverage_map: &HashMap<String, HashSet<u32>>, kernel_src_dir: &str, work_dir: &str) {
-    // Create a file tree structure
-    let mut file_tree: HashMap<String, (usize, usize)> = HashMap::new(); // (covered_lines, total_lines)
-    let mut total_covered = 0;
-    let mut total_lines = 0;
+/// Remaps every path in the coverage data, merging files that end up with the same path
+pub fn remap_coverage(coverage_map: HashMap<String, LineSet>, mappings: &[PathMapping]) -> HashMap<String, LineSet> {
+    if mappings.is_empty() {
+        return coverage_map;
+    }
     
-    // Store file content and coverage data
-    let mut file_data = Vec::new();
+    let mut result: HashMap<String, LineSet> = HashMap::new();
+    for (path, lines) in coverage_map {
+        result.entry(remap_path(&path, mappings)).or_default().union_with(&lines);
+    }
     
-    // Process each file in the coverage map
-    for (file_path, covered_lines) in coverage_map {
-        let full_path = format!("{}/{}", kernel_src_dir, file_path);
-        
-        // Skip files that don't exist
-        if !Path::new(&full_path).exists() {
-            eprintln!("Warning: Source file not found: {}", full_path);
-            continue;
+    result
+}
+
+/// Finds the build directories absolute coverage paths were recorded
+/// below, like gcov's prefix stripping but without being told the prefix:
+/// each absolute path the source tree does not know has leading components
+/// dropped until the rest names a source, and the prefixes found that way
+/// become mappings to the tree's root, the most common first
+pub fn detect_build_prefixes(paths: &[&str], sources: &dyn SourceTree, jobs: usize) -> Vec<PathMapping> {
+    let prefixes = parallel_map(paths, jobs, |path| {
+        let relative = path.strip_prefix('/').or_else(|| {
+            let drive = path.get(..3)?;
+            (drive.as_bytes()[0].is_ascii_alphabetic() && drive.ends_with(":/")).then(|| &path[3..])
+        })?;
+        if sources.find(path).is_some() {
+            return None;
         }
         
-        // Read the source file
-        let source_content = match fs::read_to_string(&full_path) {
-            Ok(content) => content,
-            Err(e) => {
-                eprintln!("Failed to read source file {}: {}", full_path, e);
-                continue;
+        let mut rest = relative;
+        while let Some((_, tail)) = rest.split_once('/') {
+            rest = tail;
+            if sources.find(rest).is_some() {
+                return Some(&path[..path.len() - rest.len()]);
             }
-        };
-        
-        // Count total lines in the file
-        let file_total_lines = source_content.lines().count();
-        let file_covered_lines = covered_lines.len();
-        
-        // Update global stats
-        total_covered += file_covered_lines;
-        total_lines += file_total_lines;
-        
-        println!("Processing file: {} ({} of {} lines covered)", 
-            file_path, file_covered_lines, file_total_lines);
-        
-        // Build file tree entries
-        build_file_tree_entries(file_path, file_covered_lines, file_total_lines, &mut file_tree);
-        
-        // Process line coverage
-        let coverage_pct = if file_total_lines > 0 { 
-            (file_covered_lines as f64 / file_total_lines as f64) * 100.0 
-        } else { 
-            0.0 
-        };
-        
-        // Store file data for later use in the HTML generation
-        file_data.push((
-            file_path.to_string(),
-            source_content,
-            covered_lines.clone(),
-            file_covered_lines,
-            file_total_lines,
-            coverage_pct
-        ));
-    }
+        }
+        None
+    });
     
-    // Create the combined HTML file
-    let combined_html_path = format!("{}/coverage_report.html", work_dir);
-    let mut html_file = File::create(&combined_html_path).expect("Failed to create combined HTML file");
+    let mut votes: HashMap<&str, usize> = HashMap::new();
+    for prefix in prefixes.into_iter().flatten() {
+        *votes.entry(prefix).or_insert(0) += 1;
+    }
+    let mut votes: Vec<(&str, usize)> = votes.into_iter().collect();
+    votes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
     
-    // Write HTML head with CSS and JavaScript
-    write_combined_html_head(&mut html_file)
-        .expect("Failed to write HTML head");
+    votes.into_iter()
+        .map(|(prefix, files)| {
+            info!("Detected build prefix {} ({} files)", prefix, files);
+            PathMapping { from: prefix.to_string(), to: String::new() }
+        })
+        .collect()
+}
+
+/// Strips the build prefixes `detect_build_prefixes` finds from the paths
+/// of the coverage data, merging files that end up with the same path
+pub fn remap_build_prefixes(coverage_map: HashMap<String, LineSet>, sources: &dyn SourceTree, jobs: usize) -> HashMap<String, LineSet> {
+    let paths: Vec<&str> = coverage_map.keys().map(String::as_str).collect();
+    let mappings = detect_build_prefixes(&paths, sources, jobs);
+    remap_coverage(coverage_map, &mappings)
+}
+
+/// Returns the path relative to the subtree root, or `None` for paths outside it
+pub fn focus_path(path: &str, root: &str) -> Option<String> {
+    if root.is_empty() {
+        return Some(path.to_string());
+    }
     
-    // Write body opening
-    html_file.write_all(b"<body>\n").expect("Failed to write to HTML file");
+    path.strip_prefix(root)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .map(|rest| rest.to_string())
+}
+
+/// Keeps only the files below the subtree root, with paths relative to it
+pub fn focus_coverage(coverage_map: HashMap<String, LineSet>, root: &str) -> HashMap<String, LineSet> {
+    coverage_map.into_iter()
+        .filter_map(|(path, lines)| focus_path(&path, root).map(|path| (path, lines)))
+        .collect()
+}
+
+/// Drops the empty and `.` components of a path and folds `dir/..` away,
+/// without looking at the file system
+pub fn clean_path(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
     
-    // Sidebar with file tree
-    html_file.write_all(b"<div id=\"sidebar\" class=\"sidebar\">\n")
-        .expect("Failed to write to HTML file");
+    let cleaned = components.join("/");
+    if path.starts_with('/') { format!("/{}", cleaned) } else { cleaned }
+}
+
+/// Finds the paths that name the same source: paths that only differ in
+/// `./`, `//` or `dir/..` components, and paths that the source tree
+/// resolves to the same real file, such as a header reached through
+/// several include directories or a symlink. With `ignore_case`, paths that
+/// only match a source when letter case is ignored take its spelling.
+/// Returns the new name of every path that needs one: the real path below
+/// the source root for files with several paths, where the tree can tell.
+pub fn alias_names(paths: &[&str], sources: &dyn SourceTree, ignore_case: bool, jobs: usize) -> HashMap<String, String> {
+    // How each path is spelled in the tree, its real path and its name
+    // relative to the real root
+    let resolved = parallel_map(paths, jobs, |path| {
+        let mut spelled = clean_path(path);
+        let mut found = sources.find(&spelled);
+        if found.is_none() && ignore_case && let Some(respelled) = sources.find_ignore_case(&spelled) {
+            found = sources.find(&respelled);
+            spelled = respelled;
+        }
+        let real = found.and_then(|(root, full_path)| {
+            let real_path = sources.canonical_path(&full_path)?;
+            let name = sources.canonical_path(&root)
+                .and_then(|real_root| real_path.strip_prefix(&real_root)?.strip_prefix('/').map(|name| name.to_string()));
+            Some((real_path, name))
+        });
+        (spelled, real)
+    });
     
-    let overall_coverage = if total_lines > 0 { (total_covered as f64 / total_lines as f64) * 100.0 } else { 0.0 };
+    let mut groups: HashMap<String, Vec<(&str, String)>> = HashMap::new();
+    let mut names: HashMap<String, String> = HashMap::new();
+    for (path, (spelled, real)) in paths.iter().zip(resolved) {
+        let (key, name) = match real {
+            Some((real_path, name)) => (real_path, name),
+            None => (spelled.clone(), None),
+        };
+        if let Some(name) = name {
+            names.entry(key.clone()).or_insert(name);
+        }
+        groups.entry(key).or_default().push((path, spelled));
+    }
     
-    html_file.write_all(format!(
-        "<div class=\"coverage-header\">\n<h2>Coverage Report</h2>\n<div class=\"coverage-summary\">Overall: <span class=\"{}\">{:.1}%</span> ({} of {} lines)</div>\n</div>\n",
-        get_coverage_class(overall_coverage),
-        overall_coverage,
-        total_covered,
-        total_lines
-    ).as_bytes()).expect("Failed to write to HTML file");
+    let mut renames = HashMap::new();
+    for (key, members) in groups {
+        let name = match (members.len(), names.remove(&key)) {
+            (1, _) | (_, None) => members.iter().map(|(_, spelled)| spelled.clone()).min().unwrap_or_default(),
+            (_, Some(name)) => name,
+        };
+        for (path, _) in members {
+            if path != name {
+                renames.insert(path.to_string(), name.clone());
+            }
+        }
+    }
     
-    // Organize files into a proper tree structure
-    let mut tree: HashMap<String, Vec<(String, usize, usize)>> = HashMap::new();
-    build_directory_tree(&file_tree, &mut tree);
+    renames
+}
+
+/// Merges the files whose paths name the same source, as found by
+/// `alias_names`, under a single name. Returns `None` when every path
+/// already names a distinct file as spelled.
+pub fn merge_aliased_files(coverage_map: &HashMap<String, LineSet>, sources: &dyn SourceTree, ignore_case: bool, jobs: usize) -> Option<HashMap<String, LineSet>> {
+    let paths: Vec<&str> = coverage_map.keys().map(String::as_str).collect();
+    let renames = alias_names(&paths, sources, ignore_case, jobs);
+    if renames.is_empty() {
+        return None;
+    }
     
-    // Recursively render the tree
-    render_combined_tree(&tree, "", &mut html_file, 0);
+    let mut merged: HashMap<String, LineSet> = HashMap::new();
+    for (path, lines) in coverage_map {
+        let name = renames.get(path).unwrap_or(path);
+        merged.entry(name.clone()).or_default().union_with(lines);
+    }
+    if merged.len() < coverage_map.len() {
+        info!("Merged {} coverage entries naming the same source file as another", coverage_map.len() - merged.len());
+    }
     
-    html_file.write_all(b"</div>\n")
-        .expect("Failed to write to HTML file");
+    Some(merged)
+}
+
+/// Like `merge_aliased_files` for hit counts, summing the counts of the
+/// paths merged, e.g. of a header's copies inlined into several
+/// translation units
+pub fn merge_aliased_counts(coverage_counts: CoverageCounts, sources: &dyn SourceTree, ignore_case: bool, jobs: usize) -> CoverageCounts {
+    let paths: Vec<&str> = coverage_counts.keys().map(String::as_str).collect();
+    let renames = alias_names(&paths, sources, ignore_case, jobs);
+    if renames.is_empty() {
+        return coverage_counts;
+    }
     
-    // Content area for displaying file content
-    html_file.write_all(
-        b"<div id=\"content\" class=\"content\">\n<div id=\"welcome\" class=\"welcome\">\n<h1>Coverage Report</h1>\n<p>Select a file from the sidebar to view coverage details.</p>\n<p>Generated with FFFuzzer coverage tool</p>\n</div>\n"
-    ).expect("Failed to write to HTML file");
+    let files = coverage_counts.len();
+    let mut merged: CoverageCounts = HashMap::new();
+    for (path, lines) in coverage_counts {
+        let name = renames.get(&path).cloned().unwrap_or(path);
+        let merged_lines = merged.entry(name).or_default();
+        for (line, count) in lines {
+            *merged_lines.entry(line).or_insert(0) += count;
+        }
+    }
+    if merged.len() < files {
+        info!("Merged {} coverage entries naming the same source file as another", files - merged.len());
+    }
     
-    // Create containers for each file's content (initially hidden)
-    for (file_path, _, _, _, _, _) in &file_data {
-        let file_id = file_path.replace("/", "_").replace(".", "_");
-        html_file.write_all(format!(
-            "<div id=\"file_{}\" class=\"file-content\" style=\"display:none;\"></div>\n",
-            file_id
-        ).as_bytes()).expect("Failed to write to HTML file");
+    merged
+}
+
+/// Source roots of a subtree: each root joined with the subtree directory
+pub fn subtree_roots(source_roots: &[String], root: &str) -> Vec<String> {
+    source_roots.iter()
+        .map(|source_root| Path::new(source_root).join(root).to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Remaps every path in the hit counts, summing the counts of files that end up with the same path
+pub fn remap_coverage_counts(coverage_counts: CoverageCounts, mappings: &[PathMapping]) -> CoverageCounts {
+    if mappings.is_empty() {
+        return coverage_counts;
     }
     
-    html_file.write_all(b"</div>\n")
-        .expect("Failed to write to HTML file");
+    let mut result: CoverageCounts = HashMap::new();
+    for (path, lines) in coverage_counts {
+        let merged = result.entry(remap_path(&path, mappings)).or_default();
+        for (line, count) in lines {
+            *merged.entry(line).or_insert(0) += count;
+        }
+    }
     
-    // Write JavaScript code for functions and data
-    html_file.write_all(b"<script>\n").expect("Failed to write to HTML file");
+    result
+}
+
+/// Options controlling how the HTML report is rendered
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    /// Soft-wrap long source lines by default
+    pub wrap_lines: bool,
+    /// Number of columns a tab character occupies by default
+    pub tab_width: usize,
+    /// Write each file's source and coverage to a separate JSON chunk
+    /// next to the report and load it on demand
+    pub split_data: bool,
+    /// Emit CSS, JavaScript and report data as external files and add a
+    /// strict Content-Security-Policy, so no inline code is needed
+    pub external_assets: bool,
+    /// Pre-render every file into a static HTML page linked from a static
+    /// index, so the report works without JavaScript
+    pub no_js: bool,
+    /// Register a service worker (`sw.js`) that keeps the pages and data
+    /// of the report cached for browsing offline, as `serve` does; reports
+    /// split over several files always get one
+    pub service_worker: bool,
+    /// Minify the bundled CSS and JavaScript and drop the line breaks
+    /// between tags in the generated HTML
+    pub minify: bool,
+    /// Split the interactive report into a page per top-level directory and
+    /// an index once the data of a single page would exceed this many bytes
+    pub shard_size: Option<u64>,
+    /// Largest self-contained report, estimated from the size of the
+    /// sources; larger reports are written with `split_data` and
+    /// `external_assets` instead, with a warning
+    pub max_report_size: Option<u64>,
+    /// Rough bound on the memory used for files in flight: batches of
+    /// files are cut so their sources fit in a quarter of it, and file
+    /// data kept in memory spills to a temporary file past another quarter.
+    /// The coverage data itself is not counted
+    pub max_memory: Option<u64>,
+    /// Cutoffs used to color coverage percentages
+    pub thresholds: CoverageThresholds,
+    /// Link covered lines to an external code browser instead of embedding
+    /// the sources; `{rev}`, `{path}` and `{line}` are substituted
+    pub source_url_template: Option<String>,
+    /// Revision substituted for `{rev}` in the source URL template
+    pub source_rev: String,
+    /// Render tabs and trailing whitespace visibly
+    pub show_whitespace: bool,
+    /// Elide the rest of lines longer than this many characters behind an
+    /// expand control (interactive views only)
+    pub max_line_length: Option<usize>,
+    /// Prefix rewrites applied to coverage paths before looking up sources
+    pub path_mappings: Vec<PathMapping>,
+    /// Strip the build directory absolute coverage paths were recorded
+    /// below, detected from which paths name sources without it
+    pub detect_build_prefix: bool,
+    /// Match coverage paths to sources without regard to letter case, for
+    /// coverage recorded on a case-insensitive file system
+    pub ignore_case_paths: bool,
+    /// `(directory, percentage)` coverage goals shown with their pass/fail status
+    pub goals: Vec<(String, f64)>,
+    /// Coverage file of an earlier run; lines gained and lost against it are highlighted
+    pub baseline: Option<String>,
+    /// When the coverage data was recorded; files whose source changed
+    /// after it are marked as possibly stale. `ReportBuilder` takes it from
+    /// the coverage file when not set
+    pub coverage_modified: Option<SystemTime>,
+    /// SHA-256 of the sources the coverage was recorded against, by path as
+    /// in the coverage data. Sources that differ are marked as possibly
+    /// stale, or fail the report when `strict`. `ReportBuilder` takes them
+    /// from the checksum records of the coverage file.
+    pub checksums: Checksums,
+    /// Subscribe to live coverage updates pushed by `cov2html serve --live`
+    pub live: bool,
+    /// Worker threads used to read sources and render pages; 0 uses one
+    /// per logical CPU
+    pub jobs: usize,
+    /// Fail on malformed coverage lines and missing or unreadable sources
+    /// instead of skipping them
+    pub strict: bool,
+    /// Globs of source files to include with zero coverage when the
+    /// coverage data does not mention them
+    pub include_uncovered: Vec<String>,
+    /// Built-in presets of files to leave out of the report
+    pub exclusions: Vec<ExclusionPreset>,
+    /// Which lines of each file its coverage is taken over
+    pub denominator: Denominator,
+    /// Lines with instructions by path as in the coverage data, read from
+    /// the DWARF of the binary the coverage came from with
+    /// `read_line_tables`. With the `dwarf` denominator, files listed are
+    /// counted over these lines.
+    pub line_tables: LineTables,
+    /// Kernel `.config` deciding which `#ifdef CONFIG_X` blocks are compiled;
+    /// without it only `#if 0` blocks are known not to be
+    pub kernel_config: Option<KernelConfig>,
+    /// Title shown in the page title and headings
+    pub title: String,
+    /// Format of the coverage file and the baseline
+    pub input_format: InputFormat,
+    /// Directory rendered HTML files are cached in between reports; files
+    /// whose source, covered lines and options did not change are reused
+    /// rather than rendered again
+    pub cache_dir: Option<String>,
+    /// Directory with `report.html`, `report.css` or `report.js` templates
+    /// replacing the built-in ones
+    pub template_dir: Option<String>,
+    /// Output formats the report is written in
+    pub renderers: Vec<&'static dyn ReportRenderer>,
+    /// Receives the progress of report generation
+    pub progress: ProgressReporter,
+    /// Set from another thread to stop report generation with
+    /// `Cov2HtmlError::Cancelled`
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Subdirectory the report is rooted at. Coverage data passed to
+    /// `generate_report` must already be focused on it with `focus_coverage`;
+    /// sources are looked up below it.
+    pub root: Option<String>,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            wrap_lines: false,
+            tab_width: 8,
+            split_data: false,
+            external_assets: false,
+            no_js: false,
+            service_worker: false,
+            minify: false,
+            shard_size: Some(DEFAULT_SHARD_SIZE),
+            max_report_size: None,
+            max_memory: None,
+            thresholds: CoverageThresholds::default(),
+            source_url_template: None,
+            source_rev: "latest".to_string(),
+            show_whitespace: false,
+            max_line_length: None,
+            path_mappings: Vec::new(),
+            detect_build_prefix: true,
+            ignore_case_paths: false,
+            goals: Vec::new(),
+            baseline: None,
+            coverage_modified: None,
+            checksums: Checksums::new(),
+            live: false,
+            jobs: 0,
+            strict: false,
+            include_uncovered: Vec::new(),
+            root: None,
+            exclusions: Vec::new(),
+            denominator: Denominator::default(),
+            line_tables: LineTables::new(),
+            kernel_config: None,
+            title: "Coverage Report".to_string(),
+            input_format: InputFormat::Auto,
+            cache_dir: None,
+            template_dir: None,
+            renderers: vec![DEFAULT_RENDERER],
+            progress: ProgressReporter::default(),
+            cancel: None,
+        }
+    }
+}
+
+impl ReportOptions {
+    /// Whether the cancel flag is set
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Fails with `Cov2HtmlError::Cancelled` once the cancel flag is set
+    pub(crate) fn check_cancelled(&self) -> Result<(), Cov2HtmlError> {
+        if self.is_cancelled() {
+            return Err(Cov2HtmlError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Directory in the report directory the report is rendered into before it
+/// replaces the files of the previous report
+const STAGING_DIR: &str = ".cov2html-partial";
+
+/// Directory in the report directory `report --incremental` caches rendered
+/// files in
+pub const CACHE_DIR: &str = ".cov2html-cache";
+
+/// Size of the file data above which an interactive report is split into a
+/// page per top-level directory, as larger pages make browsers struggle
+pub const DEFAULT_SHARD_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Parses a size in bytes with an optional `K`, `M` or `G` suffix (powers of
+/// 1024), e.g. `200M`, for use as a clap value parser
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let trimmed = size.trim();
+    let (number, shift) = match trimmed.as_bytes().last() {
+        Some(b'k' | b'K') => (&trimmed[..trimmed.len() - 1], 10),
+        Some(b'm' | b'M') => (&trimmed[..trimmed.len() - 1], 20),
+        Some(b'g' | b'G') => (&trimmed[..trimmed.len() - 1], 30),
+        _ => (trimmed, 0),
+    };
+    number.parse::<u64>().ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size '{}' (expected bytes with an optional K, M or G suffix)", size))
+}
+
+/// Finds the first source root containing the given file and returns the
+/// root together with the full path of the file
+pub fn resolve_source<'a>(source_roots: &'a [String], file_path: &str) -> Option<(&'a str, String)> {
+    source_roots.iter()
+        .map(|root| (root.as_str(), format!("{}/{}", root, file_path)))
+        .find(|(_, full_path)| Path::new(full_path).is_file())
+}
+
+/// Looks a file up under each root in turn, matching every component of
+/// its path without regard to letter case (an exact match wins), and
+/// returns its path relative to the root as spelled on disk
+pub fn resolve_source_ignore_case(source_roots: &[String], file_path: &str) -> Option<String> {
+    source_roots.iter().find_map(|root| {
+        let mut dir = PathBuf::from(root);
+        let mut components = Vec::new();
+        for component in file_path.split('/').filter(|component| !component.is_empty()) {
+            let name = if dir.join(component).exists() {
+                component.to_string()
+            } else {
+                let lowercase = component.to_lowercase();
+                fs::read_dir(&dir).ok()?
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .filter(|name| name.to_lowercase() == lowercase)
+                    .min()?
+            };
+            dir.push(&name);
+            components.push(name);
+        }
+        dir.is_file().then(|| components.join("/"))
+    })
+}
+
+/// Generate a report in the formats of `options.renderers` from already
+/// parsed (and remapped) coverage data and return the coverage totals it
+/// shows; `ReportBuilder` wraps this for reports generated from a coverage file
+pub fn generate_report(coverage_map: &HashMap<String, LineSet>, sources: &dyn SourceTree, work_dir: &str, options: &ReportOptions) -> Result<CoverageSummary, Cov2HtmlError> {
+    // Create the work directory if it doesn't exist
+    if !Path::new(work_dir).exists() {
+        fs::create_dir_all(work_dir).map_err(|source| Cov2HtmlError::WriteReport { path: work_dir.to_string(), source })?;
+    }
     
-    // File data objects
-    html_file.write_all(b"const fileData = {\n").expect("Failed to write to HTML file");
+    // Render into a staging directory that replaces the files of the
+    // previous report only once every renderer succeeded, so a failed or
+    // cancelled run leaves no half-written files behind
+    let staging_dir = Path::new(work_dir).join(STAGING_DIR);
+    let staging = staging_dir.to_string_lossy().into_owned();
+    let _ = fs::remove_dir_all(&staging_dir);
+    fs::create_dir_all(&staging_dir).map_err(|source| Cov2HtmlError::WriteReport { path: staging.clone(), source })?;
+    let published_path = |path: &str| match path.strip_prefix(staging.as_str()) {
+        Some(rest) => format!("{}{}", work_dir, rest),
+        None => path.to_string(),
+    };
     
-    for (file_path, source_content, covered_lines, covered_count, total_lines, coverage_pct) in &file_data {
-        let file_id = file_path.replace("/", "_").replace(".", "_");
-        
-        // Convert the covered lines to a JSON array
-        let covered_lines_json = covered_lines.iter()
-            .map(|line| line.to_string())
-            .collect::<Vec<String>>()
-            .join(", ");
-        
-        // Prepare the source content lines for JSON
-        // Don't html_escape here since we'll use innerHTML to render it properly
-        let source_lines: Vec<String> = source_content.lines()
-            .map(|line| line.replace("\\", "\\\\").replace("\"", "\\\""))
-            .collect();
-        
-        let source_json = source_lines.iter()
-            .map(|line| format!("\"{}\"", line))
-            .collect::<Vec<String>>()
-            .join(",\n        ");
+    // Render the report in every requested format; the totals of the first one are returned
+    let mut formats = Vec::new();
+    let rendered = render_report(coverage_map, sources, options, |context| {
+        let mut summary: Option<CoverageSummary> = None;
+        for renderer in &options.renderers {
+            options.check_cancelled()?;
+            let rendered = renderer.render(context, &staging)?;
+            options.check_cancelled()?;
+            formats.extend(rendered.outputs.iter().map(|_| renderer.name()));
+            for path in &rendered.outputs {
+                if let Ok(metadata) = fs::metadata(path) {
+                    options.progress.report(Progress::BytesWritten { path: published_path(path), bytes: metadata.len() });
+                }
+            }
+            match &mut summary {
+                Some(summary) => {
+                    summary.outputs.extend(rendered.outputs);
+                    summary.phases.extend(rendered.phases);
+                }
+                None => summary = Some(rendered),
+            }
+        }
         
-        html_file.write_all(format!(
-            "  \"{}\": {{\n    path: \"{}\",\n    covered: [{}],\n    totalLines: {},\n    coveredCount: {},\n    coveragePct: {:.1},\n    source: [\n        {}\n    ]\n  }},\n",
-            file_id, file_path, covered_lines_json, total_lines, covered_count, coverage_pct, source_json
-        ).as_bytes()).expect("Failed to write to HTML file");
-    }
-    
-    html_file.write_all(b"};\n\n").expect("Failed to write to HTML file");
-    
-    // Write JavaScript functions
-    html_file.write_all(r#"
-// Function to safely display source code
-function displaySourceSafely(text) {
-  // First encode all HTML entities to prevent XSS attacks
-  const encodedText = text
-    .replace(/&/g, '&amp;')
-    .replace(/</g, '&lt;')
-    .replace(/>/g, '&gt;')
-    .replace(/"/g, '&quot;')
-    .replace(/'/g, '&#39;');
-    
-  // Replace encoded preprocessor directives to display them nicely
-  // This handles #include<xxx> and #include <xxx> formats
-  return encodedText
-    .replace(/(#\s*include\s*)&lt;([^&]+)&gt;/g, '$1<span class="include-brackets">&lt;</span>$2<span class="include-brackets">&gt;</span>')
-    .replace(/(#\s*define\s*[^&\s]+\s*)&lt;([^&]+)&gt;/g, '$1<span class="include-brackets">&lt;</span>$2<span class="include-brackets">&gt;</span>');
-}
-
-// Function to show a specific file
-function showFile(fileId) {
-  // Hide welcome message and all file content
-  document.getElementById('welcome').style.display = 'none';
-  const fileContainers = document.querySelectorAll('.file-content');
-  fileContainers.forEach(container => {
-    container.style.display = 'none';
-  });
-  
-  // Get the file container
-  const fileContainer = document.getElementById('file_' + fileId);
-  if (!fileContainer) return;
-  
-  // If the file hasn't been loaded yet, generate the content
-  if (fileContainer.innerHTML === '') {
-    const data = fileData[fileId];
-    if (!data) return;
-    
-    // Create file header
-    const header = document.createElement('div');
-    header.className = 'file-header';
-    header.innerHTML = `
-      <h2>${data.path}</h2>
-      <div class=\"coverage-summary\">Coverage: <span class=\"${getCoverageClass(data.coveragePct)}\">${data.coveragePct.toFixed(1)}%</span> (${data.coveredCount} of ${data.totalLines} lines)</div>
-    `;
-    fileContainer.appendChild(header);
-    
-    // Create source code container
-    const pre = document.createElement('pre');
-    pre.className = 'source-code';
-    
-    // Add each line
-    for (let i = 0; i < data.source.length; i++) {
-      const lineNum = i + 1;
-      const isCovered = data.covered.includes(lineNum);
-      const lineDiv = document.createElement('div');
-      lineDiv.className = 'line' + (isCovered ? ' covered' : '');
-      
-      const lineNumSpan = document.createElement('span');
-      lineNumSpan.className = 'line-number';
-      lineNumSpan.textContent = lineNum;
-      
-      const lineContentSpan = document.createElement('span');
-      lineContentSpan.className = 'line-content';
-      // Use our custom function to safely display source code with proper formatting
-      lineContentSpan.innerHTML = displaySourceSafely(data.source[i]);
-      
-      lineDiv.appendChild(lineNumSpan);
-      lineDiv.appendChild(lineContentSpan);
-      pre.appendChild(lineDiv);
-    }
-    
-    fileContainer.appendChild(pre);
-  }
-  
-  // Show the file container
-  fileContainer.style.display = 'block';
-  
-  // Highlight the selected file in the sidebar
-  const fileLinks = document.querySelectorAll('.file-link');
-  fileLinks.forEach(link => {
-    link.parentElement.classList.remove('active');
-    if (link.getAttribute('data-id') === fileId) {
-      link.parentElement.classList.add('active');
-      
-      // Expand parent directories
-      let parent = link.parentElement.parentElement;
-      while (parent) {
-        if (parent.classList.contains('tree-child')) {
-          parent.classList.add('expanded');
-          const toggle = parent.previousElementSibling;
-          if (toggle && toggle.classList.contains('tree-toggle')) {
-            toggle.classList.add('expanded');
-          }
-        }
-        parent = parent.parentElement;
-      }
-    }
-  });
-}
-
-// Function to get coverage class based on percentage
-function getCoverageClass(percentage) {
-  if (percentage >= 80.0) {
-    return 'coverage-good';
-  } else if (percentage >= 50.0) {
-    return 'coverage-medium';
-  } else {
-    return 'coverage-bad';
-  }
-}
-
-// Set up tree toggles
-function setupTreeToggles() {
-  const toggles = document.querySelectorAll('.tree-toggle');
-  toggles.forEach(toggle => {
-    toggle.addEventListener('click', function() {
-      this.classList.toggle('expanded');
-      const childrenContainer = this.nextElementSibling;
-      if (childrenContainer && childrenContainer.classList.contains('tree-child')) {
-        childrenContainer.classList.toggle('expanded');
-      }
+        Ok(summary.unwrap_or_else(|| summarize_coverage(context.coverage_map, context.sources, &options.exclusions, options.denominator, options.kernel_config.as_ref(), &options.line_tables, options.jobs)))
     });
-  });
-}
-
-// Initialize when the page loads
-window.onload = function() {
-  setupTreeToggles();
-};
-"#.as_bytes()).expect("Failed to write to HTML file");
-    
-    html_file.write_all(b"</script>\n").expect("Failed to write to HTML file");
     
-    // Close the HTML
-    html_file.write_all(b"</body>\n</html>\n").expect("Failed to write to HTML file");
+    let mut summary = match rendered {
+        Ok(summary) => summary,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging_dir);
+            return Err(e);
+        }
+    };
+    publish_staged(&staging_dir, work_dir).map_err(|source| Cov2HtmlError::WriteReport { path: work_dir.to_string(), source })?;
+    for (output, format) in summary.outputs.iter_mut().zip(formats) {
+        *output = published_path(output);
+        info!("Generated {} coverage report at {}", format, output);
+    }
     
-    println!("Coverage summary: {} of {} lines covered ({:.2}%)", 
-        total_covered, total_lines, 
-        if total_lines > 0 { (total_covered as f64 / total_lines as f64) * 100.0 } else { 0.0 });
+    Ok(summary)
 }
 
-/// Builds file tree entries for a given file path
-fn build_file_tree_entries(file_path: &str, covered_lines: usize, total_lines: usize, file_tree: &mut HashMap<String, (usize, usize)>) {
-    let components: Vec<&str> = file_path.split('/').collect();
-    let mut current_path = String::new();
+/// Moves the files of the staging directory into the report directory,
+/// replacing those of the previous report, and removes it
+fn publish_staged(staging_dir: &Path, work_dir: &str) -> io::Result<()> {
+    let mut published = HashSet::new();
+    for entry in fs::read_dir(staging_dir)? {
+        let entry = entry?;
+        let target = Path::new(work_dir).join(entry.file_name());
+        if target.is_dir() {
+            fs::remove_dir_all(&target)?;
+        }
+        fs::rename(entry.path(), &target)?;
+        published.insert(entry.file_name());
+    }
+    fs::remove_dir(staging_dir)?;
     
-    for (i, component) in components.iter().enumerate() {
-        if i > 0 {
-            current_path.push('/');
+    // Assets named after their contents are not replaced by the new ones
+    for entry in fs::read_dir(work_dir)? {
+        let entry = entry?;
+        if !published.contains(&entry.file_name()) && is_hashed_asset(&entry.file_name().to_string_lossy()) {
+            fs::remove_file(entry.path())?;
         }
-        current_path.push_str(component);
-        
-        if i == components.len() - 1 {
-            // This is the file
-            file_tree.insert(current_path.clone(), (covered_lines, total_lines));
-        } else {
-            // This is a directory - initialize if not exists
-            file_tree.entry(current_path.clone()).or_insert((0, 0));
+    }
+    
+    Ok(())
+}
+
+/// Whether a file name is that of a report asset named after a hash of its
+/// contents, `report<...>.<16 hex digits>.css` or `.js`
+fn is_hashed_asset(name: &str) -> bool {
+    let mut parts = name.rsplitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("css" | "js"), Some(hash), Some(stem)) => {
+            stem.starts_with("report") && hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit())
         }
+        _ => false,
     }
 }
 
-/// Builds a directory tree structure from file entries
-fn build_directory_tree(file_tree: &HashMap<String, (usize, usize)>, tree: &mut HashMap<String, Vec<(String, usize, usize)>>) {
-    // First pass: identify all directories
-    for (path, (covered, total)) in file_tree {
-        let components: Vec<&str> = path.split('/').collect();
-        
-        // Add all parent directories to the tree
-        let mut parent_path = String::new();
-        for (i, component) in components.iter().enumerate() {
-            if i > 0 {
-                parent_path.push('/');
+/// Prepares the coverage data of a report (subtree roots, baseline and
+/// uncovered files) and renders it with `render`
+pub(crate) fn render_report(
+    coverage_map: &HashMap<String, LineSet>,
+    sources: &dyn SourceTree,
+    options: &ReportOptions,
+    render: impl FnOnce(&RenderContext) -> Result<CoverageSummary, Cov2HtmlError>
+) -> Result<CoverageSummary, Cov2HtmlError> {
+    // Sources of a subtree report are looked up below the subtree
+    let all_sources = sources;
+    let focused_sources;
+    let sources = match &options.root {
+        Some(root) => {
+            focused_sources = sources.subtree(root);
+            focused_sources.as_ref()
+        }
+        None => sources,
+    };
+    
+    // Add the never covered files matching --include-uncovered
+    let start = PhaseStart::now();
+    let with_uncovered;
+    let coverage_map = if options.include_uncovered.is_empty() {
+        coverage_map
+    } else {
+        with_uncovered = include_uncovered_files(coverage_map, sources, &options.include_uncovered);
+        info!("Included {} uncovered files", with_uncovered.len() - coverage_map.len());
+        &with_uncovered
+    };
+    let uncovered_phase = (!options.include_uncovered.is_empty()).then(|| PhaseStats::finish("scan sources", start));
+    
+    // Checksums and line tables follow their files to the names aliases
+    // are merged under
+    let renamed_options;
+    let options = if options.checksums.is_empty() && options.line_tables.is_empty() {
+        options
+    } else {
+        let paths: Vec<&str> = coverage_map.keys().map(String::as_str).collect();
+        let renames = alias_names(&paths, sources, options.ignore_case_paths, options.jobs);
+        let checksums = options.checksums.iter()
+            .map(|(path, sum)| (renames.get(path).unwrap_or(path).clone(), sum.clone()))
+            .collect();
+        let mut line_tables = LineTables::new();
+        for (path, lines) in &options.line_tables {
+            let merged: &mut LineSet = line_tables.entry(renames.get(path).unwrap_or(path).clone()).or_default();
+            *merged = merged.union(lines);
+        }
+        renamed_options = ReportOptions { checksums, line_tables, ..options.clone() };
+        &renamed_options
+    };
+    
+    // Count symlinked and differently spelled paths of a source only once
+    let unaliased;
+    let coverage_map = match merge_aliased_files(coverage_map, sources, options.ignore_case_paths, options.jobs) {
+        Some(merged) => {
+            unaliased = merged;
+            &unaliased
+        }
+        None => coverage_map,
+    };
+    
+    // Compare against the baseline run, if any
+    let start = PhaseStart::now();
+    let mut baseline_warnings = Vec::new();
+    let baseline = match &options.baseline {
+        Some(baseline_file) => {
+            let baseline_map = parse_coverage_file_with_warnings(baseline_file, options.input_format, options.strict, &mut baseline_warnings)
+                .map_err(|source| Cov2HtmlError::ReadCoverage { path: baseline_file.clone(), source })?;
+            let mut baseline_map = remap_coverage(baseline_map, &options.path_mappings);
+            if options.detect_build_prefix {
+                baseline_map = remap_build_prefixes(baseline_map, all_sources, options.jobs);
             }
-            parent_path.push_str(component);
-            
-            // Create entry for parent directories if they don't exist
-            if i < components.len() - 1 {
-                let parent_dir = if i == 0 { 
-                    String::new() 
-                } else { 
-                    parent_path[..parent_path.rfind('/').unwrap_or(0)].to_string() 
-                };
-                tree.entry(parent_dir).or_insert_with(Vec::new);
+            if let Some(root) = &options.root {
+                baseline_map = focus_coverage(baseline_map, root);
             }
+            if let Some(merged) = merge_aliased_files(&baseline_map, sources, options.ignore_case_paths, options.jobs) {
+                baseline_map = merged;
+            }
+            let diff = diff_coverage(&baseline_map, coverage_map);
+            info!("Compared with baseline: {} lines gained, {} lines lost", diff.gained(), diff.lost());
+            Some(diff)
         }
-        
-        // Add file to its parent directory
-        if components.len() > 1 {
-            let parent = parent_path[..parent_path.rfind('/').unwrap_or(0)].to_string();
-            tree.entry(parent)
-                .or_insert_with(Vec::new)
-                .push((path.clone(), *covered, *total));
-        } else {
-            // Root level file
-            tree.entry(String::new())
-                .or_insert_with(Vec::new)
-                .push((path.clone(), *covered, *total));
-        }
+        None => None,
+    };
+    let baseline_phase = baseline.is_some().then(|| PhaseStats::finish("baseline", start));
+    
+    options.progress.report(Progress::FilesDiscovered { files: coverage_map.len() });
+    
+    options.check_cancelled()?;
+    
+    let totals = OnceLock::new();
+    let context = RenderContext { coverage_map, sources, options, baseline: baseline.as_ref(), totals: &totals };
+    let mut summary = render(&context)?;
+    summary.phases.splice(0..0, baseline_phase.into_iter().chain(uncovered_phase));
+    summary.warnings.splice(0..0, baseline_warnings);
+    
+    Ok(summary)
+}
+
+/// Returns the coverage data plus an empty entry for every file under the
+/// source tree that matches one of the globs and is not covered at all
+pub fn include_uncovered_files(coverage_map: &HashMap<String, LineSet>, sources: &dyn SourceTree, patterns: &[String]) -> HashMap<String, LineSet> {
+    let mut result = coverage_map.clone();
+    
+    for path in sources.matching_files(patterns) {
+        result.entry(path).or_default();
     }
+    
+    result
 }
 
-/// Recursively renders the directory tree for the combined HTML
-fn render_combined_tree(
-    tree: &HashMap<String, Vec<(String, usize, usize)>>, 
-    current_path: &str, 
-    html_file: &mut File,
-    level: usize
-) {
-    if let Some(children) = tree.get(current_path) {
-        // Sort children: directories first, then files
-        let mut dirs: Vec<&str> = Vec::new();
-        let mut files: Vec<(usize, &str, usize, usize)> = Vec::new(); // (index, name, covered, total)
-        
-        for (i, (path, covered, total)) in children.iter().enumerate() {
-            if *total == 0 {
-                // This is a directory
-                let name = if current_path.is_empty() {
-                    path
-                } else {
-                    &path[current_path.len() + 1..]
-                };
-                
-                if !name.contains('/') {
-                    dirs.push(name);
-                }
-            } else {
-                // This is a file
-                let name = path.split('/').last().unwrap_or(path);
-                files.push((i, name, *covered, *total));
-            }
+/// Collects the paths, relative to the root, of the files below `dir`
+/// matching any of the globs. Hidden entries and symlinks are skipped.
+pub(crate) fn collect_matching_files(dir: &Path, relative: &str, patterns: &[String], paths: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read directory {}: {}", dir.display(), e);
+            return;
         }
-        
-        dirs.sort();
-        files.sort_by(|a, b| a.1.cmp(b.1));
-        
-        // Render directories
-        for dir in dirs {
-            let full_path = if current_path.is_empty() {
-                dir.to_string()
-            } else {
-                format!("{}/{}", current_path, dir)
-            };
-            
-            // Write directory with toggle
-            html_file.write_all(format!(
-                "<div class=\"directory\">\n<div class=\"tree-toggle{}\">{}/</div>\n",
-                if level == 0 { " expanded" } else { "" }, dir
-            ).as_bytes()).expect("Failed to write to HTML file");
-            
-            // Write container for children
-            html_file.write_all(format!(
-                "<div class=\"tree-child{}\">\n",
-                if level == 0 { " expanded" } else { "" }
-            ).as_bytes()).expect("Failed to write to HTML file");
-            
-            // Recursively render children
-            render_combined_tree(tree, &full_path, html_file, level + 1);
-            
-            html_file.write_all(b"</div>\n</div>\n")
-                .expect("Failed to write to HTML file");
+    };
+    
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
         }
         
-        // Render files
-        for (_, name, covered, total) in files {
-            let coverage_pct = if total > 0 { (covered as f64 / total as f64) * 100.0 } else { 0.0 };
-            let color_class = get_coverage_class(coverage_pct);
-            
-            let path = if current_path.is_empty() {
-                name.to_string()
-            } else {
-                format!("{}/{}", current_path, name)
-            };
-            
-            let file_id = path.replace("/", "_").replace(".", "_");
-            
-            html_file.write_all(format!(
-                "<div class=\"file-entry\"><a href=\"javascript:void(0)\" onclick=\"showFile('{}')\" class=\"file-link\" data-id=\"{}\">{} <span class=\"coverage-badge {}\">({:.1}%)</span></a></div>\n",
-                file_id, file_id, name, color_class, coverage_pct
-            ).as_bytes()).expect("Failed to write to HTML file");
+        let path = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => collect_matching_files(&entry.path(), &path, patterns, paths),
+            Ok(file_type) if file_type.is_file() && patterns.iter().any(|pattern| glob_match(pattern, &path)) => paths.push(path),
+            _ => {}
         }
     }
 }
 
-/// Writes the HTML head with CSS styles for the combined HTML
-fn write_combined_html_head(file: &mut File) -> std::io::Result<()> {
-    file.write_all(b"<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n<title>Combined Coverage Report</title>\n<style>\n")?;
-    
-    // Write CSS styles
-    file.write_all(b"
-:root {
-    --bg-color: #fff;
-    --text-color: #333;
-    --sidebar-bg: #f5f5f5;
-    --sidebar-hover: #e0e0e0;
-    --line-highlight: #90EE90;
-    --line-number-color: #888;
-    --link-color: #0066cc;
-    --border-color: #ddd;
-    --toggle-color: #555;
-    --good-color: #4caf50;
-    --medium-color: #ff9800;
-    --bad-color: #f44336;
-    --header-bg: #f0f0f0;
+/// Format used when writing coverage data back to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// `path:line` records, one per line (the input format)
+    Text,
+    /// LCOV tracefile with `SF`/`DA` records
+    Lcov,
+    /// JSON object mapping each path to its sorted covered lines
+    Json,
 }
 
-@media (prefers-color-scheme: dark) {
-    :root {
-        --bg-color: #1e1e1e;
-        --text-color: #e0e0e0;
-        --sidebar-bg: #252525;
-        --sidebar-hover: #333;
-        --line-highlight: #2d4f2d;
-        --line-number-color: #888;
-        --link-color: #4b98e0;
-        --border-color: #444;
-        --toggle-color: #aaa;
-        --good-color: #4caf50;
-        --medium-color: #ff9800;
-        --bad-color: #f44336;
-        --header-bg: #2a2a2a;
+impl FromStr for OutputFormat {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" | "txt" => Ok(OutputFormat::Text),
+            "lcov" | "info" => Ok(OutputFormat::Lcov),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown format '{}' (expected text, lcov or json)", s)),
+        }
     }
 }
 
-* {
-    box-sizing: border-box;
-    margin: 0;
-    padding: 0;
-}
+/// Paths of test sources dropped by the `no-tests` preset
+const TEST_GLOBS: &[&str] = &[
+    "**/test/**", "**/tests/**", "**/testing/**", "**/selftests/**",
+    "**/*_test.c", "**/*_tests.c", "**/*-test.c", "**/test_*.c", "**/*_kunit.c",
+];
 
-body {
-    font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
-    color: var(--text-color);
-    background: var(--bg-color);
-    display: flex;
-    height: 100vh;
-    overflow: hidden;
-    margin: 0;
+/// Paths of generated sources dropped by the `no-generated` preset
+const GENERATED_GLOBS: &[&str] = &[
+    "**/*.pb-c.c", "**/*.pb-c.h", "**/*.lex.c", "**/*.tab.c", "**/*.tab.h",
+];
+
+/// How far into a file the `no-generated` preset looks for a "DO NOT EDIT" header
+const GENERATED_HEADER_BYTES: usize = 2048;
+
+/// Built-in sets of files left out of reports and their totals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusionPreset {
+    /// Test directories and test sources such as `*_test.c`
+    NoTests,
+    /// Generated sources: protobuf-c, lex and yacc output, and files with a
+    /// "DO NOT EDIT" header
+    NoGenerated,
 }
 
-.sidebar {
-    width: 300px;
-    height: 100vh;
-    overflow: auto;
-    padding: 15px;
-    background-color: var(--sidebar-bg);
-    border-right: 1px solid var(--border-color);
-    position: relative;
+impl FromStr for ExclusionPreset {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no-tests" => Ok(ExclusionPreset::NoTests),
+            "no-generated" => Ok(ExclusionPreset::NoGenerated),
+            _ => Err(format!("unknown preset '{}' (expected no-tests or no-generated)", s)),
+        }
+    }
 }
 
-.content {
-    flex-grow: 1;
-    height: 100vh;
-    overflow: auto;
-    padding: 15px;
+impl ExclusionPreset {
+    /// Name of the preset as given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExclusionPreset::NoTests => "no-tests",
+            ExclusionPreset::NoGenerated => "no-generated",
+        }
+    }
+    
+    /// Whether the preset drops a file because of its path
+    pub fn excludes_path(&self, path: &str) -> bool {
+        let globs = match self {
+            ExclusionPreset::NoTests => TEST_GLOBS,
+            ExclusionPreset::NoGenerated => GENERATED_GLOBS,
+        };
+        globs.iter().any(|glob| glob_match(glob, path))
+    }
+    
+    /// Whether the preset drops a file because of its contents
+    pub fn excludes_source(&self, content: &[u8]) -> bool {
+        match self {
+            ExclusionPreset::NoTests => false,
+            ExclusionPreset::NoGenerated => {
+                let header = &content[..content.len().min(GENERATED_HEADER_BYTES)];
+                header.windows(11).any(|window| window.eq_ignore_ascii_case(b"DO NOT EDIT"))
+            }
+        }
+    }
 }
 
-.coverage-header, .file-header {
-    padding-bottom: 15px;
-    margin-bottom: 15px;
-    border-bottom: 1px solid var(--border-color);
+/// How the totals of a run are printed on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    /// Human-readable lines
+    Text,
+    /// A single JSON object
+    Json,
 }
 
-.coverage-summary {
-    margin-top: 8px;
-    font-size: 14px;
+impl FromStr for SummaryFormat {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" | "txt" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(format!("unknown summary format '{}' (expected text or json)", s)),
+        }
+    }
 }
 
-.coverage-good { color: var(--good-color); }
-.coverage-medium { color: var(--medium-color); }
-.coverage-bad { color: var(--bad-color); }
+/// Totals for a set of coverage data, computed without rendering a report
+#[derive(Debug, Clone, Default)]
+pub struct CoverageSummary {
+    /// Files whose source was found and counted
+    pub files: usize,
+    /// Files listed in the coverage data whose source could not be read
+    pub missing_files: usize,
+    /// Covered lines of each file whose source could not be read, so they
+    /// can be listed in the report
+    pub missing_sources: BTreeMap<String, usize>,
+    /// Covered lines in the counted files
+    pub covered_lines: usize,
+    /// Lines that hold code in the counted files, the denominator of the
+    /// coverage: blank, comment and preprocessor lines are left out unless
+    /// covered
+    pub total_lines: usize,
+    /// All lines of the counted files, the non-code ones included
+    pub source_lines: usize,
+    /// `(covered, total)` lines of each counted file
+    pub file_totals: BTreeMap<String, (usize, usize)>,
+    /// Time and memory spent in each phase, when a report was generated
+    pub phases: Vec<PhaseStats>,
+    /// Problems that did not stop the report, in the order they were found
+    pub warnings: Vec<Warning>,
+    /// Files written for the report, in the order of the renderers
+    pub outputs: Vec<String>,
+}
 
-.directory {
-    margin: 4px 0;
+/// Wall time and memory use of one phase of report generation
+#[derive(Debug, Clone)]
+pub struct PhaseStats {
+    /// Phase name, e.g. "parse" or "write HTML"
+    pub name: &'static str,
+    /// Wall time spent in the phase
+    pub elapsed: Duration,
+    /// Resident memory at the end of the phase in KiB, where the platform reports it
+    pub rss_kib: Option<u64>,
+    /// Peak resident memory of the process so far in KiB
+    pub peak_rss_kib: Option<u64>,
 }
 
-.file-entry {
-    margin: 4px 0;
-    padding-left: 3px;
+impl PhaseStats {
+    /// Records a phase that started at `start` and ends now
+    pub fn finish(name: &'static str, start: PhaseStart) -> PhaseStats {
+        let (rss_kib, peak_rss_kib) = memory_usage();
+        PhaseStats { name, elapsed: start.elapsed(), rss_kib, peak_rss_kib }
+    }
 }
 
-.file-entry.active .file-link {
-    background-color: var(--sidebar-hover);
-    font-weight: bold;
+/// When a phase started. Wall time is not measured on
+/// `wasm32-unknown-unknown`, which has no clock.
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseStart {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    instant: Instant,
 }
 
-.file-link {
-    text-decoration: none;
-    color: var(--link-color);
-    display: block;
-    padding: 4px 8px;
-    border-radius: 3px;
-    transition: background-color 0.2s;
+impl PhaseStart {
+    /// A phase starting now
+    pub fn now() -> PhaseStart {
+        PhaseStart {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            instant: Instant::now(),
+        }
+    }
+    
+    /// Wall time since the phase started
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        return self.instant.elapsed();
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        return Duration::ZERO;
+    }
 }
 
-.file-link:hover {
-    background-color: var(--sidebar-hover);
+/// Current and peak resident memory in KiB, read from /proc on Linux
+fn memory_usage() -> (Option<u64>, Option<u64>) {
+    let Ok(status) = fs::read_to_string("/proc/self/status") else {
+        return (None, None);
+    };
+    let field = |name: &str| {
+        status.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+    };
+    
+    (field("VmRSS:"), field("VmHWM:"))
 }
 
-.coverage-badge {
-    font-size: 0.85em;
-    margin-left: 5px;
+impl CoverageSummary {
+    /// Overall coverage percentage
+    pub fn coverage_pct(&self) -> f64 {
+        if self.total_lines > 0 {
+            (self.covered_lines as f64 / self.total_lines as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Run summary as a single JSON object, with the number of warnings
+    /// logged and the paths written during the run
+    pub fn to_json(&self, input: &str, warnings: usize) -> String {
+        let outputs = self.outputs.iter().map(|path| json_string(path)).collect::<Vec<String>>().join(",");
+        
+        format!(
+            "{{\"input\":{},\"files\":{},\"missing_files\":{},\"covered_lines\":{},\"total_lines\":{},\"source_lines\":{},\"coverage_pct\":{:.2},\"warnings\":{},\"outputs\":[{}]}}",
+            json_string(input), self.files, self.missing_files, self.covered_lines, self.total_lines, self.source_lines, self.coverage_pct(), warnings, outputs
+        )
+    }
 }
 
-.tree-toggle {
-    cursor: pointer;
-    user-select: none;
-    padding: 4px 8px;
-    border-radius: 3px;
-    transition: background-color 0.2s;
-    position: relative;
-    font-weight: 500;
+/// Best per-file coverage percentages achieved so far, keyed by path
+pub type Ratchet = BTreeMap<String, f64>;
+
+/// A file whose coverage dropped below its recorded ratchet value
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatchetRegression {
+    pub path: String,
+    /// Percentage recorded in the ratchet file
+    pub recorded: f64,
+    /// Percentage measured now
+    pub current: f64,
 }
 
-.tree-toggle:hover {
-    background-color: var(--sidebar-hover);
+/// Reads a ratchet file of `path:percentage` records
+pub fn read_ratchet(file_path: &str) -> io::Result<Ratchet> {
+    let mut ratchet = Ratchet::new();
+    
+    for line in fs::read_to_string(file_path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid ratchet record: {}", line));
+        let (path, percent) = line.rsplit_once(':').ok_or_else(invalid)?;
+        let percent = percent.trim().parse::<f64>().map_err(|_| invalid())?;
+        ratchet.insert(path.to_string(), percent);
+    }
+    
+    Ok(ratchet)
 }
 
-.tree-toggle::before {
-    content: '\xE2\x96\xB6';
-    display: inline-block;
-    margin-right: 5px;
-    font-size: 0.9em;
-    transition: transform 0.2s;
-    color: var(--toggle-color);
+/// Writes a ratchet file with one `path:percentage` record per line, sorted by path
+pub fn write_ratchet(ratchet: &Ratchet, file_path: &str) -> io::Result<()> {
+    let mut out = io::BufWriter::new(File::create(file_path)?);
+    for (path, percent) in ratchet {
+        writeln!(out, "{}:{:.2}", path, percent)?;
+    }
+    out.flush()
 }
 
-.tree-toggle.expanded::before {
-    transform: rotate(90deg);
+/// Rounds a percentage to the two decimals stored in ratchet files, so
+/// values read back compare equal to the ones that were written
+fn ratchet_pct(covered: usize, total: usize) -> f64 {
+    let percent = if total > 0 { (covered as f64 / total as f64) * 100.0 } else { 0.0 };
+    (percent * 100.0).round() / 100.0
 }
 
-.tree-child {
-    margin-left: 15px;
-    display: none;
-    border-left: 1px solid var(--border-color);
-    padding-left: 10px;
+/// Lists files whose coverage is below their recorded ratchet value. Files
+/// that are no longer part of the coverage data are not reported.
+pub fn ratchet_regressions(ratchet: &Ratchet, file_totals: &BTreeMap<String, (usize, usize)>) -> Vec<RatchetRegression> {
+    file_totals.iter()
+        .filter_map(|(path, (covered, total))| {
+            let recorded = *ratchet.get(path)?;
+            let current = ratchet_pct(*covered, *total);
+            (current < recorded).then(|| RatchetRegression { path: path.clone(), recorded, current })
+        })
+        .collect()
 }
 
-.tree-child.expanded {
-    display: block;
+/// Raises every recorded value to the current coverage where it improved
+/// and records files that were not in the ratchet yet
+pub fn update_ratchet(ratchet: &mut Ratchet, file_totals: &BTreeMap<String, (usize, usize)>) {
+    for (path, (covered, total)) in file_totals {
+        let current = ratchet_pct(*covered, *total);
+        let recorded = ratchet.entry(path.clone()).or_insert(current);
+        if current > *recorded {
+            *recorded = current;
+        }
+    }
 }
 
-.source-code {
-    margin: 0;
-    font-family: 'SFMono-Regular', Consolas, 'Liberation Mono', Menlo, monospace;
-    background-color: var(--bg-color);
-    line-height: 1.5;
-    overflow-x: auto;
-    tab-size: 4;
+/// Coverage of a directory measured against its configured goal
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoalResult {
+    /// Directory the goal applies to; empty for the whole tree
+    pub directory: String,
+    /// Required coverage percentage
+    pub goal: f64,
+    /// Covered lines in the directory
+    pub covered_lines: usize,
+    /// Total lines in the directory
+    pub total_lines: usize,
 }
 
-.line {
-    display: flex;
-    white-space: pre;
+impl GoalResult {
+    /// Coverage percentage of the directory
+    pub fn coverage_pct(&self) -> f64 {
+        if self.total_lines > 0 {
+            (self.covered_lines as f64 / self.total_lines as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+    
+    /// Whether the directory meets its goal
+    pub fn passed(&self) -> bool {
+        self.coverage_pct() >= self.goal
+    }
 }
 
-.line.covered {
-    background-color: var(--line-highlight);
+/// Measures each `(directory, goal)` pair against per-file `(covered, total)` line counts
+pub fn evaluate_goals(goals: &[(String, f64)], file_totals: &BTreeMap<String, (usize, usize)>) -> Vec<GoalResult> {
+    goals.iter()
+        .map(|(directory, goal)| {
+            let mut result = GoalResult {
+                directory: directory.clone(),
+                goal: *goal,
+                covered_lines: 0,
+                total_lines: 0,
+            };
+            
+            for (path, (covered, total)) in file_totals {
+                let in_directory = directory.is_empty()
+                    || path.strip_prefix(directory.as_str()).is_some_and(|rest| rest.starts_with('/'));
+                if in_directory {
+                    result.covered_lines += covered;
+                    result.total_lines += total;
+                }
+            }
+            
+            result
+        })
+        .collect()
 }
 
-.line-number {
-    color: var(--line-number-color);
-    padding: 0 12px;
-    margin-right: 12px;
-    text-align: right;
-    user-select: none;
-    border-right: 1px solid var(--border-color);
-    min-width: 40px;
+/// Computes coverage totals by counting the lines of each covered source
+/// file, on up to `jobs` worker threads (one per logical CPU when `jobs` is 0)
+pub fn summarize_coverage(
+    coverage_map: &HashMap<String, LineSet>,
+    sources: &dyn SourceTree,
+    exclusions: &[ExclusionPreset],
+    denominator: Denominator,
+    kernel_config: Option<&KernelConfig>,
+    line_tables: &LineTables,
+    jobs: usize,
+) -> CoverageSummary {
+    summarize_with(coverage_map, sources, exclusions, denominator, line_tables, jobs, &|full_path, covered_lines, instrumented| {
+        let content = sources.read(full_path)?;
+        if exclusions.iter().any(|preset| preset.excludes_source(&content)) {
+            return Ok(None);
+        }
+        let lines = count_lines(&content);
+        let uncounted = uncounted_lines(&content, Language::detect(full_path), kernel_config);
+        let non_code = match instrumented {
+            Some(instrumented) => non_instrumented_lines(lines, instrumented).union(&uncounted),
+            None => non_code_lines(&content, Language::detect(full_path), denominator, kernel_config),
+        };
+        let (covered, total) = line_counts(lines, &non_code, &uncounted, covered_lines);
+        Ok(Some((lines, covered, total)))
+    })
 }
 
-.line-content {
-    flex: 1;
+/// Checks the sources with a checksum in `options` against it, returning a
+/// warning for each that differs; in strict mode a difference fails instead
+pub(crate) fn verify_checksums(coverage_map: &HashMap<String, LineSet>, sources: &dyn SourceTree, options: &ReportOptions) -> Result<Vec<Warning>, Cov2HtmlError> {
+    let expected: Vec<(&String, &String)> = coverage_map.keys()
+        .filter_map(|path| Some((path, options.checksums.get(path)?)))
+        .collect();
+    let mismatches: Vec<Warning> = parallel_map(&expected, options.jobs, |(path, expected)| {
+        let (_, full_path) = sources.find(path)?;
+        let actual = sha256_hex(&sources.read(&full_path).ok()?);
+        (actual != **expected).then(|| Warning::ChecksumMismatch { path: path.to_string(), expected: expected.to_string(), actual })
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    
+    for warning in &mismatches {
+        if options.strict {
+            error!("{}", warning);
+        } else {
+            warn!("{}", warning);
+        }
+    }
+    if options.strict && !mismatches.is_empty() {
+        return Err(Cov2HtmlError::ChecksumMismatch { count: mismatches.len() });
+    }
+    
+    Ok(mismatches)
 }
 
-.include-brackets {
-    color: var(--text-color);
+/// Like `summarize_coverage`, but sources unchanged since an earlier run
+/// are not read: their line counts come from the source index in
+/// `cache_dir`, such as the one `report --incremental` keeps. The sources
+/// that had to be read are added to the index for the next run.
+#[cfg(feature = "html")]
+#[allow(clippy::too_many_arguments)]
+pub fn summarize_coverage_cached(
+    coverage_map: &HashMap<String, LineSet>,
+    sources: &dyn SourceTree,
+    exclusions: &[ExclusionPreset],
+    denominator: Denominator,
+    kernel_config: Option<&KernelConfig>,
+    line_tables: &LineTables,
+    jobs: usize,
+    cache_dir: &str,
+) -> io::Result<CoverageSummary> {
+    let cache = FragmentCache::open(cache_dir, denominator, kernel_config, exclusions)?;
+    let summary = summarize_with(coverage_map, sources, exclusions, denominator, line_tables, jobs, &|full_path, covered_lines, instrumented| {
+        let modified = sources.modified(full_path);
+        let source = match cache.source(full_path, modified) {
+            Some(source) => source,
+            None => {
+                let content = decode_source(sources.read(full_path)?);
+                let source = SourceInfo::new(&content, Language::detect(full_path), denominator, kernel_config, exclusions);
+                cache.add_source(full_path, modified, source.clone());
+                source
+            }
+        };
+        let (covered, total) = match instrumented {
+            Some(instrumented) => line_counts(source.lines, &non_instrumented_lines(source.lines, instrumented).union(&source.uncounted), &source.uncounted, covered_lines),
+            None => source.line_counts(covered_lines),
+        };
+        Ok(source.excluded.is_none().then_some((source.lines, covered, total)))
+    });
+    cache.finish_sources()?;
+    Ok(summary)
 }
 
-.welcome {
-    display: flex;
-    flex-direction: column;
-    align-items: center;
-    justify-content: center;
-    height: 100%;
+/// Counts a source given its full path, covered lines and the lines its line
+/// table has instructions on, if any: its number of lines, of covered lines
+/// that count and of lines with code, or `None` for files excluded by their
+/// contents
+type LineCounter<'a> = dyn Fn(&str, &LineSet, Option<&LineSet>) -> io::Result<Option<(usize, usize, usize)>> + Sync + 'a;
+
+/// Sums up the covered files, taking the lines of each source from `count`
+fn summarize_with(
+    coverage_map: &HashMap<String, LineSet>,
+    sources: &dyn SourceTree,
+    exclusions: &[ExclusionPreset],
+    denominator: Denominator,
+    line_tables: &LineTables,
+    jobs: usize,
+    count: &LineCounter,
+) -> CoverageSummary {
+    let mut summary = CoverageSummary::default();
+    
+    let unaliased;
+    let coverage_map = match merge_aliased_files(coverage_map, sources, false, jobs) {
+        Some(merged) => {
+            unaliased = merged;
+            &unaliased
+        }
+        None => coverage_map,
+    };
+    
+    let entries: Vec<(&String, &LineSet)> = coverage_map.iter()
+        .filter(|(file_path, _)| !exclusions.iter().any(|preset| preset.excludes_path(file_path)))
+        .collect();
+    
+    // Sources are counted on the worker pool; `None` marks files excluded
+    // by their contents
+    let totals = parallel_map(&entries, jobs, |(file_path, covered_lines)| {
+        let total = sources.find(file_path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+            .and_then(|(_, full_path)| count(&full_path, covered_lines, denominator.instrumented(line_tables, file_path)));
+        total.transpose()
+    });
+    
+    for ((file_path, covered_lines), total) in entries.into_iter().zip(totals) {
+        match total {
+            None => {}
+            Some(Ok((file_source_lines, file_covered_lines, file_total_lines))) => {
+                summary.files += 1;
+                summary.covered_lines += file_covered_lines;
+                summary.total_lines += file_total_lines;
+                summary.source_lines += file_source_lines;
+                summary.file_totals.insert(file_path.clone(), (file_covered_lines, file_total_lines));
+            }
+            Some(Err(_)) => {
+                summary.missing_files += 1;
+                summary.missing_sources.insert(file_path.clone(), covered_lines.len());
+            }
+        }
+    }
+    
+    summary
 }
 
-.welcome h1 {
-    margin-bottom: 20px;
+/// Number of worker threads `parallel_map` uses for `jobs`: one per logical
+/// CPU when `jobs` is 0
+pub(crate) fn worker_count(jobs: usize) -> usize {
+    // wasm32-unknown-unknown cannot spawn threads
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        1
+    } else if jobs == 0 {
+        std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        jobs
+    }
 }
-")?;
+
+/// Applies `f` to every item on up to `jobs` worker threads (one per logical
+/// CPU when `jobs` is 0) and returns the results in the order of the items
+pub(crate) fn parallel_map<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let jobs = worker_count(jobs).min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
     
-    file.write_all(b"</style>\n</head>\n")?;
-    Ok(())
+    // Workers take the next unclaimed item until none are left
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| scope.spawn(|| {
+                let mut done = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        return done;
+                    };
+                    done.push((index, f(item)));
+                }
+            }))
+            .collect();
+        
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("Worker thread panicked"))
+            .collect()
+    });
+    
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
-/// Helper function to get CSS class based on coverage percentage
-fn get_coverage_class(percentage: f64) -> &'static str {
-    if percentage >= 80.0 {
-        "coverage-good"
-    } else if percentage >= 50.0 {
-        "coverage-medium"
+/// Counts lines the same way `str::lines` does, without requiring UTF-8
+pub(crate) fn count_lines(content: &[u8]) -> usize {
+    let newlines = content.iter().filter(|b| **b == b'\n').count();
+    if content.is_empty() || content.ends_with(b"\n") {
+        newlines
     } else {
-        "coverage-bad"
+        newlines + 1
+    }
+}
+
+/// Decodes a source file as UTF-8, taking the bytes that are not part of
+/// a valid sequence as Latin-1, so files with e.g. a stray Latin-1 name in a
+/// comment are still shown, one character per byte and on the same lines
+#[cfg(feature = "html")]
+pub(crate) fn decode_source(content: Vec<u8>) -> String {
+    match String::from_utf8(content) {
+        Ok(text) => text,
+        Err(e) => {
+            let mut text = String::new();
+            for chunk in e.as_bytes().utf8_chunks() {
+                text.push_str(chunk.valid());
+                text.extend(chunk.invalid().iter().map(|&b| char::from(b)));
+            }
+            text
+        }
+    }
+}
+
+/// Identifier of a file in a report, naming its data chunk and its page
+/// without JavaScript and used in element ids: the path with every byte
+/// other than an ASCII letter, digit or `-` written as `_` and two hex
+/// digits, so that no two paths share one
+#[cfg(any(feature = "html", feature = "serve"))]
+pub(crate) fn file_id(path: &str) -> String {
+    let mut id = String::with_capacity(path.len() + 16);
+    for byte in path.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' => id.push(char::from(byte)),
+            _ => id.push_str(&format!("_{:02x}", byte)),
+        }
+    }
+    id
+}
+
+/// Encodes a string as a quoted JSON string literal
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::MemorySources;
+
+    fn summarize(source: &str, covered: &[u32], denominator: Denominator) -> CoverageSummary {
+        let mut sources = MemorySources::new();
+        sources.insert("src/f.c", source);
+        let coverage_map = HashMap::from([("src/f.c".to_string(), covered.iter().copied().collect())]);
+        summarize_coverage(&coverage_map, &sources, &[], denominator, None, &LineTables::new(), 1)
+    }
+
+    #[test]
+    fn covered_excluded_lines_are_left_out_of_the_summary() {
+        let source = "int f(int x) {\n    int y = x; // COV_EXCL_LINE\n\n    return y; }\nint g(void) { return 0; }\nint h(void) { return 1; }\n";
+        let summary = summarize(source, &[1, 2, 4], Denominator::Executable);
+        assert_eq!((summary.covered_lines, summary.total_lines), (2, 4));
+        assert_eq!(summary.file_totals["src/f.c"], (2, 4));
+        let summary = summarize(source, &[1, 2, 4], Denominator::All);
+        assert_eq!((summary.covered_lines, summary.total_lines), (2, 5));
+    }
+
+    #[test]
+    fn covered_lines_in_disabled_blocks_are_left_out_of_the_summary() {
+        let source = "int f(void) { return 0; }\n#if 0\nint g(void) { return 1; }\n#endif\nint h(void) { return 2; }\nint i(void) { return 3; }\n";
+        let summary = summarize(source, &[1, 3, 5], Denominator::Executable);
+        assert_eq!((summary.covered_lines, summary.total_lines), (2, 3));
+        let summary = summarize(source, &[1, 3, 5], Denominator::All);
+        assert_eq!((summary.covered_lines, summary.total_lines), (2, 5));
+    }
+
+    #[test]
+    fn covered_lines_under_lcov_markers_are_left_out_of_the_summary() {
+        let source = "int f(int x) {\n    int y = x; // LCOV_EXCL_LINE\n\n    return y; }\nint g(void) { return 0; }\nint h(void) { return 1; }\n";
+        let summary = summarize(source, &[1, 2, 4], Denominator::Executable);
+        assert_eq!((summary.covered_lines, summary.total_lines), (2, 4));
+        let summary = summarize(source, &[1, 2, 4], Denominator::All);
+        assert_eq!((summary.covered_lines, summary.total_lines), (2, 5));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn only_the_dwarf_denominator_counts_over_line_tables() {
+        let mut sources = MemorySources::new();
+        sources.insert("src/f.c", "int f(void) {\n    return 0; }\nint g(void) { return 1; }\n");
+        let coverage_map = HashMap::from([("src/f.c".to_string(), [1].into_iter().collect())]);
+        let line_tables = LineTables::from([("src/f.c".to_string(), [1, 2].into_iter().collect())]);
+        let totals = |denominator| {
+            let summary = summarize_coverage(&coverage_map, &sources, &[], denominator, None, &line_tables, 1);
+            (summary.covered_lines, summary.total_lines)
+        };
+        assert_eq!(totals(Denominator::Dwarf), (1, 2));
+        assert_eq!(totals(Denominator::Executable), (1, 3));
+        // Files without line table rows are counted like with `executable`
+        let summary = summarize_coverage(&coverage_map, &sources, &[], Denominator::Dwarf, None, &LineTables::new(), 1);
+        assert_eq!((summary.covered_lines, summary.total_lines), (1, 3));
+    }
+
+    /// Source tree counting the files read from it
+    #[derive(Debug)]
+    struct CountingSources(MemorySources, AtomicUsize);
+
+    impl SourceTree for CountingSources {
+        fn find(&self, file_path: &str) -> Option<(String, String)> {
+            self.0.find(file_path)
+        }
+
+        fn read(&self, full_path: &str) -> io::Result<Vec<u8>> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0.read(full_path)
+        }
+
+        fn matching_files(&self, patterns: &[String]) -> Vec<String> {
+            self.0.matching_files(patterns)
+        }
+
+        fn subtree(&self, root: &str) -> Box<dyn SourceTree> {
+            self.0.subtree(root)
+        }
+    }
+
+    #[test]
+    fn formats_with_only_totals_read_sources_once() {
+        let work_dir = std::env::temp_dir().join(format!("cov2html-test-{}-totals", std::process::id()));
+        let work_dir = work_dir.to_string_lossy().into_owned();
+        let mut sources = MemorySources::new();
+        sources.insert("src/f.c", "int x;\n");
+        let sources = CountingSources(sources, AtomicUsize::new(0));
+        let coverage_map = HashMap::from([("src/f.c".to_string(), [1].into_iter().collect())]);
+        let options = ReportOptions { renderers: vec![&crate::renderer::TextRenderer, &crate::renderer::JsonRenderer], ..ReportOptions::default() };
+
+        let summary = generate_report(&coverage_map, &sources, &work_dir, &options).unwrap();
+        assert_eq!(sources.1.load(Ordering::Relaxed), 1);
+        assert_eq!((summary.covered_lines, summary.total_lines, summary.outputs.len()), (1, 1, 2));
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn reports_name_their_published_files() {
+        let work_dir = std::env::temp_dir().join(format!("cov2html-test-{}-published", std::process::id()));
+        let work_dir = work_dir.to_string_lossy().into_owned();
+        let mut sources = MemorySources::new();
+        sources.insert("src/f.c", "int x;\n");
+        let coverage_map = HashMap::from([("src/f.c".to_string(), [1].into_iter().collect())]);
+        let options = ReportOptions { renderers: vec![&crate::renderer::TextRenderer, &crate::renderer::JsonRenderer], ..ReportOptions::default() };
+
+        let summary = generate_report(&coverage_map, &sources, &work_dir, &options).unwrap();
+        assert_eq!(summary.outputs, [format!("{}/coverage.txt", work_dir), format!("{}/coverage.json", work_dir)]);
+        assert!(summary.outputs.iter().all(|output| Path::new(output).is_file()));
+        assert!(!Path::new(&work_dir).join(STAGING_DIR).exists());
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+}
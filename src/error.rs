@@ -0,0 +1,101 @@
+use std::fmt;
+use std::io;
+
+use thiserror::Error;
+
+/// Errors that stop a report from being generated
+#[derive(Debug, Error)]
+pub enum Cov2HtmlError {
+    /// A coverage file could not be read or, in strict mode, had a malformed line
+    #[error("failed to read coverage file {path}: {source}")]
+    ReadCoverage {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// In strict mode, source files of the coverage data could not be read
+    #[error("{count} source files are missing or unreadable")]
+    MissingSources { count: usize },
+
+    /// In strict mode, sources differed from the checksums recorded with the
+    /// coverage data
+    #[error("{count} source files do not match the checksums recorded with the coverage")]
+    ChecksumMismatch { count: usize },
+
+    /// Writing the report failed, e.g. because the disk is full
+    #[error("failed to write report to {path}: {source}")]
+    WriteReport {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A template in the template directory could not be read
+    #[error("failed to read template {path}: {source}")]
+    ReadTemplate {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Writing a report to a caller-provided writer failed
+    #[error("failed to write report: {0}")]
+    WriteOutput(#[source] io::Error),
+
+    /// Report generation was cancelled through the cancel flag
+    #[error("report generation was cancelled")]
+    Cancelled,
+
+    /// The report was not fully configured
+    #[error("no {0} given for the report")]
+    MissingSetting(&'static str),
+}
+
+/// Result of generating a report
+pub type Result<T> = std::result::Result<T, Cov2HtmlError>;
+
+/// A problem that did not stop a report from being generated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A coverage record that could not be parsed and was skipped
+    MalformedRecord { file: String, line: usize, message: String },
+    /// A covered file that was not found under any source root
+    MissingSource { path: String },
+    /// A covered file whose source could not be read
+    UnreadableSource { path: String, message: String },
+    /// A file left out of the report by an exclusion preset
+    SkippedFile { path: String, reason: String },
+    /// A file whose coverage was possibly recorded against another version
+    /// of its source: it covers lines past the end, or the source changed
+    /// after the coverage was recorded
+    StaleCoverage { path: String, reason: String },
+    /// A source whose SHA-256 differs from the checksum recorded with the
+    /// coverage, so the coverage belongs to another revision of it
+    ChecksumMismatch { path: String, expected: String, actual: String },
+    /// A self-contained report estimated at `size` bytes that exceeded the
+    /// `limit` and was written with lazily loaded data instead
+    OversizedReport { size: u64, limit: u64 },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::MalformedRecord { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+            Warning::MissingSource { path } => write!(f, "Source file not found: {}", path),
+            Warning::UnreadableSource { path, message } => write!(f, "Failed to read source file {}: {}", path, message),
+            Warning::SkippedFile { path, reason } => write!(f, "Skipped {}: {}", path, reason),
+            Warning::StaleCoverage { path, reason } => write!(f, "Coverage of {} is possibly stale: {}", path, reason),
+            Warning::ChecksumMismatch { path, expected, actual } => write!(
+                f,
+                "Source {} does not match the coverage data: SHA-256 is {}, recorded {}",
+                path, actual, expected
+            ),
+            Warning::OversizedReport { size, limit } => write!(
+                f,
+                "Report of about {:.1} MiB exceeds the limit of {:.1} MiB; writing external assets and lazily loaded data chunks instead",
+                *size as f64 / (1024.0 * 1024.0), *limit as f64 / (1024.0 * 1024.0)
+            ),
+        }
+    }
+}
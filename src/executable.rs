@@ -0,0 +1,769 @@
+//! Which lines of a source count toward its total.
+//!
+//! Blank lines, lines holding nothing but comments and preprocessor
+//! directives never run, so counting them makes comment-heavy kernel files
+//! look far worse covered than they are. With the `tree-sitter` feature, C
+//! and Rust sources are parsed and only the lines statements start on count,
+//! much like the lines gcov and llvm-cov instrument. Other sources, and
+//! builds without the feature, fall back to a lexical scan that knows
+//! comments, string literals and line continuations but does not parse the
+//! code. When the binary the coverage came from is given, its DWARF line
+//! tables say exactly which lines have instructions, and they take
+//! precedence for the files they list.
+//!
+//! Code the preprocessor leaves out, behind `#if 0` or, given the kernel
+//! `.config`, behind `#ifdef CONFIG_X` for an option that is not set, is
+//! never compiled and does not count either. Lines marked with
+//! `COV_EXCL_LINE`, or between `COV_EXCL_START` and `COV_EXCL_STOP`, are
+//! left out whatever the denominator, as grcov does, and so are lcov's
+//! `LCOV_EXCL_` markers.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::coverage::Language;
+use crate::lines::LineSet;
+
+/// Lines each source file has instructions on, by path, as read from the
+/// DWARF line tables of a binary
+pub type LineTables = HashMap<String, LineSet>;
+
+/// Which lines of a source its coverage percentage is taken over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Denominator {
+    /// Every line of the file not excluded by a marker comment
+    All,
+    /// The lines with code
+    #[default]
+    Executable,
+    /// The lines the DWARF line tables of the binary attribute instructions
+    /// to; files without line table rows count their lines with code
+    Dwarf,
+}
+
+impl FromStr for Denominator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Denominator::All),
+            "executable" => Ok(Denominator::Executable),
+            "dwarf" => Ok(Denominator::Dwarf),
+            _ => Err(format!("unknown denominator '{}' (expected all, executable or dwarf)", s)),
+        }
+    }
+}
+
+impl Denominator {
+    /// Name of the denominator as given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Denominator::All => "all",
+            Denominator::Executable => "executable",
+            Denominator::Dwarf => "dwarf",
+        }
+    }
+
+    /// Lines of `file_path` with instructions in `line_tables`, which only
+    /// the `dwarf` denominator counts over
+    pub(crate) fn instrumented<'a>(&self, line_tables: &'a LineTables, file_path: &str) -> Option<&'a LineSet> {
+        match self {
+            Denominator::Dwarf => line_tables.get(file_path),
+            Denominator::All | Denominator::Executable => None,
+        }
+    }
+
+    /// How lines are told apart for this denominator in this build, which
+    /// cached line counts must agree on
+    #[cfg(feature = "html")]
+    pub(crate) fn method(&self) -> &'static str {
+        match self {
+            Denominator::All => "all",
+            Denominator::Executable | Denominator::Dwarf if cfg!(feature = "tree-sitter") => "tree-sitter",
+            Denominator::Executable | Denominator::Dwarf => "lexical",
+        }
+    }
+}
+
+/// Options set in a kernel `.config`, which decide the `#ifdef CONFIG_X`
+/// blocks that are compiled
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct KernelConfig {
+    /// Value of each option set, by name, e.g. `CONFIG_SMP` to `y`
+    options: BTreeMap<String, String>,
+}
+
+impl KernelConfig {
+    /// Parses the `CONFIG_X=value` lines of a `.config`; options that are
+    /// not set, commented out as `# CONFIG_X is not set`, are left out
+    pub fn parse(text: &str) -> KernelConfig {
+        let options = text.lines()
+            .filter_map(|line| line.trim().split_once('='))
+            .filter(|(name, _)| name.starts_with("CONFIG_"))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .filter(|(_, value)| value != "n")
+            .collect();
+        KernelConfig { options }
+    }
+
+    /// Reads and parses a `.config`
+    pub fn read(path: impl AsRef<Path>) -> io::Result<KernelConfig> {
+        fs::read_to_string(path).map(|text| KernelConfig::parse(&text))
+    }
+
+    /// Whether the preprocessor sees `name` defined: options built in or
+    /// with a value define their own name, modules `<name>_MODULE`, as in
+    /// the kernel's autoconf.h. `None` for names that are not options.
+    fn defined(&self, name: &str) -> Option<bool> {
+        if !name.starts_with("CONFIG_") {
+            return None;
+        }
+        if let Some(option) = name.strip_suffix("_MODULE")
+            && self.options.get(option).is_some_and(|value| value == "m")
+        {
+            return Some(true);
+        }
+        Some(self.options.get(name).is_some_and(|value| value != "m"))
+    }
+
+    /// Value of an option as `IS_ENABLED()` and friends see it: `y`, `m`
+    /// or nothing
+    fn tristate(&self, name: &str) -> Option<Option<&str>> {
+        name.starts_with("CONFIG_").then(|| self.options.get(name).map(String::as_str))
+    }
+}
+
+/// Syntax the scan needs to know about a language
+struct Syntax {
+    /// Whether `/* */` and `//` start comments
+    c_comments: bool,
+    /// Whether `#` at the start of a line starts a comment
+    hash_comments: bool,
+    /// Whether `#` at the start of a line starts a preprocessor directive
+    preprocessor: bool,
+    /// Whether `'` quotes character literals; Rust uses it for lifetimes too
+    char_literals: bool,
+}
+
+impl Syntax {
+    fn of(language: Language) -> Syntax {
+        let (c_comments, hash_comments, preprocessor, char_literals) = match language {
+            Language::C | Language::Header | Language::Assembly => (true, false, true, true),
+            Language::Rust => (true, false, false, false),
+            // Device tree properties such as `#address-cells` start with `#`
+            Language::DeviceTree => (true, false, false, false),
+            Language::Makefile => (false, true, false, false),
+            Language::Other => (false, false, false, false),
+        };
+        Syntax { c_comments, hash_comments, preprocessor, char_literals }
+    }
+}
+
+/// Returns the lines of `content` in `language` that are left out of its
+/// total with `denominator`, the uncounted ones included. Lines are
+/// numbered from 1.
+pub fn non_code_lines(content: &[u8], language: Language, denominator: Denominator, config: Option<&KernelConfig>) -> LineSet {
+    let non_code = match denominator {
+        Denominator::All => LineSet::new(),
+        #[cfg(feature = "tree-sitter")]
+        Denominator::Executable | Denominator::Dwarf => non_statement_lines(content, language)
+            .unwrap_or_else(|| lexical_non_code_lines(content, language)),
+        #[cfg(not(feature = "tree-sitter"))]
+        Denominator::Executable | Denominator::Dwarf => lexical_non_code_lines(content, language),
+    };
+    non_code.union(&uncounted_lines(content, language, config))
+}
+
+/// Returns the lines of `content` that never count, even when covered,
+/// whatever the denominator: those disabled by the preprocessor with
+/// `config` and those excluded by marker comments
+pub fn uncounted_lines(content: &[u8], language: Language, config: Option<&KernelConfig>) -> LineSet {
+    disabled_lines(content, language, config).union(&excluded_lines(content))
+}
+
+/// Marker comments excluding lines from coverage
+struct ExclusionMarkers {
+    /// Excludes the line it is on
+    line: &'static str,
+    /// Excludes the lines from the one it is on
+    start: &'static str,
+    /// Ends the exclusion on the line it is on
+    stop: &'static str,
+}
+
+/// Markers recognized in sources: grcov's and lcov's. lcov's
+/// `LCOV_EXCL_BR_LINE` and `LCOV_EXCL_BR_START`/`STOP` only exclude branch
+/// data and keep their lines, so with line coverage alone they change
+/// nothing.
+const EXCLUSION_MARKERS: &[ExclusionMarkers] = &[
+    ExclusionMarkers { line: "COV_EXCL_LINE", start: "COV_EXCL_START", stop: "COV_EXCL_STOP" },
+    ExclusionMarkers { line: "LCOV_EXCL_LINE", start: "LCOV_EXCL_START", stop: "LCOV_EXCL_STOP" },
+];
+
+/// Returns the lines of `content` excluded by marker comments: those with
+/// a line marker such as `COV_EXCL_LINE` on them, and those from a start
+/// marker through the stop marker, or through the end of the file when it
+/// is missing. Markers only count as whole words, wherever they are on the
+/// line.
+pub fn excluded_lines(content: &[u8]) -> LineSet {
+    let mut excluded = LineSet::new();
+    // Cheap check, since most sources have no markers at all
+    if !content.windows(8).any(|window| window == b"COV_EXCL") {
+        return excluded;
+    }
+
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    let mut in_region = false;
+    for (index, line) in content.split(|byte| *byte == b'\n').enumerate() {
+        let has = |marker: &str| has_word(line, marker.as_bytes());
+        let starts = EXCLUSION_MARKERS.iter().any(|markers| has(markers.start));
+        let stops = EXCLUSION_MARKERS.iter().any(|markers| has(markers.stop));
+        if in_region || starts || stops || EXCLUSION_MARKERS.iter().any(|markers| has(markers.line)) {
+            excluded.insert(index as u32 + 1);
+        }
+        in_region = (in_region || starts) && !stops;
+    }
+
+    excluded
+}
+
+/// Whether `word` is on `line` without an identifier character next to it
+fn has_word(line: &[u8], word: &[u8]) -> bool {
+    let is_identifier = |byte: Option<&u8>| byte.is_some_and(|byte| byte.is_ascii_alphanumeric() || *byte == b'_');
+    line.windows(word.len()).enumerate().any(|(start, window)| {
+        window == word && !is_identifier(start.checked_sub(1).and_then(|before| line.get(before))) && !is_identifier(line.get(start + word.len()))
+    })
+}
+
+/// Conditional block of the preprocessor the scan is in
+struct Conditional {
+    /// Whether the block is inside a branch that is not compiled
+    outer_disabled: bool,
+    /// Whether an earlier branch is compiled, if known
+    taken: Option<bool>,
+    /// Whether the current branch is not compiled
+    disabled: bool,
+}
+
+impl Conditional {
+    /// Enters the next branch, compiled when `condition` holds
+    fn branch(&mut self, condition: Option<bool>) {
+        self.disabled = self.outer_disabled || self.taken == Some(true) || condition == Some(false);
+        self.taken = match (self.taken, condition) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        };
+    }
+}
+
+/// Returns the lines of `content` inside conditional branches the
+/// preprocessor leaves out: `#if 0` and the `#else` of `#if 1`, and given
+/// `config`, the branches on kernel options that are not set. Branches on
+/// anything else are taken to be compiled. The directives opening and
+/// closing a branch are not part of it.
+pub fn disabled_lines(content: &[u8], language: Language, config: Option<&KernelConfig>) -> LineSet {
+    let syntax = Syntax::of(language);
+    let mut disabled = LineSet::new();
+    if !syntax.preprocessor || content.is_empty() {
+        return disabled;
+    }
+
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    let mut stack: Vec<Conditional> = Vec::new();
+    let mut in_comment = false;
+    // Text and first line of the directive being read, while it continues
+    // on the next line
+    let mut directive: Option<(Vec<u8>, u32)> = None;
+    for (index, line) in content.split(|byte| *byte == b'\n').enumerate() {
+        let line_number = index as u32 + 1;
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let first = line.iter().position(|byte| !byte.is_ascii_whitespace());
+        let starts_directive = directive.is_none() && !in_comment && first.is_some_and(|first| line[first] == b'#');
+        in_comment = scan(line, &syntax, in_comment).1;
+        if !starts_directive && directive.is_none() {
+            if stack.last().is_some_and(|conditional| conditional.disabled) {
+                disabled.insert(line_number);
+            }
+            continue;
+        }
+
+        let (text, start) = directive.get_or_insert_with(|| (Vec::new(), line_number));
+        text.extend_from_slice(line.strip_suffix(b"\\").unwrap_or(line));
+        text.push(b' ');
+        if line.ends_with(b"\\") {
+            continue;
+        }
+        let start = *start;
+        let text = directive.take().map(|(text, _)| text).unwrap_or_default();
+        let text = String::from_utf8_lossy(&text);
+        // Comments end the condition as far as the scan is concerned
+        let text = text.trim_start().trim_start_matches('#');
+        let text = text.split("/*").next().unwrap_or(text).split("//").next().unwrap_or(text).trim();
+        let (keyword, condition) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let condition = condition.trim();
+
+        // Whether the branch the directive itself is in is compiled
+        let enclosing_disabled = stack.last().is_some_and(|conditional| conditional.disabled);
+        let directive_disabled = match keyword {
+            "if" | "ifdef" | "ifndef" => {
+                let condition = match keyword {
+                    "if" => evaluate(condition, config),
+                    "ifdef" => defined(condition, config),
+                    _ => defined(condition, config).map(|defined| !defined),
+                };
+                let mut conditional = Conditional { outer_disabled: enclosing_disabled, taken: Some(false), disabled: false };
+                conditional.branch(condition);
+                stack.push(conditional);
+                enclosing_disabled
+            }
+            "elif" | "elifdef" | "elifndef" | "else" => match stack.last_mut() {
+                Some(conditional) => {
+                    conditional.branch(match keyword {
+                        "elif" => evaluate(condition, config),
+                        "elifdef" => defined(condition, config),
+                        "elifndef" => defined(condition, config).map(|defined| !defined),
+                        _ => Some(true),
+                    });
+                    conditional.outer_disabled
+                }
+                None => false,
+            },
+            "endif" => stack.pop().is_some_and(|conditional| conditional.outer_disabled),
+            _ => enclosing_disabled,
+        };
+        if directive_disabled {
+            disabled.union_with(&(start..=line_number).collect());
+        }
+    }
+
+    disabled
+}
+
+/// Whether `name` is defined, for the names `config` knows
+fn defined(name: &str, config: Option<&KernelConfig>) -> Option<bool> {
+    config?.defined(name)
+}
+
+/// Evaluates the condition of an `#if`, or `None` when it depends on more
+/// than integer literals, `defined` and the kernel options in `config`
+fn evaluate(condition: &str, config: Option<&KernelConfig>) -> Option<bool> {
+    let tokens = tokenize(condition);
+    let mut parser = ConditionParser { tokens: &tokens, position: 0, config };
+    let value = parser.or()?;
+    if parser.position == tokens.len() { value } else { None }
+}
+
+/// Splits a condition into identifiers and numbers, operators and
+/// parentheses
+fn tokenize(condition: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = condition.trim_start();
+    while let Some(first) = rest.chars().next() {
+        let length = if first.is_ascii_alphanumeric() || first == '_' {
+            rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len())
+        } else if rest.starts_with("&&") || rest.starts_with("||") || rest.starts_with("==") || rest.starts_with("!=") {
+            2
+        } else {
+            first.len_utf8()
+        };
+        tokens.push(&rest[..length]);
+        rest = rest[length..].trim_start();
+    }
+    tokens
+}
+
+/// Recursive descent over the tokens of a condition. Each level returns
+/// `None` for syntax it does not handle, which gives up on the condition,
+/// and `Some(None)` for values that are not known.
+struct ConditionParser<'a> {
+    tokens: &'a [&'a str],
+    position: usize,
+    config: Option<&'a KernelConfig>,
+}
+
+impl<'a> ConditionParser<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.position).copied();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn expect(&mut self, token: &str) -> Option<()> {
+        (self.next()? == token).then_some(())
+    }
+
+    fn or(&mut self) -> Option<Option<bool>> {
+        let mut value = self.and()?;
+        while self.peek() == Some("||") {
+            self.position += 1;
+            let right = self.and()?;
+            value = match (value, right) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            };
+        }
+        Some(value)
+    }
+
+    fn and(&mut self) -> Option<Option<bool>> {
+        let mut value = self.unary()?;
+        while self.peek() == Some("&&") {
+            self.position += 1;
+            let right = self.unary()?;
+            value = match (value, right) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+        }
+        Some(value)
+    }
+
+    fn unary(&mut self) -> Option<Option<bool>> {
+        match self.next()? {
+            "!" => Some(self.unary()?.map(|value| !value)),
+            "(" => {
+                let value = self.or()?;
+                self.expect(")")?;
+                Some(value)
+            }
+            "defined" => {
+                let parenthesized = self.peek() == Some("(");
+                if parenthesized {
+                    self.position += 1;
+                }
+                let name = self.next()?;
+                if parenthesized {
+                    self.expect(")")?;
+                }
+                Some(defined(name, self.config))
+            }
+            macro_name @ ("IS_ENABLED" | "IS_BUILTIN" | "IS_MODULE") => {
+                self.expect("(")?;
+                let name = self.next()?;
+                self.expect(")")?;
+                let value = self.config.and_then(|config| config.tristate(name));
+                Some(value.map(|value| match macro_name {
+                    "IS_ENABLED" => value.is_some_and(|value| value == "y" || value == "m"),
+                    "IS_BUILTIN" => value == Some("y"),
+                    _ => value == Some("m"),
+                }))
+            }
+            token if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                let digits = token.trim_end_matches(['u', 'U', 'l', 'L']);
+                let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+                    Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+                    None => digits.parse::<u64>().ok()?,
+                };
+                Some(Some(value != 0))
+            }
+            // Options stand for their value in `#if`, and undefined names for 0
+            name if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+                let value = self.config.and_then(|config| config.tristate(name));
+                Some(value.map(|value| value.is_some_and(|value| value != "0" && value != "m")))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the lines that hold no code: blank lines, lines with only
+/// comments and the lines of preprocessor directives, including their
+/// continuations
+fn lexical_non_code_lines(content: &[u8], language: Language) -> LineSet {
+    let syntax = Syntax::of(language);
+    let mut non_code = LineSet::new();
+    if content.is_empty() {
+        return non_code;
+    }
+
+    // A trailing newline does not start another line
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    let mut in_comment = false;
+    let mut in_directive = false;
+    for (index, line) in content.split(|byte| *byte == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let continued = line.ends_with(b"\\");
+        let first = line.iter().position(|byte| !byte.is_ascii_whitespace());
+
+        // A directive runs on through its continuation lines, comments
+        // inside it included
+        if in_directive || (syntax.preprocessor && !in_comment && first.is_some_and(|first| line[first] == b'#')) {
+            in_directive = continued;
+            in_comment = scan(line, &syntax, in_comment).1;
+            non_code.insert(index as u32 + 1);
+            continue;
+        }
+
+        let (has_code, comment_open) = scan(line, &syntax, in_comment);
+        in_comment = comment_open;
+        if !has_code {
+            non_code.insert(index as u32 + 1);
+        }
+    }
+
+    non_code
+}
+
+/// Returns the lines no statement starts on in a C or Rust source, or `None`
+/// for other languages. Function headers count as the line their entry is
+/// attributed to. Parts the grammar cannot make sense of, such as functions
+/// behind unusual macros, keep the lines the lexical scan finds code on.
+#[cfg(feature = "tree-sitter")]
+fn non_statement_lines(content: &[u8], language: Language) -> Option<LineSet> {
+    let grammar: tree_sitter::Language = match language {
+        Language::C | Language::Header => tree_sitter_c::LANGUAGE.into(),
+        Language::Rust => tree_sitter_rust::LANGUAGE.into(),
+        _ => return None,
+    };
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&grammar).ok()?;
+    let tree = parser.parse(content, None)?;
+
+    let lexical = lexical_non_code_lines(content, language);
+    let mut rows: Vec<u32> = Vec::new();
+    // Nodes still to visit, with whether they are inside a function
+    let mut pending = vec![(tree.root_node(), false)];
+    while let Some((node, in_function)) = pending.pop() {
+        let start = node.start_position().row as u32 + 1;
+        if node.is_error() {
+            let end = node.end_position().row as u32 + 1;
+            rows.extend((start..=end).filter(|line| !lexical.contains(*line)));
+            continue;
+        }
+
+        let kind = node.kind();
+        let function = matches!(kind, "function_definition" | "function_item");
+        if function || (in_function && is_statement(kind, &node)) {
+            rows.push(start);
+        }
+        // The condition of a `do { } while` loop is evaluated on its own line
+        if kind == "do_statement" && let Some(condition) = node.child_by_field_name("condition") {
+            rows.push(condition.start_position().row as u32 + 1);
+        }
+        // Every expression directly in a Rust block runs, the tail one included
+        if kind == "block" {
+            rows.extend(node.named_children(&mut node.walk())
+                .filter(|child| !matches!(child.kind(), "line_comment" | "block_comment" | "attribute_item"))
+                .map(|child| child.start_position().row as u32 + 1));
+        }
+
+        pending.extend(node.named_children(&mut node.walk()).map(|child| (child, in_function || function)));
+    }
+
+    let statements: LineSet = rows.into_iter().collect();
+    let lines = content.strip_suffix(b"\n").unwrap_or(content).split(|byte| *byte == b'\n').count() as u32;
+    let all: LineSet = (1..=lines).filter(|_| !content.is_empty()).collect();
+    Some(all.difference(&statements))
+}
+
+/// Whether a node inside a function is a statement that runs: C statements
+/// other than blocks and local declarations with an initializer, Rust
+/// statements and match arms
+#[cfg(feature = "tree-sitter")]
+fn is_statement(kind: &str, node: &tree_sitter::Node) -> bool {
+    match kind {
+        "compound_statement" => false,
+        "declaration" => node.named_children(&mut node.walk()).any(|child| child.kind() == "init_declarator"),
+        "let_declaration" | "match_arm" => true,
+        kind => kind.ends_with("_statement"),
+    }
+}
+
+/// Returns the lines of a source with `total_lines` lines that its line
+/// table attributes no instructions to
+pub fn non_instrumented_lines(total_lines: usize, instrumented: &LineSet) -> LineSet {
+    let all: LineSet = (1..=total_lines as u32).collect();
+    all.difference(instrumented)
+}
+
+/// Covered lines and lines counted toward the total of a source with
+/// `total_lines` lines: all but the non-code ones, though a covered line
+/// always counts unless it is one of the `uncounted` lines, which
+/// `non_code` includes
+pub fn line_counts(total_lines: usize, non_code: &LineSet, uncounted: &LineSet, covered_lines: &LineSet) -> (usize, usize) {
+    let counted = covered_lines.difference(uncounted);
+    (counted.len(), total_lines - non_code.difference(&counted).len())
+}
+
+/// Scans one line, starting inside a block comment when `in_comment`.
+/// Returns whether anything outside comments was found and whether a block
+/// comment is open at its end.
+fn scan(line: &[u8], syntax: &Syntax, mut in_comment: bool) -> (bool, bool) {
+    let mut has_code = false;
+    let mut i = 0;
+    while i < line.len() {
+        let byte = line[i];
+        let next = line.get(i + 1).copied();
+        if in_comment {
+            if byte == b'*' && next == Some(b'/') {
+                in_comment = false;
+                i += 2;
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+
+        match byte {
+            b'/' if syntax.c_comments && next == Some(b'*') => {
+                in_comment = true;
+                i += 2;
+            }
+            b'/' if syntax.c_comments && next == Some(b'/') => break,
+            b'#' if syntax.hash_comments => break,
+            b'"' => {
+                has_code = true;
+                i = skip_literal(line, i, b'"');
+            }
+            b'\'' if syntax.char_literals => {
+                has_code = true;
+                i = skip_literal(line, i, b'\'');
+            }
+            byte if byte.is_ascii_whitespace() => i += 1,
+            _ => {
+                has_code = true;
+                i += 1;
+            }
+        }
+    }
+
+    (has_code, in_comment)
+}
+
+/// Index just past the literal opened by the quote at `start`, or the end of
+/// the line for literals that continue on the next one
+fn skip_literal(line: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < line.len() {
+        match line[i] {
+            b'\\' => i += 2,
+            byte if byte == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXCLUDED_SOURCE: &str = "\
+int f(int x) {
+    int y = x; // COV_EXCL_LINE
+
+    return y; }
+int g(void) { return 0; }
+int h(void) { return 1; }
+";
+
+    #[test]
+    fn covered_excluded_lines_are_not_counted() {
+        let content = EXCLUDED_SOURCE.as_bytes();
+        let covered: LineSet = [1, 2, 4].into_iter().collect();
+        let excluded = excluded_lines(content);
+        assert_eq!(excluded.iter().collect::<Vec<u32>>(), [2]);
+
+        let non_code = non_code_lines(content, Language::C, Denominator::Executable, None);
+        assert_eq!(line_counts(6, &non_code, &excluded, &covered), (2, 4));
+        let non_code = non_code_lines(content, Language::C, Denominator::All, None);
+        assert_eq!(line_counts(6, &non_code, &excluded, &covered), (2, 5));
+    }
+
+    #[test]
+    fn covered_disabled_lines_are_not_counted() {
+        let content = b"int a;\n#if 0\nint b;\n#endif\nint c;\n";
+        let covered: LineSet = [1, 3, 5].into_iter().collect();
+        let uncounted = uncounted_lines(content, Language::C, None);
+        assert_eq!(uncounted.iter().collect::<Vec<u32>>(), [3]);
+
+        let non_code = non_code_lines(content, Language::C, Denominator::Executable, None);
+        assert_eq!(line_counts(5, &non_code, &uncounted, &covered), (2, 2));
+        let non_code = non_code_lines(content, Language::C, Denominator::All, None);
+        assert_eq!(line_counts(5, &non_code, &uncounted, &covered), (2, 4));
+    }
+
+    #[test]
+    fn lcov_markers_exclude_like_grcov_ones() {
+        let lcov = EXCLUDED_SOURCE.replace("COV_EXCL_LINE", "LCOV_EXCL_LINE");
+        let content = lcov.as_bytes();
+        let covered: LineSet = [1, 2, 4].into_iter().collect();
+        let excluded = excluded_lines(content);
+        assert_eq!(excluded.iter().collect::<Vec<u32>>(), [2]);
+        let non_code = non_code_lines(content, Language::C, Denominator::Executable, None);
+        assert_eq!(line_counts(6, &non_code, &excluded, &covered), (2, 4));
+
+        let region = "int a;\n// LCOV_EXCL_START\nint b;\nint c;\n// LCOV_EXCL_STOP\nint d;\n";
+        assert_eq!(excluded_lines(region.as_bytes()).iter().collect::<Vec<u32>>(), [2, 3, 4, 5]);
+        // Branch-only markers leave their lines counted
+        assert!(excluded_lines(b"int a; // LCOV_EXCL_BR_LINE\n").is_empty());
+    }
+
+    fn lines(lines: &LineSet) -> Vec<u32> {
+        lines.iter().collect()
+    }
+
+    #[test]
+    fn blank_comment_and_directive_lines_hold_no_code() {
+        let content = b"\
+#include <linux/init.h>
+#define TWICE(x) \\
+    ((x) * 2)
+
+/* A comment
+ * over lines */
+int x; /* trailing */
+// only a comment
+static int y = 1;
+";
+        assert_eq!(lines(&lexical_non_code_lines(content, Language::C)), [1, 2, 3, 4, 5, 6, 8]);
+        assert_eq!(lines(&lexical_non_code_lines(b"# comment\nobj-y += a.o\n", Language::Makefile)), [1]);
+    }
+
+    #[test]
+    fn preprocessor_disables_if_0_and_unset_options() {
+        let content = b"\
+#if 0
+dead();
+#else
+live();
+#endif
+#ifdef CONFIG_FOO
+foo();
+#endif
+#if IS_ENABLED(CONFIG_BAR)
+bar();
+#endif
+";
+        assert_eq!(lines(&disabled_lines(content, Language::C, None)), [2]);
+        let config = KernelConfig::parse("CONFIG_BAR=m\n# CONFIG_FOO is not set\n");
+        assert_eq!(lines(&disabled_lines(content, Language::C, Some(&config))), [2, 7]);
+    }
+
+    #[test]
+    fn exclusion_regions_run_to_their_stop_marker() {
+        let content = b"a;\n// COV_EXCL_START\nb;\n// COV_EXCL_STOP\nc;\n// COV_EXCL_START\nd;\n";
+        assert_eq!(lines(&excluded_lines(content)), [2, 3, 4, 6, 7]);
+        // Markers only count as whole words
+        assert!(excluded_lines(b"int MY_COV_EXCL_LINE;\n").is_empty());
+    }
+
+    #[test]
+    fn denominator_names_round_trip() {
+        for denominator in [Denominator::All, Denominator::Executable, Denominator::Dwarf] {
+            assert_eq!(denominator.name().parse::<Denominator>(), Ok(denominator));
+        }
+        assert!("everything".parse::<Denominator>().is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! C ABI for generating reports in-process, e.g. from C fuzzing harnesses.
+//!
+//! The declarations are in `include/cov2html.h`, generated from this module
+//! with `cbindgen --config cbindgen.toml --output include/cov2html.h`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::builder::ReportBuilder;
+use crate::error::Cov2HtmlError;
+
+/// Result of a `cov2html_generate` call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cov2HtmlStatus {
+    /// The report was written
+    Ok = 0,
+    /// A required path was NULL or not valid UTF-8
+    InvalidArgument = 1,
+    /// The coverage file could not be read or, in strict mode, had a malformed line
+    ReadCoverage = 2,
+    /// In strict mode, source files of the coverage data could not be read
+    MissingSources = 3,
+    /// Writing the report failed
+    WriteReport = 4,
+    /// Report generation panicked
+    Panic = 5,
+    /// In strict mode, sources differed from the checksums recorded with the
+    /// coverage data
+    ChecksumMismatch = 6,
+}
+
+/// Report settings; start from `cov2html_default_options()`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Cov2HtmlOptions {
+    /// Title shown in the page title and headings, or NULL for the default
+    pub title: *const c_char,
+    /// Fail on malformed coverage lines and missing or unreadable sources
+    pub strict: bool,
+    /// Worker threads used to read sources and render pages; 0 uses one per
+    /// logical CPU
+    pub jobs: usize,
+    /// Write per-file data chunks next to the report and load them on demand
+    pub split_data: bool,
+    /// Emit CSS, JavaScript and data as external files
+    pub external_assets: bool,
+    /// Pre-render every file into static HTML pages that work without JavaScript
+    pub no_js: bool,
+}
+
+thread_local! {
+    /// Message of the last failed call on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records the message returned by `cov2html_last_error`
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Reads a NULL-terminated UTF-8 string argument
+///
+/// # Safety
+///
+/// `value` must be NULL or point to a NULL-terminated string.
+unsafe fn str_argument<'a>(value: *const c_char, name: &str) -> Result<&'a str, Cov2HtmlStatus> {
+    if value.is_null() {
+        set_last_error(format!("{} is NULL", name));
+        return Err(Cov2HtmlStatus::InvalidArgument);
+    }
+    // SAFETY: the caller guarantees `value` is a NULL-terminated string.
+    unsafe { CStr::from_ptr(value) }.to_str().map_err(|_| {
+        set_last_error(format!("{} is not valid UTF-8", name));
+        Cov2HtmlStatus::InvalidArgument
+    })
+}
+
+/// Options matching the defaults of `cov2html report`
+#[unsafe(no_mangle)]
+pub extern "C" fn cov2html_default_options() -> Cov2HtmlOptions {
+    Cov2HtmlOptions {
+        title: std::ptr::null(),
+        strict: false,
+        jobs: 0,
+        split_data: false,
+        external_assets: false,
+        no_js: false,
+    }
+}
+
+/// Generates a report of `coverage_path` with sources from `source_dir` into
+/// `out_dir`. `options` may be NULL to use the defaults. On failure the
+/// reason is available from `cov2html_last_error()`.
+///
+/// # Safety
+///
+/// The paths must be NULL-terminated strings, and `options` must be NULL or
+/// point to a valid `Cov2HtmlOptions` whose `title` is NULL or a
+/// NULL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cov2html_generate(
+    coverage_path: *const c_char,
+    source_dir: *const c_char,
+    out_dir: *const c_char,
+    options: *const Cov2HtmlOptions,
+) -> Cov2HtmlStatus {
+    // SAFETY: the caller guarantees the validity of the arguments.
+    let builder = match unsafe { report_builder(coverage_path, source_dir, out_dir, options) } {
+        Ok(builder) => builder,
+        Err(status) => return status,
+    };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| builder.generate())) {
+        Ok(Ok(_)) => Cov2HtmlStatus::Ok,
+        Ok(Err(e)) => {
+            let status = match e {
+                Cov2HtmlError::ReadCoverage { .. } => Cov2HtmlStatus::ReadCoverage,
+                Cov2HtmlError::MissingSources { .. } => Cov2HtmlStatus::MissingSources,
+                Cov2HtmlError::ChecksumMismatch { .. } => Cov2HtmlStatus::ChecksumMismatch,
+                _ => Cov2HtmlStatus::WriteReport,
+            };
+            set_last_error(e.to_string());
+            status
+        }
+        Err(_) => {
+            set_last_error("report generation panicked".to_string());
+            Cov2HtmlStatus::Panic
+        }
+    }
+}
+
+/// Configures a report from the arguments of `cov2html_generate`
+///
+/// # Safety
+///
+/// See `cov2html_generate`.
+unsafe fn report_builder(
+    coverage_path: *const c_char,
+    source_dir: *const c_char,
+    out_dir: *const c_char,
+    options: *const Cov2HtmlOptions,
+) -> Result<ReportBuilder, Cov2HtmlStatus> {
+    // SAFETY: the caller guarantees the validity of the arguments.
+    let (coverage_path, source_dir, out_dir) = unsafe {
+        (
+            str_argument(coverage_path, "coverage_path")?,
+            str_argument(source_dir, "source_dir")?,
+            str_argument(out_dir, "out_dir")?,
+        )
+    };
+    // SAFETY: `options` is NULL or points to valid options.
+    let options = unsafe { options.as_ref() }.copied().unwrap_or_else(|| cov2html_default_options());
+
+    let mut builder = ReportBuilder::new()
+        .coverage(coverage_path)
+        .source_root(source_dir)
+        .output(out_dir)
+        .strict(options.strict)
+        .jobs(options.jobs);
+    if !options.title.is_null() {
+        // SAFETY: a non-NULL title is a NULL-terminated string.
+        builder = builder.title(unsafe { str_argument(options.title, "title") }?);
+    }
+    let report_options = builder.options_mut();
+    report_options.split_data = options.split_data;
+    report_options.external_assets = options.external_assets;
+    report_options.no_js = options.no_js;
+
+    Ok(builder)
+}
+
+/// Message describing why the last failed call on this thread failed, or
+/// NULL. The string is valid until the next call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn cov2html_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use crate::builder::ReportBuilder;
+use crate::coverage::{CoverageSummary, ReportOptions};
+use crate::error::Cov2HtmlError;
+use crate::model::CoverageReport;
+use crate::source::{CachedSources, SourceTree};
+
+/// Generates report after report from the same source tree, keeping the
+/// sources read in memory in between, e.g. in a daemon that publishes a new
+/// report every few minutes. Only sources modified since the previous
+/// report are read again.
+///
+/// ```no_run
+/// use cov2html::ReportGenerator;
+/// use cov2html::coverage::ReportOptions;
+///
+/// let generator = ReportGenerator::new(vec!["linux".to_string()], ReportOptions::default());
+/// loop {
+///     generator.generate("cov.txt", "report")?;
+///     std::thread::sleep(std::time::Duration::from_secs(300));
+/// }
+/// # Ok::<(), cov2html::error::Cov2HtmlError>(())
+/// ```
+#[derive(Debug)]
+pub struct ReportGenerator {
+    sources: Arc<CachedSources>,
+    options: ReportOptions,
+}
+
+impl ReportGenerator {
+    /// Generates reports with `options` from the sources in `sources`
+    pub fn new(sources: impl SourceTree + 'static, options: ReportOptions) -> ReportGenerator {
+        ReportGenerator { sources: Arc::new(CachedSources::new(sources)), options }
+    }
+
+    /// Options the reports are generated with
+    pub fn options(&self) -> &ReportOptions {
+        &self.options
+    }
+
+    /// Changes the options of the following reports; the cache is kept
+    pub fn options_mut(&mut self) -> &mut ReportOptions {
+        &mut self.options
+    }
+
+    /// Number of source files held in memory
+    pub fn cached_files(&self) -> usize {
+        self.sources.cached_files()
+    }
+
+    /// Forgets every cached source, e.g. after switching branches
+    pub fn clear_cache(&self) {
+        self.sources.clear();
+    }
+
+    /// Generates a report of a coverage file into `work_dir`
+    pub fn generate(&self, coverage_file: &str, work_dir: &str) -> Result<CoverageSummary, Cov2HtmlError> {
+        self.builder().coverage(coverage_file).output(work_dir).generate()
+    }
+
+    /// Generates a report of already parsed coverage into `work_dir`
+    pub fn generate_report(&self, report: &CoverageReport, work_dir: &str) -> Result<CoverageSummary, Cov2HtmlError> {
+        self.builder().coverage_report(report).output(work_dir).generate()
+    }
+
+    fn builder(&self) -> ReportBuilder {
+        ReportBuilder::new().options(self.options.clone()).source_tree(self.sources.clone())
+    }
+}
@@ -0,0 +1,1722 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+
+use log::{debug, error, info, warn};
+use serde_json::{json, Value};
+
+use crate::coverage::{
+    decode_source, evaluate_goals, file_id, parallel_map, render_report, resolve_source, CoverageDiff, CoverageSummary, CoverageThresholds, ExclusionPreset, FileDiff,
+    GoalResult, Language, PhaseStart, PhaseStats, ReportOptions,
+};
+use crate::cache::{Fnv, FragmentCache, SourceInfo};
+use crate::checksum::sha256_hex;
+use crate::error::{Cov2HtmlError, Warning};
+use crate::executable::{disabled_lines, excluded_lines, line_counts, non_code_lines, non_instrumented_lines};
+use crate::lines::LineSet;
+use crate::minify::{minify_css, minify_js, MinifyHtml};
+use crate::progress::Progress;
+use crate::source::SourceTree;
+use crate::tree::{build_directory_tree, build_file_tree_entries, sorted_tree_children, DirectoryTree, FileTree};
+
+/// Per-file data collected for rendering, alive only while the file is
+/// rendered
+struct FileData<'a> {
+    path: &'a str,
+    source: String,
+    covered_lines: &'a LineSet,
+    covered_count: usize,
+    /// Lines with code, the denominator of its coverage
+    total_lines: usize,
+    /// All lines, the blank, comment and preprocessor ones included
+    source_lines: usize,
+    coverage_pct: f64,
+    language: Language,
+    /// Lines covered now but not in the baseline, sorted
+    gained: &'a [u32],
+    /// Lines covered in the baseline but not now, sorted
+    lost: &'a [u32],
+    /// Why the coverage is possibly stale, if it is
+    stale: Option<&'a str>,
+    /// Lines the preprocessor leaves out, shown as not compiled
+    disabled: LineSet,
+    /// Lines excluded by marker comments
+    excluded: LineSet,
+    /// Why the source could not be read, for files shown by their covered
+    /// line numbers only
+    missing: Option<&'a str>,
+}
+
+/// What is kept of a file once it was rendered
+#[derive(Clone)]
+struct FileSummary {
+    /// Shared with the file tree
+    path: Arc<str>,
+    covered_count: usize,
+    total_lines: usize,
+    language: Language,
+    /// Number of lines gained and lost against the baseline
+    gained: usize,
+    lost: usize,
+    /// Bytes of its entry in the page's file data
+    entry_len: u64,
+}
+
+/// A file read and rendered on the worker pool: the root its source was
+/// found under, its number of lines, of covered lines that count and of lines with code, why its
+/// coverage is possibly stale and what rendering it returned, or the preset that excludes it by its contents
+type ProcessedFile = Result<(String, (usize, usize, usize), Option<String>, io::Result<String>), ExclusionPreset>;
+
+/// Serialized `fileData` entries waiting for the page around them to be
+/// written: in a scratch file in the report directory or, without one, in
+/// memory until they outgrow `limit` and move to a temporary file
+enum EntrySpill {
+    Memory { entries: Vec<u8>, limit: Option<u64> },
+    File { path: String, file: io::BufWriter<File> },
+}
+
+impl EntrySpill {
+    /// Spills to a scratch file in `work_dir`, or to memory without one,
+    /// holding at most a quarter of `max_memory` there
+    fn new(work_dir: Option<&str>, max_memory: Option<u64>) -> io::Result<EntrySpill> {
+        let Some(work_dir) = work_dir else {
+            // There is no file system to spill to on the web
+            let limit = max_memory.filter(|_| !cfg!(target_family = "wasm")).map(|max_memory| max_memory / 4);
+            return Ok(EntrySpill::Memory { entries: Vec::new(), limit });
+        };
+        EntrySpill::file(format!("{}/.file-data.tmp", work_dir))
+    }
+
+    fn file(path: String) -> io::Result<EntrySpill> {
+        let file = File::options().read(true).write(true).create(true).truncate(true).open(&path)?;
+        Ok(EntrySpill::File { path, file: io::BufWriter::new(file) })
+    }
+
+    /// Moves entries held in memory past their limit to a temporary file
+    fn spill_if_over_limit(&mut self) -> io::Result<()> {
+        let EntrySpill::Memory { entries, limit: Some(limit) } = self else {
+            return Ok(());
+        };
+        if entries.len() as u64 <= *limit {
+            return Ok(());
+        }
+        static SPILLS: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir()
+            .join(format!("cov2html-{}-{}.file-data.tmp", process::id(), SPILLS.fetch_add(1, Ordering::Relaxed)))
+            .to_string_lossy()
+            .into_owned();
+        debug!("File data exceeds {} bytes in memory; spilling to {}", limit, path);
+        let entries = mem::take(entries);
+        *self = EntrySpill::file(path)?;
+        self.write_all(&entries)
+    }
+
+    /// Copies the entries written so far to `out`
+    fn copy_to(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        match self {
+            EntrySpill::Memory { entries, .. } => out.write_all(entries),
+            EntrySpill::File { file, .. } => {
+                file.flush()?;
+                let file = file.get_mut();
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(file, out)?;
+                Ok(())
+            }
+        }
+    }
+    
+    /// Copies the bytes in `range` of the entries written so far to `out`
+    fn copy_range_to(&mut self, range: Range<u64>, out: &mut dyn Write) -> io::Result<()> {
+        match self {
+            EntrySpill::Memory { entries, .. } => out.write_all(&entries[range.start as usize..range.end as usize]),
+            EntrySpill::File { file, .. } => {
+                file.flush()?;
+                let file = file.get_mut();
+                file.seek(SeekFrom::Start(range.start))?;
+                io::copy(&mut Read::by_ref(file).take(range.end - range.start), out)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Write for EntrySpill {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match self {
+            EntrySpill::Memory { entries, .. } => entries.write(buf)?,
+            EntrySpill::File { file, .. } => file.write(buf)?,
+        };
+        self.spill_if_over_limit()?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            EntrySpill::Memory { .. } => Ok(()),
+            EntrySpill::File { file, .. } => file.flush(),
+        }
+    }
+}
+
+impl Drop for EntrySpill {
+    fn drop(&mut self) {
+        if let EntrySpill::File { path, .. } = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Writes a self-contained interactive HTML report to `out` instead of a
+/// report directory, e.g. to render into memory or a socket. `split_data`,
+/// `external_assets`, `no_js`, `service_worker`, `shard_size` and
+/// `max_report_size` are ignored as they need files next to the page, and
+/// so are `renderers` and `cache_dir`.
+pub fn write_html_report(coverage_map: &HashMap<String, LineSet>, sources: &dyn SourceTree, options: &ReportOptions, out: &mut dyn Write) -> Result<CoverageSummary, Cov2HtmlError> {
+    let options = &ReportOptions { split_data: false, external_assets: false, no_js: false, service_worker: false, ..options.clone() };
+    let templates = Templates::load(options)?;
+    
+    render_report(coverage_map, sources, options, |context| {
+        let mut entries = EntrySpill::new(None, options.max_memory).map_err(Cov2HtmlError::WriteOutput)?;
+        let with_baseline = context.baseline.is_some();
+        let report = collect_report_data(
+            context.coverage_map,
+            context.sources,
+            options,
+            context.baseline,
+            &FileRenderer { render: &|data| file_data_entry(data, "", options, with_baseline), outputs: &|_, _, _| Vec::new(), cache: None },
+            &mut entries,
+            Cov2HtmlError::WriteOutput,
+        )?;
+        let start = PhaseStart::now();
+        let mut out = MinifyHtml::new(io::BufWriter::new(out), options.minify);
+        write_combined_report(&mut out, &report, &mut |out| entries.copy_to(out), "", None, options, &templates)
+            .and_then(|()| out.flush())
+            .map_err(Cov2HtmlError::WriteOutput)?;
+        log_coverage_summary(&report.summary);
+        let mut summary = report.summary;
+        summary.phases.push(PhaseStats::finish("write HTML", start));
+        
+        Ok(summary)
+    })
+}
+
+/// Writes the drag-and-drop viewer page with the WebAssembly build of the
+/// `wasm` feature embedded, for rendering reports entirely in the browser
+pub fn write_viewer_html(wasm: &[u8], out: &mut dyn Write) -> io::Result<()> {
+    if !wasm.starts_with(b"\0asm") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a WebAssembly module"));
+    }
+    
+    render_template(out, "viewer.html", VIEWER_PAGE, |name, out| match name {
+        "wasm" => out.write_all(base64_encode(wasm).as_bytes()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown placeholder {{{{{}}}}} in viewer.html", name))),
+    })
+}
+
+/// Renders a coverage diff as a standalone HTML page. When source roots
+/// are given, the text of every gained or lost line is shown.
+pub fn write_diff_html(diff: &CoverageDiff, source_roots: &[String], html_path: &str) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(html_path)?);
+    
+    file.write_all(b"<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n<title>Coverage Diff</title>\n<style>\n")?;
+    file.write_all(REPORT_CSS.as_bytes())?;
+    file.write_all(DIFF_CSS.as_bytes())?;
+    file.write_all(b"</style>\n</head>\n<body class=\"diff\">\n<div class=\"content\">\n")?;
+    
+    write!(
+        file,
+        "<div class=\"coverage-header\">\n<h2>Coverage Diff</h2>\n<div class=\"coverage-summary\"><span class=\"diff-gained\">+{}</span> lines gained, <span class=\"diff-lost\">-{}</span> lines lost in {} files</div>\n</div>\n",
+        diff.gained(), diff.lost(), diff.files.len()
+    )?;
+    
+    for file_diff in &diff.files {
+        write!(
+            file,
+            "<details class=\"diff-file\" open>\n<summary><span class=\"diff-path\">{}</span> <span class=\"diff-gained\">+{}</span> <span class=\"diff-lost\">-{}</span></summary>\n<pre class=\"source-code\">\n",
+            html_escape(&file_diff.path), file_diff.gained.len(), file_diff.lost.len()
+        )?;
+        
+        let source_lines: Vec<String> = resolve_source(source_roots, &file_diff.path)
+            .and_then(|(_, full_path)| fs::read(full_path).ok())
+            .map(|content| decode_source(content).lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        
+        // Interleave gained and lost lines in line order
+        let mut lines: Vec<(u32, bool)> = file_diff.gained.iter().map(|line| (*line, true))
+            .chain(file_diff.lost.iter().map(|line| (*line, false)))
+            .collect();
+        lines.sort_unstable();
+        
+        for (line, gained) in lines {
+            let text = (line as usize).checked_sub(1)
+                .and_then(|index| source_lines.get(index))
+                .map(String::as_str)
+                .unwrap_or("");
+            writeln!(
+                file,
+                "<div class=\"line {}\"><span class=\"line-number\">{}{}</span><span class=\"line-content\">{}</span></div>",
+                if gained { "gained" } else { "lost" },
+                if gained { '+' } else { '-' },
+                line,
+                html_escape(text)
+            )?;
+        }
+        
+        file.write_all(b"</pre>\n</details>\n")?;
+    }
+    
+    file.write_all(b"</div>\n</body>\n</html>\n")?;
+    file.flush()
+}
+
+/// Generates a single combined HTML coverage report from coverage data,
+/// highlighting the lines gained and lost against a baseline when given
+pub fn generate_combined_html(
+    coverage_map: &HashMap<String, LineSet>,
+    sources: &dyn SourceTree,
+    work_dir: &str,
+    options: &ReportOptions,
+    baseline: Option<&CoverageDiff>
+) -> Result<CoverageSummary, Cov2HtmlError> {
+    let write_error = |source| Cov2HtmlError::WriteReport { path: work_dir.to_string(), source };
+    
+    // A self-contained report too large for browsers loads its data lazily instead
+    let oversized = oversized_report(coverage_map, sources, options);
+    let lazy_options;
+    let options = match &oversized {
+        Some(warning) => {
+            warn!("{}", warning);
+            lazy_options = ReportOptions { split_data: true, external_assets: true, ..options.clone() };
+            &lazy_options
+        }
+        None => options,
+    };
+    let templates = Templates::load(options)?;
+    
+    // Files rendered from the same data by an earlier report are reused
+    let cache = match &options.cache_dir {
+        Some(cache_dir) => Some((
+            FragmentCache::open(cache_dir, options.denominator, options.kernel_config.as_ref(), &options.exclusions).map_err(|source| Cov2HtmlError::WriteReport { path: cache_dir.clone(), source })?,
+            fragment_fingerprint(options, &templates, baseline.is_some()),
+        )),
+        None => None,
+    };
+    
+    // Each file is rendered as soon as its source is read: into its static
+    // page, or into its entry of the page's file data, spilled to a scratch
+    // file until the page around it is written
+    let (report, written) = if options.no_js {
+        let files_dir = format!("{}/files", work_dir);
+        fs::create_dir_all(&files_dir).map_err(write_error)?;
+        let renderer = FileRenderer {
+            render: &|data| write_static_file_page(data, &files_dir, options, &templates).map(|()| String::new()),
+            outputs: &|path, _, _| vec![format!("{}/{}.html", files_dir, file_id(path))],
+            cache: cache.as_ref(),
+        };
+        let report = collect_report_data(coverage_map, sources, options, baseline, &renderer, &mut io::sink(), write_error)?;
+        let start = PhaseStart::now();
+        let written = write_static_report(&report, work_dir, options, &templates);
+        (report, written.map(|()| start))
+    } else {
+        let data_dir = format!("{}/data", work_dir);
+        if options.split_data {
+            fs::create_dir_all(&data_dir).map_err(write_error)?;
+        }
+        let mut entries = EntrySpill::new(Some(work_dir), options.max_memory).map_err(write_error)?;
+        let renderer = FileRenderer {
+            render: &|data| file_data_entry(data, &data_dir, options, baseline.is_some()),
+            outputs: &|path, source_hash, covered_lines| if options.split_data && options.source_url_template.is_none() {
+                vec![format!("{}/{}", data_dir, chunk_name(path, source_hash, covered_lines, options))]
+            } else {
+                Vec::new()
+            },
+            cache: cache.as_ref(),
+        };
+        let report = collect_report_data(coverage_map, sources, options, baseline, &renderer, &mut entries, write_error)?;
+        let start = PhaseStart::now();
+        // Pages whose file data would be too large for browsers are split
+        // by directory; live updates need the whole report on one page
+        let data_size: u64 = report.files.iter().map(|file| file.entry_len).sum();
+        let written = match options.shard_size {
+            Some(shard_size) if data_size > shard_size && !options.live => {
+                info!("File data of {} bytes exceeds the shard size of {} bytes; writing a page per directory", data_size, shard_size);
+                write_sharded_report(&report, &mut entries, work_dir, options, &templates)
+            }
+            _ => File::create(format!("{}/coverage_report.html", work_dir)).and_then(|file| {
+                let mut html_file = MinifyHtml::new(io::BufWriter::new(file), options.minify);
+                write_combined_report(&mut html_file, &report, &mut |out| entries.copy_to(out), work_dir, None, options, &templates)?;
+                html_file.flush()
+            }),
+        };
+        if written.is_ok() {
+            log_coverage_summary(&report.summary);
+        }
+        (report, written.map(|()| start))
+    };
+    let start = written.map_err(write_error)?;
+    if let (Some((cache, _)), Some(cache_dir)) = (cache, &options.cache_dir) {
+        let reused = cache.finish().map_err(|source| Cov2HtmlError::WriteReport { path: cache_dir.clone(), source })?;
+        info!("Reused {} of {} rendered files from the cache", reused, report.files.len());
+    }
+    let mut summary = report.summary;
+    summary.phases.push(PhaseStats::finish("write HTML", start));
+    if let Some(warning) = oversized {
+        summary.warnings.insert(0, warning);
+    }
+    
+    Ok(summary)
+}
+
+/// Returns a warning when `max_report_size` is set and a self-contained
+/// report, estimated by the size of the covered sources, would exceed it
+fn oversized_report(coverage_map: &HashMap<String, LineSet>, sources: &dyn SourceTree, options: &ReportOptions) -> Option<Warning> {
+    let limit = options.max_report_size?;
+    // Split, static and code browser reports do not embed the sources in the page
+    if options.split_data || options.no_js || options.source_url_template.is_some() {
+        return None;
+    }
+    
+    let paths: Vec<&String> = coverage_map.keys().collect();
+    let size = parallel_map(&paths, options.jobs, |file_path| {
+        sources.find(file_path).and_then(|(_, full_path)| sources.size(&full_path)).unwrap_or(0)
+    }).into_iter().sum::<u64>();
+    // Sources grow by about half as JSON strings, one per line
+    let size = size + size / 2;
+    (size > limit).then_some(Warning::OversizedReport { size, limit })
+}
+
+/// Hash of what rendering a file depends on besides the file itself, so a
+/// cached fragment is only reused for the same options and templates
+fn fragment_fingerprint(options: &ReportOptions, templates: &Templates, with_baseline: bool) -> Fnv {
+    let mut hasher = Fnv::default();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    (options.no_js, options.split_data, options.external_assets, options.minify, options.wrap_lines, options.show_whitespace, with_baseline).hash(&mut hasher);
+    (options.thresholds.good.to_bits(), options.thresholds.medium.to_bits(), options.tab_width).hash(&mut hasher);
+    (&options.title, &options.source_url_template, &options.source_rev, options.denominator.method(), &options.kernel_config).hash(&mut hasher);
+    options.max_line_length.hash(&mut hasher);
+    (&templates.report_page, &templates.report_css, &templates.report_js).hash(&mut hasher);
+    hasher
+}
+
+/// Cache key of a file's fragment: the fingerprint, the file's path and
+/// source, its covered, gained and lost lines, whether it is stale and the
+/// lines its line table has instructions on, if it is counted over them
+#[allow(clippy::too_many_arguments)]
+fn fragment_key(fingerprint: &Fnv, path: &str, source: &SourceInfo, covered_lines: &LineSet, gained: &[u32], lost: &[u32], stale: bool, instrumented: Option<&LineSet>) -> u64 {
+    let mut hasher = fingerprint.clone();
+    (path, source.hash, source.lines).hash(&mut hasher);
+    for range in covered_lines.ranges() {
+        range.into_inner().hash(&mut hasher);
+    }
+    (gained, lost, stale).hash(&mut hasher);
+    instrumented.is_some().hash(&mut hasher);
+    for range in instrumented.iter().flat_map(|instrumented| instrumented.ranges()) {
+        range.into_inner().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// How each file of a report is rendered: `render` returns what is written
+/// for it to the report data and `outputs` lists the files it writes
+/// besides, given the hash of the source and its covered lines. With a cache and its fingerprint, files rendered from the same
+/// data by an earlier report are reused without reading their sources.
+struct FileRenderer<'a> {
+    render: &'a (dyn Fn(&FileData) -> io::Result<String> + Sync),
+    outputs: &'a (dyn Fn(&str, u64, &LineSet) -> Vec<String> + Sync),
+    cache: Option<&'a (FragmentCache, Fnv)>,
+}
+
+/// Per-file data, trees and totals of a report, ready to be written
+struct ReportData {
+    files: Vec<FileSummary>,
+    /// Subdirectories and files of every directory
+    tree: DirectoryTree,
+    summary: CoverageSummary,
+}
+
+/// Reads the sources of the covered files a batch at a time, renders each
+/// file with `renderer` on the worker pool and writes what it returns to `out`
+/// in file order, so only a batch of sources is held at once. Returns
+/// everything else the HTML writers need.
+fn collect_report_data(
+    coverage_map: &HashMap<String, LineSet>,
+    sources: &dyn SourceTree,
+    options: &ReportOptions,
+    baseline: Option<&CoverageDiff>,
+    renderer: &FileRenderer,
+    out: &mut dyn Write,
+    write_error: impl Fn(io::Error) -> Cov2HtmlError,
+) -> Result<ReportData, Cov2HtmlError> {
+    // Create a file tree structure
+    let mut file_tree = FileTree::new();
+    let mut total_covered = 0;
+    let mut total_lines = 0;
+    let mut source_lines = 0;
+    let mut missing_files = 0;
+    let mut missing_sources = BTreeMap::new();
+    // Views of the files with missing sources, written after all the
+    // counted files so the entries of those stay in file order
+    let mut missing_entries = Vec::new();
+    let mut mismatched_files = 0;
+    let mut warnings = Vec::new();
+    
+    // What is kept of each rendered file
+    let mut files: Vec<FileSummary> = Vec::new();
+    
+    // Gained and lost lines per file when comparing against a baseline
+    let baseline_files: HashMap<&str, &FileDiff> = baseline
+        .map(|diff| diff.files.iter().map(|file_diff| (file_diff.path.as_str(), file_diff)).collect())
+        .unwrap_or_default();
+    
+    let mut phases = Vec::new();
+    let start = PhaseStart::now();
+    // Files are taken in path order so the same coverage always gives the same report
+    let mut entries: Vec<(&String, &LineSet)> = Vec::new();
+    let mut paths: Vec<(&String, &LineSet)> = coverage_map.iter().collect();
+    paths.sort_unstable_by_key(|(file_path, _)| *file_path);
+    for (file_path, covered_lines) in paths {
+        match options.exclusions.iter().find(|preset| preset.excludes_path(file_path)) {
+            Some(preset) => {
+                debug!("Excluded by {}: {}", preset.name(), file_path);
+                warnings.push(Warning::SkippedFile { path: file_path.clone(), reason: format!("excluded by {}", preset.name()) });
+            }
+            None => entries.push((file_path, covered_lines)),
+        }
+    }
+    
+    // Reads, counts and renders one file; problems are reported below, in order
+    let process_file = |file_path: &str, covered_lines: &LineSet| -> Result<ProcessedFile, Warning> {
+        // Skip files that don't exist under any source root
+        let (source_root, full_path) = sources.find(file_path)
+            .ok_or_else(|| Warning::MissingSource { path: file_path.to_string() })?;
+        let file_diff = baseline_files.get(file_path);
+        let gained = file_diff.map_or(&[][..], |diff| diff.gained.as_slice());
+        let lost = file_diff.map_or(&[][..], |diff| diff.lost.as_slice());
+        
+        // An unchanged source whose fragment is cached is not even read
+        let modified = (renderer.cache.is_some() || options.coverage_modified.is_some()).then(|| sources.modified(&full_path)).flatten();
+        // Files in the line tables are counted over their lines with
+        // instructions, which the cached fragment then depends on too
+        let instrumented = options.denominator.instrumented(&options.line_tables, file_path);
+        let key = |fingerprint: &Fnv, source: &SourceInfo, stale: bool| {
+            fragment_key(fingerprint, file_path, source, covered_lines, gained, lost, stale, instrumented)
+        };
+        let counts = |source: &SourceInfo| match instrumented {
+            Some(instrumented) => line_counts(source.lines, &non_instrumented_lines(source.lines, instrumented).union(&source.uncounted), &source.uncounted, covered_lines),
+            None => source.line_counts(covered_lines),
+        };
+        
+        // Coverage of lines past the end, or of a source edited since it was
+        // recorded, is probably shown on the wrong lines
+        let stale = |total_lines: usize| {
+            if let Some(last) = covered_lines.ranges().last().map(|range| *range.end())
+                && last as usize > total_lines
+            {
+                return Some(format!("covers line {} of a file with {} lines", last, total_lines));
+            }
+            modified.zip(options.coverage_modified)
+                .is_some_and(|(modified, recorded)| modified > recorded)
+                .then(|| "source changed after the coverage was recorded".to_string())
+        };
+        // Sources with a recorded checksum are always read to verify it
+        let expected = options.checksums.get(file_path);
+        if let Some((cache, fingerprint)) = renderer.cache
+            && expected.is_none()
+            && let Some(source) = cache.source(&full_path, modified)
+        {
+            if let Some(preset) = source.excluded {
+                return Ok(Err(preset));
+            }
+            let stale = stale(source.lines);
+            if let Some(text) = cache.reuse(key(fingerprint, &source, stale.is_some()), &(renderer.outputs)(file_path, source.hash, covered_lines)) {
+                let (covered, total) = counts(&source);
+                return Ok(Ok((source_root, (source.lines, covered, total), stale, Ok(text))));
+            }
+        }
+        
+        let content = sources.read(&full_path)
+            .map_err(|e| Warning::UnreadableSource { path: full_path.clone(), message: e.to_string() })?;
+        let mismatch = expected.map(|expected| (expected, sha256_hex(&content))).filter(|(expected, actual)| *expected != actual);
+        if options.strict && let Some((expected, actual)) = mismatch {
+            return Err(Warning::ChecksumMismatch { path: file_path.to_string(), expected: expected.clone(), actual });
+        }
+        let content = decode_source(content);
+        let language = Language::detect(file_path);
+        let source = renderer.cache.map(|(cache, _)| {
+            let source = SourceInfo::new(&content, language, options.denominator, options.kernel_config.as_ref(), &options.exclusions);
+            cache.add_source(&full_path, modified, source.clone());
+            source
+        });
+        let excluded = match &source {
+            Some(source) => source.excluded,
+            None => options.exclusions.iter().copied().find(|preset| preset.excludes_source(content.as_bytes())),
+        };
+        if let Some(preset) = excluded {
+            return Ok(Err(preset));
+        }
+        
+        // Only lines with code count toward the total, and never those
+        // excluded by marker comments or disabled by the preprocessor, even
+        // when covered
+        let excluded = excluded_lines(content.as_bytes());
+        let disabled = disabled_lines(content.as_bytes(), language, options.kernel_config.as_ref());
+        let (file_source_lines, (file_covered_lines, file_total_lines)) = match &source {
+            Some(source) => (source.lines, counts(source)),
+            None => {
+                let lines = content.lines().count();
+                let uncounted = disabled.union(&excluded);
+                let non_code = match instrumented {
+                    Some(instrumented) => non_instrumented_lines(lines, instrumented).union(&uncounted),
+                    None => non_code_lines(content.as_bytes(), language, options.denominator, options.kernel_config.as_ref()),
+                };
+                (lines, line_counts(lines, &non_code, &uncounted, covered_lines))
+            }
+        };
+        let stale = match mismatch {
+            Some(_) => Some("source does not match the checksum recorded with the coverage".to_string()),
+            None => stale(file_source_lines),
+        };
+        let coverage_pct = if file_total_lines > 0 { 
+            (file_covered_lines as f64 / file_total_lines as f64) * 100.0 
+        } else { 
+            0.0 
+        };
+        let data = FileData {
+            path: file_path,
+            // Sources are only needed for counting when linking to a code browser
+            source: if options.source_url_template.is_some() { String::new() } else { content },
+            covered_lines,
+            covered_count: file_covered_lines,
+            total_lines: file_total_lines,
+            source_lines: file_source_lines,
+            coverage_pct,
+            language,
+            gained,
+            lost,
+            stale: stale.as_deref(),
+            disabled,
+            excluded,
+            missing: None,
+        };
+        let rendered = match (renderer.cache, &source) {
+            (Some((cache, fingerprint)), Some(source)) => cache.render(key(fingerprint, source, stale.is_some()), &(renderer.outputs)(file_path, source.hash, covered_lines), || (renderer.render)(&data)),
+            _ => (renderer.render)(&data),
+        };
+        Ok(Ok((source_root, (file_source_lines, file_covered_lines, file_total_lines), stale, rendered)))
+    };
+    
+    let processed = AtomicUsize::new(0);
+    let mut next = 0;
+    while next < entries.len() {
+        let batch = &entries[next..entries.len().min(next + FILE_BATCH)];
+        // With a memory budget the batch also ends before its sources outgrow
+        // their share, though it always takes at least one file
+        let batch = match options.max_memory {
+            Some(max_memory) => {
+                let mut bytes = 0;
+                let fits = batch.iter().position(|(file_path, _)| {
+                    bytes += sources.find(file_path).and_then(|(_, full_path)| sources.size(&full_path)).unwrap_or(0);
+                    bytes > max_memory / 4
+                });
+                &batch[..fits.map_or(batch.len(), |fits| fits.max(1))]
+            }
+            None => batch,
+        };
+        next += batch.len();
+        
+        let results = parallel_map(batch, options.jobs, |(file_path, covered_lines)| {
+            if options.is_cancelled() {
+                return None;
+            }
+            let result = process_file(file_path, covered_lines);
+            let done = processed.fetch_add(1, Ordering::Relaxed) + 1;
+            options.progress.report(Progress::FileProcessed { path: file_path.to_string(), done, total: entries.len() });
+            Some(result)
+        });
+        let results = results.into_iter().collect::<Option<Vec<_>>>().ok_or(Cov2HtmlError::Cancelled)?;
+        
+        for ((file_path, covered_lines), result) in batch.iter().zip(results) {
+            let (source_root, (file_source_lines, file_covered_lines, file_total_lines), stale, rendered) = match result {
+                Ok(Ok(result)) => result,
+                Ok(Err(preset)) => {
+                    debug!("Excluded by {}: {}", preset.name(), file_path);
+                    warnings.push(Warning::SkippedFile { path: file_path.to_string(), reason: format!("excluded by {}", preset.name()) });
+                    continue;
+                }
+                Err(warning) => {
+                    if options.strict {
+                        error!("{}", warning);
+                    } else {
+                        warn!("{}", warning);
+                    }
+                    match warning {
+                        Warning::ChecksumMismatch { .. } => mismatched_files += 1,
+                        _ => {
+                            missing_files += 1;
+                            missing_sources.insert(file_path.to_string(), covered_lines.len());
+                            // The covered lines still get a view, numbered
+                            // against a placeholder, until the path is fixed
+                            let reason = warning.to_string();
+                            let data = FileData {
+                                path: file_path,
+                                source: String::new(),
+                                covered_lines,
+                                covered_count: covered_lines.len(),
+                                total_lines: 0,
+                                source_lines: 0,
+                                coverage_pct: 0.0,
+                                language: Language::detect(file_path),
+                                gained: &[],
+                                lost: &[],
+                                stale: None,
+                                disabled: LineSet::new(),
+                                excluded: LineSet::new(),
+                                missing: Some(&reason),
+                            };
+                            missing_entries.push((renderer.render)(&data).map_err(&write_error)?);
+                        }
+                    }
+                    warnings.push(warning);
+                    continue;
+                }
+            };
+            let rendered = rendered.map_err(&write_error)?;
+            out.write_all(rendered.as_bytes()).map_err(&write_error)?;
+            if let Some(reason) = stale {
+                let warning = Warning::StaleCoverage { path: file_path.to_string(), reason };
+                warn!("{}", warning);
+                warnings.push(warning);
+            }
+            
+            // Update global stats
+            total_covered += file_covered_lines;
+            total_lines += file_total_lines;
+            source_lines += file_source_lines;
+            
+            debug!("Processing file: {} from {} ({} of {} lines covered)", 
+                file_path, source_root, file_covered_lines, file_total_lines);
+            
+            // Build file tree entries
+            let path = build_file_tree_entries(file_path, file_covered_lines, file_total_lines, &mut file_tree);
+            
+            let file_diff = baseline_files.get(file_path.as_str());
+            files.push(FileSummary {
+                path,
+                covered_count: file_covered_lines,
+                total_lines: file_total_lines,
+                language: Language::detect(file_path),
+                gained: file_diff.map_or(0, |diff| diff.gained.len()),
+                lost: file_diff.map_or(0, |diff| diff.lost.len()),
+                entry_len: rendered.len() as u64,
+            });
+        }
+    }
+    for entry in &missing_entries {
+        out.write_all(entry.as_bytes()).map_err(&write_error)?;
+    }
+    phases.push(PhaseStats::finish("process files", start));
+    
+    if options.strict && missing_files > 0 {
+        return Err(Cov2HtmlError::MissingSources { count: missing_files });
+    }
+    if mismatched_files > 0 {
+        return Err(Cov2HtmlError::ChecksumMismatch { count: mismatched_files });
+    }
+    
+    // Organize files into a proper tree structure
+    let start = PhaseStart::now();
+    let mut tree = DirectoryTree::new();
+    build_directory_tree(&file_tree, &mut tree);
+    phases.push(PhaseStats::finish("build tree", start));
+    
+    let summary = CoverageSummary {
+        files: files.len(),
+        missing_files,
+        missing_sources,
+        covered_lines: total_covered,
+        total_lines,
+        source_lines,
+        file_totals: files.iter()
+            .map(|file| (file.path.to_string(), (file.covered_count, file.total_lines)))
+            .collect(),
+        phases,
+        warnings,
+        outputs: Vec::new(),
+    };
+    
+    Ok(ReportData { files, tree, summary })
+}
+
+/// Writes the interactive report page to `html_file` and, depending on the
+/// options, its external assets and per-file data chunks into `work_dir`.
+/// `file_data` writes the page's `fileData` entries. The page of a shard
+/// links back to the index and names its data script after the shard.
+fn write_combined_report(
+    html_file: &mut dyn Write,
+    report: &ReportData,
+    file_data: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+    work_dir: &str,
+    shard: Option<&str>,
+    options: &ReportOptions,
+    templates: &Templates
+) -> io::Result<()> {
+    let ReportData { files, tree, summary, .. } = report;
+    
+    if options.external_assets {
+        fs::write(format!("{}/{}", work_dir, templates.css_name), templates.report_css.as_bytes())?;
+        fs::write(format!("{}/{}", work_dir, templates.js_name), templates.report_js.as_bytes())?;
+    }
+    if registers_service_worker(options, shard) {
+        let script = if options.minify { Cow::Owned(minify_js(SERVICE_WORKER)) } else { Cow::Borrowed(SERVICE_WORKER) };
+        fs::write(format!("{}/sw.js", work_dir), script.as_bytes())?;
+    }
+    
+    render_template(html_file, "report.html", &templates.report_page, |name, out| match name {
+        "title" => out.write_all(html_escape(&options.title).as_bytes()),
+        // Styles, inline or linked
+        "head" => write_combined_html_head(out, options, templates),
+        "overall" => {
+            write!(
+                out,
+                "<span class=\"{}\">{:.1}%</span> ({} of {} lines)",
+                get_coverage_class(summary.coverage_pct(), &options.thresholds),
+                summary.coverage_pct(),
+                summary.covered_lines,
+                summary.total_lines
+            )?;
+            match shard {
+                Some(_) => out.write_all(b" <a href=\"coverage_report.html\">All directories</a>"),
+                None => Ok(()),
+            }
+        }
+        "stats" => {
+            write_language_stats(out, files, &options.thresholds)?;
+            write_goal_stats(out, &evaluate_goals(&options.goals, &summary.file_totals))?;
+            write_missing_sources(out, &summary.missing_sources, &|file_id| Some(format!("href=\"#\" data-file=\"{}\"", html_escape(file_id))))
+        }
+        // Containers for each file's content (initially hidden), the files
+        // with missing sources included
+        "files" => {
+            let missing = summary.missing_sources.keys().map(|file_path| &file_path[..]);
+            for file_path in files.iter().map(|file| &file.path[..]).chain(missing) {
+                let file_id = file_id(file_path);
+                writeln!(out, "<div id=\"file_{}\" class=\"file-content\"></div>", html_escape(&file_id))?;
+            }
+            Ok(())
+        }
+        "scripts" => write_report_scripts(out, file_data, tree, work_dir, shard, options, templates),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown placeholder {{{{{}}}}} in report.html", name))),
+    })
+}
+
+/// Writes an interactive page for each directory below the directory all
+/// files share, with its files' entries copied from `entries`, and a static
+/// `coverage_report.html` index linking them
+fn write_sharded_report(report: &ReportData, entries: &mut EntrySpill, work_dir: &str, options: &ReportOptions, templates: &Templates) -> io::Result<()> {
+    let ReportData { files, summary, .. } = report;
+    
+    // Pages are split below the directory shared by all files, so a tree
+    // under a single top-level directory is still split
+    let mut common = files.first().map_or("", |file| file.path.rsplit_once('/').map_or("", |(dir, _)| dir));
+    for file in files {
+        while !common.is_empty() && !file.path.strip_prefix(common).is_some_and(|rest| rest.starts_with('/')) {
+            common = common.rsplit_once('/').map_or("", |(dir, _)| dir);
+        }
+    }
+    let prefix_len = if common.is_empty() { 0 } else { common.len() + 1 };
+    
+    // Files of each shard and the byte ranges of their entries, by the
+    // directory below the shared one; files directly in it share a page
+    let mut shards: BTreeMap<&str, (Vec<FileSummary>, Vec<Range<u64>>)> = BTreeMap::new();
+    let mut offset = 0;
+    for file in files {
+        let directory = file.path[prefix_len..].split_once('/').map_or("", |(dir, _)| dir);
+        let (shard_files, ranges) = shards.entry(directory).or_default();
+        shard_files.push(file.clone());
+        ranges.push(offset..offset + file.entry_len);
+        offset += file.entry_len;
+    }
+    
+    let mut pages = Vec::new();
+    for (directory, (shard_files, ranges)) in shards {
+        let label = match (common, directory) {
+            ("", "") => "(top level)".to_string(),
+            (common, "") => common.to_string(),
+            ("", directory) => directory.to_string(),
+            (common, directory) => format!("{}/{}", common, directory),
+        };
+        let slug = if directory.is_empty() { "_files".to_string() } else { directory.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_") };
+        
+        let mut file_tree = FileTree::new();
+        for file in &shard_files {
+            build_file_tree_entries(&file.path, file.covered_count, file.total_lines, &mut file_tree);
+        }
+        let mut tree = DirectoryTree::new();
+        build_directory_tree(&file_tree, &mut tree);
+        let shard_summary = CoverageSummary {
+            files: shard_files.len(),
+            covered_lines: shard_files.iter().map(|file| file.covered_count).sum(),
+            total_lines: shard_files.iter().map(|file| file.total_lines).sum(),
+            ..CoverageSummary::default()
+        };
+        let shard_report = ReportData { files: shard_files, tree, summary: shard_summary };
+        
+        // Goals are shown on the index, where the totals of every file are known
+        let shard_options = ReportOptions { title: format!("{}: {}", options.title, label), goals: Vec::new(), ..options.clone() };
+        let page = format!("coverage_report-{}.html", slug);
+        let mut html_file = MinifyHtml::new(io::BufWriter::new(File::create(format!("{}/{}", work_dir, page))?), options.minify);
+        let mut file_data = |out: &mut dyn Write| ranges.iter().try_for_each(|range| entries.copy_range_to(range.clone(), out));
+        write_combined_report(&mut html_file, &shard_report, &mut file_data, work_dir, Some(&slug), &shard_options, templates)?;
+        html_file.flush()?;
+        
+        pages.push((label, page, shard_report.summary));
+    }
+    
+    // Index page with the totals of every page
+    let mut index_file = MinifyHtml::new(io::BufWriter::new(File::create(format!("{}/coverage_report.html", work_dir))?), options.minify);
+    write_static_html_head(&mut index_file, &options.title, "", options, templates)?;
+    index_file.write_all(format!(
+        "<div id=\"content\" class=\"content\">\n<div id=\"welcome\" class=\"welcome\">\n<h1>{}</h1>\n<div class=\"coverage-summary\">Overall: <span class=\"{}\">{:.1}%</span> ({} of {} lines)</div>\n<p>The report is split into a page per directory to keep each page small enough for a browser.</p>\n",
+        html_escape(&options.title),
+        get_coverage_class(summary.coverage_pct(), &options.thresholds),
+        summary.coverage_pct(),
+        summary.covered_lines,
+        summary.total_lines
+    ).as_bytes())?;
+    index_file.write_all(b"<table class=\"language-stats shard-index\">\n<tr><th>Directory</th><th>Files</th><th>Lines</th><th>Coverage</th></tr>\n")?;
+    for (label, page, shard_summary) in &pages {
+        index_file.write_all(format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{} of {}</td><td class=\"{}\">{:.1}%</td></tr>\n",
+            html_escape(page), html_escape(label), shard_summary.files, shard_summary.covered_lines, shard_summary.total_lines,
+            get_coverage_class(shard_summary.coverage_pct(), &options.thresholds), shard_summary.coverage_pct()
+        ).as_bytes())?;
+    }
+    index_file.write_all(b"</table>\n")?;
+    write_language_stats(&mut index_file, files, &options.thresholds)?;
+    write_goal_stats(&mut index_file, &evaluate_goals(&options.goals, &summary.file_totals))?;
+    write_missing_sources(&mut index_file, &summary.missing_sources, &|_| None)?;
+    index_file.write_all(b"</div>\n</div>\n</body>\n</html>\n")?;
+    index_file.flush()
+}
+
+/// Logs the totals of a written report
+fn log_coverage_summary(summary: &CoverageSummary) {
+    info!("Coverage summary: {} of {} lines covered ({:.2}%)", 
+        summary.covered_lines, summary.total_lines, summary.coverage_pct());
+}
+
+/// Number of files read and rendered on the worker pool before their
+/// output is written and their sources dropped
+const FILE_BATCH: usize = 256;
+
+/// Whether a page registers the service worker: pages that load more files
+/// than themselves keep them for offline use
+fn registers_service_worker(options: &ReportOptions, shard: Option<&str>) -> bool {
+    options.service_worker || options.split_data || shard.is_some()
+}
+
+/// Writes the report configuration, tree and file data followed by the
+/// report script, inline or as a data script named after the shard and its
+/// contents next to the page
+fn write_report_scripts(
+    html_file: &mut dyn Write,
+    file_data: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+    tree: &DirectoryTree,
+    work_dir: &str,
+    shard: Option<&str>,
+    options: &ReportOptions,
+    templates: &Templates
+) -> io::Result<()> {
+    let data_script = shard.map_or_else(|| "report-data".to_string(), |shard| format!("report-data-{}", shard));
+    // Write JavaScript code for functions and data, either inline or into
+    // a separate script when inline code is not allowed
+    let partial_path = format!("{}/{}.js.tmp", work_dir, data_script);
+    let mut data_script_file = if options.external_assets {
+        Some(HashWriter::new(io::BufWriter::new(File::create(&partial_path)?)))
+    } else {
+        html_file.write_all(b"<script>\n")?;
+        None
+    };
+    let script_file: &mut dyn Write = match data_script_file.as_mut() {
+        Some(file) => file,
+        None => &mut *html_file,
+    };
+    
+    // File data objects
+    script_file.write_all(format!(
+        "const reportConfig = {{\n  wrapLines: {},\n  tabWidth: {},\n  thresholds: {{ good: {}, medium: {} }},\n  sourceUrlTemplate: {},\n  sourceRev: {},\n  showWhitespace: {},\n  maxLineLength: {},\n  live: {},\n  serviceWorker: {}\n}};\n\n",
+        options.wrap_lines, options.tab_width, options.thresholds.good, options.thresholds.medium,
+        script_json(&json!(options.source_url_template)),
+        script_json(&json!(options.source_rev)),
+        options.show_whitespace,
+        options.max_line_length.map(|n| n.to_string()).unwrap_or_else(|| "null".to_string()),
+        options.live,
+        registers_service_worker(options, shard)
+    ).as_bytes())?;
+    
+    // Directory tree for the sidebar
+    script_file.write_all(b"const treeData = ")?;
+    write_tree_data(tree, "", script_file)?;
+    script_file.write_all(b";\n\n")?;
+    
+    script_file.write_all(b"const fileData = {\n")?;
+    
+    file_data(script_file)?;
+    script_file.write_all(b"};\n\n")?;
+    
+    // Write JavaScript functions
+    if let Some(mut data_script_file) = data_script_file {
+        data_script_file.flush()?;
+        let data_script_name = format!("{}.{:016x}.js", data_script, data_script_file.hasher.finish());
+        drop(data_script_file);
+        fs::rename(&partial_path, format!("{}/{}", work_dir, data_script_name))?;
+        html_file.write_all(format!("<script src=\"{}\"></script>\n<script src=\"{}\"></script>\n", data_script_name, templates.js_name).as_bytes())?;
+    } else {
+        html_file.write_all(templates.report_js.as_bytes())?;
+        html_file.write_all(b"</script>\n")?;
+    }
+    
+    Ok(())
+}
+
+/// Serializes the `fileData` entry of a file, writing its data chunk when
+/// the data is split
+fn file_data_entry(data: &FileData, data_dir: &str, options: &ReportOptions, with_baseline: bool) -> io::Result<String> {
+    let FileData { path: file_path, source: source_content, covered_lines, covered_count, total_lines, source_lines, coverage_pct, language, gained, lost, stale, disabled, excluded, missing } = data;
+    let file_id = file_id(file_path);
+    
+    // Covered lines in line order
+    let sorted_lines: Vec<u32> = covered_lines.iter().collect();
+    
+    let mut entry = json!({
+        "path": file_path,
+        "language": language.id(),
+        "totalLines": total_lines,
+        "sourceLines": source_lines,
+        "coveredCount": covered_count,
+        "coveragePct": (coverage_pct * 10.0).round() / 10.0,
+    });
+    
+    // Lines gained and lost against the baseline, kept in the page so the tree can show deltas
+    if with_baseline {
+        entry["gained"] = json!(gained);
+        entry["lost"] = json!(lost);
+    }
+    if let Some(reason) = stale {
+        entry["stale"] = json!(reason);
+    }
+    if let Some(reason) = missing {
+        entry["missing"] = json!(reason);
+    }
+    // Not compiled lines as [start, end] ranges, which whole `#if 0` blocks keep short
+    if !disabled.is_empty() {
+        entry["disabled"] = json!(disabled.ranges().map(|range| [*range.start(), *range.end()]).collect::<Vec<_>>());
+    }
+    if !excluded.is_empty() {
+        entry["excluded"] = json!(excluded.ranges().map(|range| [*range.start(), *range.end()]).collect::<Vec<_>>());
+    }
+    
+    // Source lines are not HTML-escaped here since the script escapes them
+    // before using innerHTML
+    let source_lines: Vec<&str> = source_content.lines().collect();
+    if options.source_url_template.is_some() || missing.is_some() {
+        // Only line numbers are stored; the source lives in the external code
+        // browser, or could not be read at all
+        entry["covered"] = json!(sorted_lines);
+    } else if options.split_data {
+        // Only the summary stays in the page; the lines are fetched when the file is opened
+        let mut source_hash = Fnv::default();
+        source_hash.write(source_content.as_bytes());
+        let chunk = chunk_name(file_path, source_hash.finish(), covered_lines, options);
+        fs::write(format!("{}/{}", data_dir, chunk), format!("{}\n", json!({ "covered": sorted_lines, "source": source_lines })))?;
+        entry["chunk"] = json!(format!("data/{}", chunk));
+    } else {
+        entry["covered"] = json!(sorted_lines);
+        entry["source"] = json!(source_lines);
+    }
+    
+    Ok(format!("  {}: {},\n", script_json(&json!(file_id)), script_json(&entry)))
+}
+
+/// File name of the data chunk of a file. With external assets it carries a
+/// hash of the source and covered lines the chunk is made of, so it
+/// changes whenever the chunk does and can be cached for good.
+fn chunk_name(file_path: &str, source_hash: u64, covered_lines: &LineSet, options: &ReportOptions) -> String {
+    let file_id = file_id(file_path);
+    if !options.external_assets {
+        return format!("{}.json", file_id);
+    }
+    let mut hasher = Fnv::default();
+    source_hash.hash(&mut hasher);
+    for range in covered_lines.ranges() {
+        range.into_inner().hash(&mut hasher);
+    }
+    format!("{}.{:016x}.json", file_id, hasher.finish())
+}
+
+/// Name of an external asset with `contents`: `stem.<hash>.ext`, after a
+/// hash of the contents so browsers and CDNs can cache it for good
+fn asset_name(stem: &str, ext: &str, contents: &str) -> String {
+    let mut hasher = Fnv::default();
+    hasher.write(contents.as_bytes());
+    format!("{}.{:016x}.{}", stem, hasher.finish(), ext)
+}
+
+/// Passes writes on to `out` while hashing them
+struct HashWriter<W: Write> {
+    out: W,
+    hasher: Fnv,
+}
+
+impl<W: Write> HashWriter<W> {
+    fn new(out: W) -> HashWriter<W> {
+        HashWriter { out, hasher: Fnv::default() }
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.hasher.write(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Recursively writes the directory tree as a JavaScript array for the virtualized sidebar
+fn write_tree_data(
+    tree: &DirectoryTree, 
+    current_path: &str, 
+    html_file: &mut dyn Write
+) -> io::Result<()> {
+    html_file.write_all(b"[")?;
+    
+    if let Some((dirs, files)) = sorted_tree_children(tree, current_path) {
+        let mut first = true;
+        
+        // Write directories
+        for dir in dirs {
+            let full_path = if current_path.is_empty() {
+                dir.to_string()
+            } else {
+                format!("{}/{}", current_path, dir)
+            };
+            
+            html_file.write_all(format!(
+                "{}{{name: {}, path: {}, children: ",
+                if first { "" } else { "," }, script_json(&json!(dir)), script_json(&json!(full_path))
+            ).as_bytes())?;
+            first = false;
+            
+            // Recursively write children
+            write_tree_data(tree, &full_path, html_file)?;
+            
+            html_file.write_all(b"}")?;
+        }
+        
+        // Write files
+        for (name, covered, total) in files {
+            let path = if current_path.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", current_path, name)
+            };
+            
+            let file_id = file_id(&path);
+            
+            html_file.write_all(format!(
+                "{}\n{{name: {}, path: {}, id: {}, covered: {}, total: {}}}",
+                if first { "" } else { "," }, script_json(&json!(name)), script_json(&json!(path)), script_json(&json!(file_id)), covered, total
+            ).as_bytes())?;
+            first = false;
+        }
+    }
+    
+    html_file.write_all(b"]")?;
+    Ok(())
+}
+
+/// Writes the index of a JavaScript-free report with the directory tree;
+/// the page of each source file is written as it is read
+fn write_static_report(report: &ReportData, work_dir: &str, options: &ReportOptions, templates: &Templates) -> io::Result<()> {
+    let ReportData { files, tree, summary, .. } = report;
+    
+    if options.external_assets {
+        fs::write(format!("{}/{}", work_dir, templates.css_name), static_css(&templates.report_css, options))?;
+    }
+    
+    // Index page with the directory tree
+    let index_path = format!("{}/coverage_report.html", work_dir);
+    let mut index_file = MinifyHtml::new(io::BufWriter::new(File::create(&index_path)?), options.minify);
+    write_static_html_head(&mut index_file, &options.title, "", options, templates)?;
+    
+    let overall_coverage = summary.coverage_pct();
+    
+    index_file.write_all(format!(
+        "<div id=\"sidebar\" class=\"sidebar\">\n<div class=\"coverage-header\">\n<h2>{}</h2>\n<div class=\"coverage-summary\">Overall: <span class=\"{}\">{:.1}%</span> ({} of {} lines)</div>\n</div>\n",
+        html_escape(&options.title),
+        get_coverage_class(overall_coverage, &options.thresholds),
+        overall_coverage,
+        summary.covered_lines,
+        summary.total_lines
+    ).as_bytes())?;
+    
+    let deltas: HashMap<&str, (usize, usize)> = files.iter()
+        .map(|file| (&file.path[..], (file.gained, file.lost)))
+        .collect();
+    render_static_tree(tree, "", &mut index_file, 0, &options.thresholds, &deltas)?;
+    
+    index_file.write_all(format!(
+        "</div>\n<div id=\"content\" class=\"content\">\n<div id=\"welcome\" class=\"welcome\">\n<h1>{}</h1>\n<p>Select a file from the sidebar to view coverage details.</p>\n<p>Generated with FFFuzzer coverage tool</p>\n",
+        html_escape(&options.title)
+    ).as_bytes())?;
+    write_language_stats(&mut index_file, files, &options.thresholds)?;
+    write_goal_stats(&mut index_file, &evaluate_goals(&options.goals, &summary.file_totals))?;
+    write_missing_sources(&mut index_file, &summary.missing_sources, &|file_id| Some(format!("href=\"files/{}.html\"", html_escape(file_id))))?;
+    index_file.write_all(b"</div>\n</div>\n</body>\n</html>\n")?;
+    index_file.flush()?;
+    
+    log_coverage_summary(summary);
+    Ok(())
+}
+
+/// Writes the static page showing one source file
+fn write_static_file_page(data: &FileData, files_dir: &str, options: &ReportOptions, templates: &Templates) -> io::Result<()> {
+    let FileData { path: file_path, source: source_content, covered_lines, covered_count, total_lines, source_lines, coverage_pct, language, gained, lost, stale, disabled, excluded, missing } = data;
+    let file_id = file_id(file_path);
+    let page_path = format!("{}/{}.html", files_dir, file_id);
+    let mut page = MinifyHtml::new(io::BufWriter::new(File::create(&page_path)?), options.minify);
+    
+    write_static_html_head(&mut page, file_path, "../", options, templates)?;
+    
+    if let Some(reason) = missing {
+        // Only the covered line numbers are known; the lines between them are elided
+        page.write_all(format!(
+            "<div class=\"content\">\n<div class=\"file-header\">\n<p><a class=\"breadcrumb\" href=\"../coverage_report.html\">&larr; {}</a></p>\n<h2 class=\"breadcrumbs\">{}</h2>\n<div class=\"coverage-summary\">{} covered lines <span class=\"missing-badge\" title=\"{}\">source not found</span></div>\n</div>\n<pre class=\"source-code missing-source\">\n",
+            html_escape(&options.title),
+            html_escape(file_path),
+            covered_count,
+            html_escape(reason)
+        ).as_bytes())?;
+        let mut last = None;
+        for line_number in covered_lines.iter() {
+            if last.is_some_and(|last| line_number > last + 1) {
+                page.write_all(b"<div class=\"line gap\"><span class=\"line-number\">&#8942;</span><span class=\"line-content\"></span></div>\n")?;
+            }
+            page.write_all(format!(
+                "<div class=\"line covered\" id=\"L{0}\"><a class=\"line-number\" href=\"#L{0}\">{0}</a><span class=\"line-content\"></span></div>\n",
+                line_number
+            ).as_bytes())?;
+            last = Some(line_number);
+        }
+        page.write_all(b"</pre>\n</div>\n</body>\n</html>\n")?;
+        return page.flush();
+    }
+    
+    page.write_all(format!(
+        "<div class=\"content\">\n<div class=\"file-header\">\n<p><a class=\"breadcrumb\" href=\"../coverage_report.html\">&larr; {}</a></p>\n<h2 class=\"breadcrumbs\">{}</h2>\n<div class=\"coverage-summary\">Coverage: <span class=\"{}\">{:.1}%</span> <span title=\"{} lines in the file, counting blank, comment and preprocessor lines\">({} of {} lines)</span>{}{}</div>\n</div>\n<pre class=\"source-code lang-{}\">\n",
+        html_escape(&options.title),
+        html_escape(file_path),
+        get_coverage_class(*coverage_pct, &options.thresholds),
+        coverage_pct,
+        source_lines,
+        covered_count,
+        total_lines,
+        if options.baseline.is_some() { delta_badge(gained.len(), lost.len()) } else { String::new() },
+        stale.map(|reason| format!(" <span class=\"stale-badge\" title=\"{}\">possibly stale</span>", html_escape(reason))).unwrap_or_default(),
+        language.id()
+    ).as_bytes())?;
+    
+    if let Some(template) = &options.source_url_template {
+        // Link each covered range to the external code browser
+        for (start, end) in covered_lines.ranges().map(|range| range.into_inner()) {
+            page.write_all(format!(
+                "<div class=\"line covered\"><a href=\"{}\">{}</a></div>\n",
+                html_escape(&source_url(template, &options.source_rev, file_path, start)),
+                if start == end { format!("Line {}", start) } else { format!("Lines {}-{}", start, end) }
+            ).as_bytes())?;
+        }
+    }
+    
+    for (i, line) in source_content.lines().enumerate() {
+        let line_number = i as u32 + 1;
+        let line_class = if gained.binary_search(&line_number).is_ok() {
+            " covered gained"
+        } else if lost.binary_search(&line_number).is_ok() {
+            " lost"
+        } else if excluded.contains(line_number) {
+            " excluded"
+        } else if disabled.contains(line_number) {
+            " not-compiled"
+        } else if covered_lines.contains(line_number) {
+            " covered"
+        } else {
+            ""
+        };
+        page.write_all(format!(
+            "<div class=\"line{}\" id=\"L{}\"><a class=\"line-number\" href=\"#L{}\">{}</a><span class=\"line-content\">{}</span></div>\n",
+            line_class,
+            line_number, line_number, line_number,
+            if options.show_whitespace { visualize_whitespace(line) } else { html_escape(line) }
+        ).as_bytes())?;
+    }
+    
+    page.write_all(b"</pre>\n</div>\n</body>\n</html>\n")?;
+    page.flush()
+}
+
+/// Writes a table with the number of files and the coverage per language
+fn write_language_stats(file: &mut dyn Write, files: &[FileSummary], thresholds: &CoverageThresholds) -> io::Result<()> {
+    // (files, covered lines, total lines) per language
+    let mut stats: BTreeMap<Language, (usize, usize, usize)> = BTreeMap::new();
+    for data in files {
+        let entry = stats.entry(data.language).or_insert((0, 0, 0));
+        entry.0 += 1;
+        entry.1 += data.covered_count;
+        entry.2 += data.total_lines;
+    }
+    
+    if stats.is_empty() {
+        return Ok(());
+    }
+    
+    file.write_all(b"<table class=\"language-stats\">\n<tr><th>Language</th><th>Files</th><th>Lines</th><th>Coverage</th></tr>\n")?;
+    
+    for (language, (files, covered, total)) in stats {
+        let coverage_pct = if total > 0 { (covered as f64 / total as f64) * 100.0 } else { 0.0 };
+        file.write_all(format!(
+            "<tr><td>{}</td><td>{}</td><td>{} of {}</td><td class=\"{}\">{:.1}%</td></tr>\n",
+            language.name(), files, covered, total, get_coverage_class(coverage_pct, thresholds), coverage_pct
+        ).as_bytes())?;
+    }
+    
+    file.write_all(b"</table>\n")?;
+    Ok(())
+}
+
+/// Writes a table of directory coverage goals and whether each one is met
+fn write_goal_stats(file: &mut dyn Write, results: &[GoalResult]) -> io::Result<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+    
+    file.write_all(b"<table class=\"language-stats goal-stats\">\n<tr><th>Directory</th><th>Goal</th><th>Coverage</th><th>Status</th></tr>\n")?;
+    
+    for result in results {
+        let (class, status) = if result.passed() { ("coverage-good", "pass") } else { ("coverage-bad", "fail") };
+        let directory = if result.directory.is_empty() { "(all files)" } else { &result.directory };
+        file.write_all(format!(
+            "<tr><td>{}</td><td>{:.1}%</td><td>{:.1}%</td><td class=\"{}\">{}</td></tr>\n",
+            html_escape(directory), result.goal, result.coverage_pct(), class, status
+        ).as_bytes())?;
+    }
+    
+    file.write_all(b"</table>\n")?;
+    Ok(())
+}
+
+/// Writes a table of the covered files whose source could not be read, with
+/// their covered lines, so they do not silently drop out of the report.
+/// `link` gives the attributes of a link to a file's view by its id, for
+/// pages that have one.
+fn write_missing_sources(file: &mut dyn Write, missing_sources: &BTreeMap<String, usize>, link: &dyn Fn(&str) -> Option<String>) -> io::Result<()> {
+    if missing_sources.is_empty() {
+        return Ok(());
+    }
+    
+    file.write_all(b"<h3>Missing sources</h3>\n<p>Covered files that were not found under the source roots or could not be read. They are not counted above.</p>\n")?;
+    file.write_all(b"<table class=\"language-stats missing-sources\">\n<tr><th>File</th><th>Covered lines</th></tr>\n")?;
+    
+    for (path, covered) in missing_sources {
+        let file_id = file_id(path);
+        let name = match link(&file_id) {
+            Some(attributes) => format!("<a {}>{}</a>", attributes, html_escape(path)),
+            None => html_escape(path),
+        };
+        file.write_all(format!("<tr><td>{}</td><td>{}</td></tr>\n", name, covered).as_bytes())?;
+    }
+    
+    file.write_all(b"</table>\n")?;
+    Ok(())
+}
+
+/// Writes the head and body opening of a page in the JavaScript-free report
+fn write_static_html_head(file: &mut dyn Write, title: &str, asset_prefix: &str, options: &ReportOptions, templates: &Templates) -> std::io::Result<()> {
+    file.write_all(format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n<title>{}</title>\n",
+        html_escape(title)
+    ).as_bytes())?;
+    
+    if options.external_assets {
+        file.write_all(format!("<meta http-equiv=\"Content-Security-Policy\" content=\"{}\">\n", CONTENT_SECURITY_POLICY).as_bytes())?;
+        file.write_all(format!("<link rel=\"stylesheet\" href=\"{}{}\">\n", asset_prefix, templates.css_name).as_bytes())?;
+    } else {
+        file.write_all(b"<style>\n")?;
+        file.write_all(templates.report_css.as_bytes())?;
+        file.write_all(format!(":root {{ --tab-width: {}; }}\n</style>\n", options.tab_width).as_bytes())?;
+    }
+    
+    file.write_all(b"</head>\n")?;
+    file.write_all(if options.wrap_lines { b"<body class=\"static wrap-lines\">\n" as &[u8] } else { b"<body class=\"static\">\n" })?;
+    Ok(())
+}
+
+/// Recursively renders the directory tree as nested `<details>` elements for the JavaScript-free report
+fn render_static_tree(
+    tree: &DirectoryTree,
+    current_path: &str,
+    html_file: &mut dyn Write,
+    level: usize,
+    thresholds: &CoverageThresholds,
+    deltas: &HashMap<&str, (usize, usize)>
+) -> io::Result<()> {
+    let Some((dirs, files)) = sorted_tree_children(tree, current_path) else {
+        return Ok(());
+    };
+    
+    // Render directories
+    for dir in dirs {
+        let full_path = if current_path.is_empty() {
+            dir.to_string()
+        } else {
+            format!("{}/{}", current_path, dir)
+        };
+        
+        html_file.write_all(format!(
+            "<details class=\"directory\"{}>\n<summary class=\"tree-toggle\">{}/</summary>\n<div class=\"static-tree-child\">\n",
+            if level == 0 { " open" } else { "" }, html_escape(dir)
+        ).as_bytes())?;
+        
+        render_static_tree(tree, &full_path, html_file, level + 1, thresholds, deltas)?;
+        
+        html_file.write_all(b"</div>\n</details>\n")?;
+    }
+    
+    // Render files
+    for (name, covered, total) in files {
+        let coverage_pct = if total > 0 { (covered as f64 / total as f64) * 100.0 } else { 0.0 };
+        
+        let path = if current_path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", current_path, name)
+        };
+        
+        let file_id = file_id(&path);
+        
+        let delta = match deltas.get(path.as_str()) {
+            Some((gained, lost)) if gained + lost > 0 => delta_badge(*gained, *lost),
+            _ => String::new(),
+        };
+        
+        html_file.write_all(format!(
+            "<div class=\"file-entry\"><a href=\"files/{}.html\" class=\"file-link\">{} <span class=\"coverage-badge {}\">({:.1}%)</span>{}</a></div>\n",
+            file_id, html_escape(name), get_coverage_class(coverage_pct, thresholds), coverage_pct, delta
+        ).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Renders the `+gained -lost` badge shown for files compared against a baseline
+fn delta_badge(gained: usize, lost: usize) -> String {
+    format!(
+        " <span class=\"delta-badge\"><span class=\"diff-gained\">+{}</span> <span class=\"diff-lost\">-{}</span></span>",
+        gained, lost
+    )
+}
+
+/// Escapes a source line for HTML, marking tabs and trailing whitespace
+fn visualize_whitespace(line: &str) -> String {
+    let body = line.trim_end_matches([' ', '\t']);
+    let trailing = &line[body.len()..];
+    
+    let mut html = html_escape(body).replace('\t', "<span class=\"ws-tab\">\t</span>");
+    if !trailing.is_empty() {
+        html.push_str("<span class=\"ws-trailing\">");
+        html.push_str(&trailing.replace('\t', "<span class=\"ws-tab\">\t</span>"));
+        html.push_str("</span>");
+    }
+    html
+}
+
+/// Builds the URL of a line in an external code browser from a template
+fn source_url(template: &str, rev: &str, path: &str, line: u32) -> String {
+    template
+        .replace("{rev}", rev)
+        .replace("{path}", path)
+        .replace("{line}", &line.to_string())
+}
+
+/// Escapes text for inclusion in HTML content or attribute values
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Serializes a value as JSON that is safe inside a `<script>` element:
+/// `<` is escaped so that no string can close the element
+fn script_json(value: &Value) -> String {
+    value.to_string().replace('<', "\\u003c")
+}
+
+/// Content-Security-Policy used for reports with external assets
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; script-src 'self'; style-src 'self'; connect-src 'self'; img-src 'self'; base-uri 'none'; form-action 'none'";
+
+/// Page skeleton of the interactive report. `{{title}}`, `{{head}}`,
+/// `{{overall}}`, `{{stats}}`, `{{files}}` and `{{scripts}}` are filled in
+/// when it is written.
+const REPORT_PAGE: &str = include_str!("../templates/report.html");
+
+/// CSS styles for the combined HTML report
+const REPORT_CSS: &str = include_str!("../templates/report.css");
+
+/// Additional CSS styles for the coverage diff page
+const DIFF_CSS: &str = include_str!("../templates/diff.css");
+
+/// JavaScript functions for the combined HTML report
+const REPORT_JS: &str = include_str!("../templates/report.js");
+
+/// Service worker caching the pages and data of a report for offline use
+const SERVICE_WORKER: &str = include_str!("../templates/sw.js");
+
+/// Drag-and-drop viewer page; `{{wasm}}` is filled in with the base64 of
+/// the WebAssembly module
+const VIEWER_PAGE: &str = include_str!("../templates/viewer.html");
+
+/// Built-in styles of the report, minified once per run
+static MINIFIED_CSS: LazyLock<String> = LazyLock::new(|| minify_css(REPORT_CSS));
+
+/// Built-in script of the report, minified once per run
+static MINIFIED_JS: LazyLock<String> = LazyLock::new(|| minify_js(REPORT_JS));
+
+/// Page skeleton, styles and script of the report, built in or overridden
+/// by files of the same name in a template directory
+struct Templates {
+    report_page: Cow<'static, str>,
+    report_css: Cow<'static, str>,
+    report_js: Cow<'static, str>,
+    /// File names of the styles and script written as external assets
+    css_name: String,
+    js_name: String,
+}
+
+impl Templates {
+    /// Reads the templates found in the template directory, keeping the
+    /// built-in version of the others, with the styles and script minified
+    /// if asked
+    fn load(options: &ReportOptions) -> Result<Templates, Cov2HtmlError> {
+        let template_dir = options.template_dir.as_deref();
+        let load = |name: &str, builtin: &'static str| -> Result<Cow<'static, str>, Cov2HtmlError> {
+            let Some(dir) = template_dir else {
+                return Ok(Cow::Borrowed(builtin));
+            };
+            let path = Path::new(dir).join(name);
+            match fs::read_to_string(&path) {
+                Ok(template) => {
+                    debug!("Using template {}", path.display());
+                    Ok(Cow::Owned(template))
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Cow::Borrowed(builtin)),
+                Err(source) => Err(Cov2HtmlError::ReadTemplate { path: path.to_string_lossy().into_owned(), source }),
+            }
+        };
+        
+        let mut templates = Templates {
+            report_page: load("report.html", REPORT_PAGE)?,
+            report_css: load("report.css", REPORT_CSS)?,
+            report_js: load("report.js", REPORT_JS)?,
+            css_name: "report.css".to_string(),
+            js_name: "report.js".to_string(),
+        };
+        // Only the built-in templates are borrowed
+        if options.minify {
+            templates.report_css = match templates.report_css {
+                Cow::Borrowed(_) => Cow::Borrowed(MINIFIED_CSS.as_str()),
+                Cow::Owned(css) => Cow::Owned(minify_css(&css)),
+            };
+            templates.report_js = match templates.report_js {
+                Cow::Borrowed(_) => Cow::Borrowed(MINIFIED_JS.as_str()),
+                Cow::Owned(js) => Cow::Owned(minify_js(&js)),
+            };
+        }
+        if options.external_assets {
+            templates.css_name = if options.no_js {
+                asset_name("report", "css", &static_css(&templates.report_css, options))
+            } else {
+                asset_name("report", "css", &templates.report_css)
+            };
+            templates.js_name = asset_name("report", "js", &templates.report_js);
+        }
+        Ok(templates)
+    }
+}
+
+/// Styles of the JavaScript-free report, which sets the tab width in CSS
+fn static_css(report_css: &str, options: &ReportOptions) -> String {
+    format!("{}\n:root {{ --tab-width: {}; }}\n", report_css, options.tab_width)
+}
+
+/// Standard base64 with padding
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    
+    encoded
+}
+
+/// Writes a template, calling `fill` to write the value of each `{{name}}`
+/// placeholder
+fn render_template(
+    out: &mut dyn Write,
+    name: &str,
+    template: &str,
+    mut fill: impl FnMut(&str, &mut dyn Write) -> io::Result<()>
+) -> io::Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.write_all(&rest.as_bytes()[..start])?;
+        let end = rest[start..].find("}}")
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unterminated placeholder in {}", name)))?;
+        fill(rest[start + 2..start + end].trim(), out)?;
+        rest = &rest[start + end + 2..];
+    }
+    
+    out.write_all(rest.as_bytes())
+}
+
+/// Writes the CSS styles of the combined HTML, inline or linked with a
+/// Content-Security-Policy
+fn write_combined_html_head(file: &mut dyn Write, options: &ReportOptions, templates: &Templates) -> std::io::Result<()> {
+    if options.external_assets {
+        // Everything is loaded from the report directory, so the policy can forbid inline code
+        file.write_all(format!("<meta http-equiv=\"Content-Security-Policy\" content=\"{}\">\n", CONTENT_SECURITY_POLICY).as_bytes())?;
+        file.write_all(format!("<link rel=\"stylesheet\" href=\"{}\">\n", templates.css_name).as_bytes())?;
+    } else {
+        file.write_all(b"<style>\n")?;
+        file.write_all(templates.report_css.as_bytes())?;
+        file.write_all(b"</style>\n")?;
+    }
+    
+    Ok(())
+}
+
+/// Helper function to get CSS class based on coverage percentage
+fn get_coverage_class(percentage: f64, thresholds: &CoverageThresholds) -> &'static str {
+    if percentage >= thresholds.good {
+        "coverage-good"
+    } else if percentage >= thresholds.medium {
+        "coverage-medium"
+    } else {
+        "coverage-bad"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executable::Denominator;
+    use crate::source::MemorySources;
+
+    #[test]
+    fn fragment_keys_follow_what_the_fragment_shows() {
+        let fingerprint = Fnv::default();
+        let source = SourceInfo::new("int x;\nint y;\n", Language::C, Denominator::Executable, None, &[]);
+        let covered: LineSet = [1].into_iter().collect();
+        let instrumented: LineSet = [1, 2].into_iter().collect();
+        let key = fragment_key(&fingerprint, "a.c", &source, &covered, &[], &[], false, None);
+        assert_eq!(key, fragment_key(&fingerprint, "a.c", &source, &covered, &[], &[], false, None));
+
+        let edited = SourceInfo::new("int x;\nint z;\n", Language::C, Denominator::Executable, None, &[]);
+        let changed = [
+            fragment_key(&fingerprint, "b.c", &source, &covered, &[], &[], false, None),
+            fragment_key(&fingerprint, "a.c", &edited, &covered, &[], &[], false, None),
+            fragment_key(&fingerprint, "a.c", &source, &[1, 2].into_iter().collect(), &[], &[], false, None),
+            fragment_key(&fingerprint, "a.c", &source, &covered, &[1], &[], false, None),
+            fragment_key(&fingerprint, "a.c", &source, &covered, &[], &[2], false, None),
+            fragment_key(&fingerprint, "a.c", &source, &covered, &[], &[], true, None),
+            fragment_key(&fingerprint, "a.c", &source, &covered, &[], &[], false, Some(&instrumented)),
+            fragment_key(&fingerprint, "a.c", &source, &covered, &[], &[], false, Some(&covered)),
+            fragment_key(&fingerprint, "a.c", &source, &covered, &[], &[], false, Some(&LineSet::new())),
+        ];
+        for (index, changed) in changed.iter().enumerate() {
+            assert_ne!(key, *changed, "change {} kept the key", index);
+        }
+    }
+
+    #[test]
+    fn fragment_fingerprints_follow_the_options() {
+        let templates = Templates::load(&ReportOptions::default()).unwrap();
+        let options = ReportOptions::default();
+        let fingerprint = fragment_fingerprint(&options, &templates, false).finish();
+        assert_eq!(fingerprint, fragment_fingerprint(&options, &templates, false).finish());
+        assert_ne!(fingerprint, fragment_fingerprint(&options, &templates, true).finish());
+        let all = ReportOptions { denominator: Denominator::All, ..ReportOptions::default() };
+        assert_ne!(fingerprint, fragment_fingerprint(&all, &templates, false).finish());
+        let truncated = ReportOptions { max_line_length: Some(80), ..ReportOptions::default() };
+        assert_ne!(fingerprint, fragment_fingerprint(&truncated, &templates, false).finish());
+
+        let edits: [fn(&mut Templates) -> &mut Cow<'static, str>; 3] = [
+            |templates| &mut templates.report_page,
+            |templates| &mut templates.report_css,
+            |templates| &mut templates.report_js,
+        ];
+        for (index, edit) in edits.iter().enumerate() {
+            let mut edited = Templates::load(&options).unwrap();
+            edit(&mut edited).to_mut().push('\n');
+            assert_ne!(fingerprint, fragment_fingerprint(&options, &edited, false).finish(), "template {} was edited", index);
+        }
+    }
+
+    #[test]
+    fn paths_get_chunks_of_their_own() {
+        assert_eq!(file_id("drivers/net-x/e1000_main.c"), "drivers_2fnet-x_2fe1000_5fmain_2ec");
+        let covered = LineSet::new();
+        for options in [ReportOptions::default(), ReportOptions { external_assets: true, ..ReportOptions::default() }] {
+            assert_ne!(chunk_name("a/b_c.c", 0, &covered, &options), chunk_name("a_b/c.c", 0, &covered, &options));
+            assert_ne!(chunk_name("a/b.c", 0, &covered, &options), chunk_name("a/b_c", 0, &covered, &options));
+        }
+    }
+
+    #[test]
+    fn paths_get_static_pages_of_their_own() {
+        let work_dir = std::env::temp_dir().join(format!("cov2html-test-{}-static-pages", std::process::id()));
+        let mut sources = MemorySources::new();
+        sources.insert("a/b_c.c", "int x;\n");
+        sources.insert("a_b/c.c", "int y;\n");
+        let coverage_map = HashMap::from([("a/b_c.c".to_string(), [1].into_iter().collect()), ("a_b/c.c".to_string(), [1].into_iter().collect())]);
+        let options = ReportOptions { no_js: true, ..ReportOptions::default() };
+
+        crate::coverage::generate_report(&coverage_map, &sources, &work_dir.to_string_lossy(), &options).unwrap();
+        let index = fs::read_to_string(work_dir.join("coverage_report.html")).unwrap();
+        for path in ["a/b_c.c", "a_b/c.c"] {
+            let page = format!("files/{}.html", file_id(path));
+            assert!(index.contains(&format!("href=\"{}\"", page)), "no link to {}", page);
+            assert!(fs::read_to_string(work_dir.join(&page)).unwrap().contains(path));
+        }
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+}
@@ -1 +1,38 @@
-pub mod coverage; 
\ No newline at end of file
+pub mod builder;
+#[cfg(feature = "html")]
+mod cache;
+mod checksum;
+pub mod combine;
+pub mod config;
+pub mod coverage;
+pub mod error;
+pub mod executable;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod generator;
+#[cfg(feature = "html")]
+pub mod html;
+pub mod lines;
+#[cfg(feature = "serve")]
+pub mod live;
+#[cfg(feature = "html")]
+mod minify;
+pub mod model;
+pub mod parser;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod renderer;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod source;
+#[cfg(feature = "symbolize")]
+pub mod symbolize;
+pub mod tree;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use builder::ReportBuilder;
+pub use generator::ReportGenerator;
+pub use lines::LineSet;
@@ -0,0 +1,432 @@
+//! Sets of covered line numbers.
+//!
+//! Kernel coverage mostly comes in long runs of consecutive lines, so a set
+//! is kept as sorted ranges rather than one entry per line. With the
+//! `roaring` feature it is a roaring bitmap instead, which stays compact and
+//! fast to merge and diff for campaigns with tens of millions of scattered
+//! lines.
+
+use std::ops::RangeInclusive;
+
+#[cfg(feature = "roaring")]
+use roaring::RoaringBitmap;
+
+/// Iterator over the lines of a `LineSet`, in ascending order
+#[cfg(not(feature = "roaring"))]
+pub type Lines<'a> = std::iter::FlatMap<std::slice::Iter<'a, (u32, u32)>, RangeInclusive<u32>, fn(&(u32, u32)) -> RangeInclusive<u32>>;
+
+/// Iterator over the lines of a `LineSet`, in ascending order
+#[cfg(feature = "roaring")]
+pub type Lines<'a> = roaring::bitmap::Iter<'a>;
+
+/// Line numbers of a file, stored as sorted, disjoint and non-adjacent
+/// inclusive ranges, or as a roaring bitmap with the `roaring` feature
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineSet {
+    #[cfg(not(feature = "roaring"))]
+    ranges: Vec<(u32, u32)>,
+    #[cfg(feature = "roaring")]
+    bitmap: RoaringBitmap,
+}
+
+impl Eq for LineSet {}
+
+impl LineSet {
+    /// Creates an empty set
+    pub fn new() -> LineSet {
+        LineSet::default()
+    }
+}
+
+#[cfg(not(feature = "roaring"))]
+impl LineSet {
+    /// Adds a line; returns whether it was not in the set yet
+    pub fn insert(&mut self, line: u32) -> bool {
+        // First range that ends at or after the line
+        let index = self.ranges.partition_point(|&(_, end)| end < line);
+        if self.ranges.get(index).is_some_and(|&(start, _)| start <= line) {
+            return false;
+        }
+
+        let extends_previous = index > 0 && self.ranges[index - 1].1.checked_add(1) == Some(line);
+        let extends_next = self.ranges.get(index).is_some_and(|&(start, _)| line.checked_add(1) == Some(start));
+        match (extends_previous, extends_next) {
+            (true, true) => {
+                self.ranges[index - 1].1 = self.ranges[index].1;
+                self.ranges.remove(index);
+            }
+            (true, false) => self.ranges[index - 1].1 = line,
+            (false, true) => self.ranges[index].0 = line,
+            (false, false) => self.ranges.insert(index, (line, line)),
+        }
+        true
+    }
+
+    /// Whether a line is in the set
+    pub fn contains(&self, line: u32) -> bool {
+        let index = self.ranges.partition_point(|&(_, end)| end < line);
+        self.ranges.get(index).is_some_and(|&(start, _)| start <= line)
+    }
+
+    /// Number of lines
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|&(start, end)| (end - start) as usize + 1).sum()
+    }
+
+    /// Whether the set has no lines
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Removes every line
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Lines in ascending order
+    pub fn iter(&self) -> Lines<'_> {
+        self.ranges.iter().flat_map(|&(start, end)| start..=end)
+    }
+
+    /// Runs of consecutive lines in ascending order
+    pub fn ranges(&self) -> impl Iterator<Item = RangeInclusive<u32>> + '_ {
+        self.ranges.iter().map(|&(start, end)| start..=end)
+    }
+
+    /// Lines in either set
+    pub fn union(&self, other: &LineSet) -> LineSet {
+        let mut ranges: Vec<(u32, u32)> = Vec::with_capacity(self.ranges.len() + other.ranges.len());
+        let (mut left, mut right) = (self.ranges.iter().peekable(), other.ranges.iter().peekable());
+        loop {
+            let next = match (left.peek(), right.peek()) {
+                (Some(a), Some(b)) if a.0 <= b.0 => left.next(),
+                (Some(_), Some(_)) => right.next(),
+                (Some(_), None) => left.next(),
+                (None, _) => right.next(),
+            };
+            let Some(&(start, end)) = next else {
+                break;
+            };
+            match ranges.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        LineSet { ranges }
+    }
+
+    /// Adds the lines of another set
+    pub fn union_with(&mut self, other: &LineSet) {
+        *self = self.union(other);
+    }
+
+    /// Lines in both sets
+    pub fn intersection(&self, other: &LineSet) -> LineSet {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (self.ranges[i], other.ranges[j]);
+            let (start, end) = (a.0.max(b.0), a.1.min(b.1));
+            if start <= end {
+                ranges.push((start, end));
+            }
+            if a.1 < b.1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        LineSet { ranges }
+    }
+
+    /// Lines in this set but not in `other`
+    pub fn difference(&self, other: &LineSet) -> LineSet {
+        let mut ranges = Vec::new();
+        let mut j = 0;
+        'ranges: for &(start, end) in &self.ranges {
+            // Skip the ranges of `other` that end before this one
+            while j < other.ranges.len() && other.ranges[j].1 < start {
+                j += 1;
+            }
+
+            let mut from = start;
+            for &(removed_start, removed_end) in &other.ranges[j..] {
+                if removed_start > end {
+                    break;
+                }
+                if removed_start > from {
+                    ranges.push((from, removed_start - 1));
+                }
+                match removed_end.checked_add(1) {
+                    Some(next) if next <= end => from = next,
+                    _ => continue 'ranges,
+                }
+            }
+            ranges.push((from, end));
+        }
+
+        LineSet { ranges }
+    }
+}
+
+#[cfg(feature = "roaring")]
+impl LineSet {
+    /// Adds a line; returns whether it was not in the set yet
+    pub fn insert(&mut self, line: u32) -> bool {
+        self.bitmap.insert(line)
+    }
+
+    /// Whether a line is in the set
+    pub fn contains(&self, line: u32) -> bool {
+        self.bitmap.contains(line)
+    }
+
+    /// Number of lines
+    pub fn len(&self) -> usize {
+        self.bitmap.len() as usize
+    }
+
+    /// Whether the set has no lines
+    pub fn is_empty(&self) -> bool {
+        self.bitmap.is_empty()
+    }
+
+    /// Removes every line
+    pub fn clear(&mut self) {
+        self.bitmap.clear();
+    }
+
+    /// Lines in ascending order
+    pub fn iter(&self) -> Lines<'_> {
+        self.bitmap.iter()
+    }
+
+    /// Runs of consecutive lines in ascending order
+    pub fn ranges(&self) -> impl Iterator<Item = RangeInclusive<u32>> + '_ {
+        let mut lines = self.bitmap.iter().peekable();
+        std::iter::from_fn(move || {
+            let start = lines.next()?;
+            let mut end = start;
+            while let Some(line) = lines.next_if(|&line| end.checked_add(1) == Some(line)) {
+                end = line;
+            }
+            Some(start..=end)
+        })
+    }
+
+    /// Lines in either set
+    pub fn union(&self, other: &LineSet) -> LineSet {
+        LineSet { bitmap: &self.bitmap | &other.bitmap }
+    }
+
+    /// Adds the lines of another set
+    pub fn union_with(&mut self, other: &LineSet) {
+        self.bitmap |= &other.bitmap;
+    }
+
+    /// Lines in both sets
+    pub fn intersection(&self, other: &LineSet) -> LineSet {
+        LineSet { bitmap: &self.bitmap & &other.bitmap }
+    }
+
+    /// Lines in this set but not in `other`
+    pub fn difference(&self, other: &LineSet) -> LineSet {
+        LineSet { bitmap: &self.bitmap - &other.bitmap }
+    }
+}
+
+#[cfg(not(feature = "roaring"))]
+impl FromIterator<u32> for LineSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(lines: I) -> LineSet {
+        let mut lines: Vec<u32> = lines.into_iter().collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for line in lines {
+            match ranges.last_mut() {
+                Some(last) if last.1 + 1 == line => last.1 = line,
+                _ => ranges.push((line, line)),
+            }
+        }
+
+        LineSet { ranges }
+    }
+}
+
+#[cfg(feature = "roaring")]
+impl FromIterator<u32> for LineSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(lines: I) -> LineSet {
+        LineSet { bitmap: lines.into_iter().collect() }
+    }
+}
+
+impl Extend<u32> for LineSet {
+    #[cfg(not(feature = "roaring"))]
+    fn extend<I: IntoIterator<Item = u32>>(&mut self, lines: I) {
+        *self = self.union(&lines.into_iter().collect());
+    }
+
+    #[cfg(feature = "roaring")]
+    fn extend<I: IntoIterator<Item = u32>>(&mut self, lines: I) {
+        self.bitmap.extend(lines);
+    }
+}
+
+impl<'a> IntoIterator for &'a LineSet {
+    type Item = u32;
+    type IntoIter = Lines<'a>;
+
+    fn into_iter(self) -> Lines<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(lines: &[u32]) -> LineSet {
+        lines.iter().copied().collect()
+    }
+
+    fn ranges(lines: &LineSet) -> Vec<(u32, u32)> {
+        lines.ranges().map(|range| (*range.start(), *range.end())).collect()
+    }
+
+    #[test]
+    fn insert_joins_ranges_at_their_edges() {
+        let mut lines = set(&[1, 2, 3, 7, 8]);
+        assert!(lines.insert(4));
+        assert_eq!(ranges(&lines), [(1, 4), (7, 8)]);
+        assert!(lines.insert(6));
+        assert_eq!(ranges(&lines), [(1, 4), (6, 8)]);
+        assert!(lines.insert(5));
+        assert_eq!(ranges(&lines), [(1, 8)]);
+        assert!(!lines.insert(1));
+        assert!(!lines.insert(8));
+        assert_eq!(lines.len(), 8);
+        assert!(lines.contains(1) && lines.contains(8) && !lines.contains(0) && !lines.contains(9));
+    }
+
+    #[test]
+    fn union_joins_touching_ranges() {
+        let union = set(&[1, 2, 3]).union(&set(&[4, 5, 10]));
+        assert_eq!(ranges(&union), [(1, 5), (10, 10)]);
+        let mut lines = set(&[10]);
+        lines.union_with(&set(&[9, 11]));
+        assert_eq!(ranges(&lines), [(9, 11)]);
+    }
+
+    #[test]
+    fn intersection_keeps_shared_edges() {
+        let intersection = set(&[1, 2, 3, 4, 5]).intersection(&set(&[5, 6, 7]));
+        assert_eq!(ranges(&intersection), [(5, 5)]);
+        assert!(set(&[1, 2, 3]).intersection(&set(&[4, 5])).is_empty());
+    }
+
+    #[test]
+    fn difference_trims_and_splits_ranges() {
+        let lines = set(&[1, 2, 3, 4, 5]);
+        assert_eq!(ranges(&lines.difference(&set(&[1]))), [(2, 5)]);
+        assert_eq!(ranges(&lines.difference(&set(&[5]))), [(1, 4)]);
+        assert_eq!(ranges(&lines.difference(&set(&[3]))), [(1, 2), (4, 5)]);
+        assert!(lines.difference(&lines).is_empty());
+    }
+
+    #[test]
+    fn adjacent_and_overlapping_ranges_merge() {
+        let ranges_of = |a: &[u32], b: &[u32]| ranges(&set(a).union(&set(b)));
+        assert_eq!(ranges_of(&[1, 2, 3], &[4, 5, 6]), [(1, 6)]);
+        assert_eq!(ranges_of(&[4, 5, 6], &[1, 2, 3]), [(1, 6)]);
+        assert_eq!(ranges_of(&[1, 2, 3, 4, 5], &[3, 4, 5, 6, 7, 8]), [(1, 8)]);
+        assert_eq!(ranges_of(&[1, 2, 3, 4, 5, 6], &[3, 4]), [(1, 6)]);
+        assert_eq!(ranges_of(&[1, 3, 5], &[2, 4]), [(1, 5)]);
+        assert_eq!(ranges_of(&[1, 2], &[4, 5]), [(1, 2), (4, 5)]);
+        assert_eq!(ranges(&[5, 3, 4, 3, 1].into_iter().collect()), [(1, 1), (3, 5)]);
+        assert_eq!(ranges(&set(&[1, 2, 3, 4, 5]).intersection(&set(&[2, 3, 4]))), [(2, 4)]);
+        assert_eq!(ranges(&set(&[1, 2, 3, 6, 7, 8]).intersection(&set(&[3, 4, 5, 6]))), [(3, 3), (6, 6)]);
+    }
+
+    #[test]
+    fn lines_up_to_u32_max() {
+        const MAX: u32 = u32::MAX;
+        let mut lines = set(&[MAX]);
+        assert!(lines.insert(MAX - 1));
+        assert!(!lines.insert(MAX));
+        assert_eq!(ranges(&lines), [(MAX - 1, MAX)]);
+        assert!(lines.contains(MAX) && !lines.contains(MAX - 2));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines.iter().collect::<Vec<u32>>(), [MAX - 1, MAX]);
+
+        assert_eq!(ranges(&lines.union(&set(&[MAX - 2]))), [(MAX - 2, MAX)]);
+        assert_eq!(ranges(&lines.union(&set(&[0]))), [(0, 0), (MAX - 1, MAX)]);
+        assert_eq!(ranges(&lines.intersection(&set(&[MAX]))), [(MAX, MAX)]);
+        assert_eq!(ranges(&lines.difference(&set(&[MAX]))), [(MAX - 1, MAX - 1)]);
+        assert_eq!(ranges(&lines.difference(&set(&[MAX - 1]))), [(MAX, MAX)]);
+        let mut extended = lines.clone();
+        extended.extend([MAX - 3, MAX - 2]);
+        assert_eq!(ranges(&extended), [(MAX - 3, MAX)]);
+    }
+
+    /// xorshift32, for reproducible pseudo-random lines
+    fn next(state: &mut u32) -> u32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state
+    }
+
+    /// Runs of lines clustered near 0 and near `u32::MAX`, like real
+    /// coverage plus the edge cases
+    fn random_lines(state: &mut u32) -> Vec<u32> {
+        let mut lines = Vec::new();
+        for _ in 0..next(state) % 8 {
+            let start = match next(state) % 3 {
+                0 => next(state) % 64,
+                1 => u32::MAX - next(state) % 64,
+                _ => next(state),
+            };
+            let len = next(state) % 6;
+            lines.extend((0..len).map_while(|offset| start.checked_add(offset)));
+        }
+        lines
+    }
+
+    /// Both backends have to match a plain `BTreeSet`, which makes them
+    /// match each other; run with and without `--features roaring`
+    #[test]
+    fn matches_a_btreeset() {
+        use std::collections::BTreeSet;
+
+        let mut state = 0x2545_f491;
+        for _ in 0..2000 {
+            let (a, b) = (random_lines(&mut state), random_lines(&mut state));
+            let (set_a, set_b): (LineSet, LineSet) = (a.iter().copied().collect(), b.iter().copied().collect());
+            let (model_a, model_b): (BTreeSet<u32>, BTreeSet<u32>) = (a.iter().copied().collect(), b.iter().copied().collect());
+            let lines = |set: &LineSet| set.iter().collect::<Vec<u32>>();
+
+            assert_eq!(lines(&set_a), model_a.iter().copied().collect::<Vec<u32>>());
+            assert_eq!(set_a.len(), model_a.len());
+            assert_eq!(set_a.is_empty(), model_a.is_empty());
+            assert_eq!(lines(&set_a.union(&set_b)), model_a.union(&model_b).copied().collect::<Vec<u32>>());
+            assert_eq!(lines(&set_a.intersection(&set_b)), model_a.intersection(&model_b).copied().collect::<Vec<u32>>());
+            assert_eq!(lines(&set_a.difference(&set_b)), model_a.difference(&model_b).copied().collect::<Vec<u32>>());
+            for line in &b {
+                assert_eq!(set_a.contains(*line), model_a.contains(line));
+            }
+
+            let mut inserted = set_a.clone();
+            let mut model = model_a.clone();
+            for line in &b {
+                assert_eq!(inserted.insert(*line), model.insert(*line));
+            }
+            assert_eq!(lines(&inserted), model.iter().copied().collect::<Vec<u32>>());
+            // Ranges are maximal: none touches the next
+            let runs = ranges(&inserted);
+            assert!(runs.windows(2).all(|pair| pair[0].1.checked_add(1).is_some_and(|next| next < pair[1].0)));
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use log::warn;
+
+use crate::coverage::{file_id, focus_path, json_string, remap_path, CoverageSummary, PathMapping};
+use crate::lines::LineSet;
+use crate::parser::parse_coverage_record;
+
+/// Reads the records appended to a growing coverage file since the last read
+#[derive(Debug)]
+pub struct CoverageTail {
+    path: String,
+    /// Bytes of the file consumed so far
+    offset: u64,
+    /// Trailing record that has not been terminated by a newline yet
+    partial: Vec<u8>,
+}
+
+/// Result of reading a coverage file incrementally
+#[derive(Debug)]
+pub enum TailRead {
+    /// `(path, line, count)` records appended since the previous read
+    Records(Vec<(String, u32, u64)>),
+    /// The file shrank, so it was truncated or replaced; the next read starts over
+    Truncated,
+}
+
+impl CoverageTail {
+    /// Starts reading the coverage file from its beginning
+    pub fn new(path: &str) -> CoverageTail {
+        CoverageTail {
+            path: path.to_string(),
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Reads the complete records appended since the previous call
+    pub fn read_records(&mut self) -> io::Result<TailRead> {
+        let mut file = File::open(&self.path)?;
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+            return Ok(TailRead::Truncated);
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = Vec::new();
+        self.offset += file.read_to_end(&mut appended)? as u64;
+        self.partial.extend_from_slice(&appended);
+
+        // Keep an unterminated last record until the writer finishes it
+        let complete = match self.partial.iter().rposition(|b| *b == b'\n') {
+            Some(end) => self.partial.drain(..=end).collect::<Vec<u8>>(),
+            None => return Ok(TailRead::Records(Vec::new())),
+        };
+
+        let records = String::from_utf8_lossy(&complete)
+            .lines()
+            .filter_map(|line| match parse_coverage_record(line) {
+                Ok(record) => record.map(|(path, line, count)| (path.to_string(), line, count)),
+                Err(message) => {
+                    warn!("{}", message);
+                    None
+                }
+            })
+            .collect();
+        Ok(TailRead::Records(records))
+    }
+}
+
+/// What changed in a live report since the last poll
+#[derive(Debug)]
+pub enum LiveChange {
+    /// No new lines were covered
+    Unchanged,
+    /// Lines of files already in the report were covered; holds the JSON
+    /// update to push to open reports
+    Updated(String),
+    /// Files missing from the generated report were covered, or the coverage
+    /// file was replaced, so the report has to be generated again
+    NeedsRegeneration,
+}
+
+/// Coverage of a served report that keeps growing while a fuzzer runs. New
+/// lines of files already in the report are tracked incrementally; only new
+/// files require generating the report again.
+#[derive(Debug)]
+pub struct LiveReport {
+    tail: CoverageTail,
+    path_mappings: Vec<PathMapping>,
+    /// Subtree the report is rooted at; records outside it are ignored
+    root: Option<String>,
+    coverage_map: HashMap<String, LineSet>,
+    /// `(covered, total)` lines of the files in the generated report, kept up to date
+    file_totals: BTreeMap<String, (usize, usize)>,
+    /// Lines covered since the report was generated, per file
+    pending: BTreeMap<String, BTreeSet<u32>>,
+}
+
+impl LiveReport {
+    /// Reads the coverage file as it is now, keeping only the files below
+    /// `root` if given
+    pub fn open(coverage_file: &str, path_mappings: &[PathMapping], root: Option<&str>) -> io::Result<LiveReport> {
+        let mut live = LiveReport {
+            tail: CoverageTail::new(coverage_file),
+            path_mappings: path_mappings.to_vec(),
+            root: root.map(str::to_string),
+            coverage_map: HashMap::new(),
+            file_totals: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        };
+        live.poll()?;
+
+        Ok(live)
+    }
+
+    /// All coverage read so far
+    pub fn coverage_map(&self) -> &HashMap<String, LineSet> {
+        &self.coverage_map
+    }
+
+    /// Records the totals of a freshly generated report
+    pub fn set_generated(&mut self, summary: &CoverageSummary) {
+        self.file_totals = summary.file_totals.clone();
+        self.pending.clear();
+    }
+
+    /// Reads newly appended records and reports what changed
+    pub fn poll(&mut self) -> io::Result<LiveChange> {
+        let records = match self.tail.read_records()? {
+            TailRead::Records(records) => records,
+            TailRead::Truncated => {
+                self.coverage_map.clear();
+                self.poll()?;
+                return Ok(LiveChange::NeedsRegeneration);
+            }
+        };
+
+        let mut new_files = false;
+        let mut changed: BTreeMap<String, BTreeSet<u32>> = BTreeMap::new();
+        for (path, line, _) in records {
+            let path = remap_path(&path, &self.path_mappings);
+            let Some(path) = focus_path(&path, self.root.as_deref().unwrap_or("")) else {
+                continue;
+            };
+            if !self.coverage_map.contains_key(&path) {
+                new_files = true;
+            }
+            if !self.coverage_map.entry(path.clone()).or_default().insert(line) {
+                continue;
+            }
+
+            // Files without a readable source are not part of the report
+            if let Some((covered, _)) = self.file_totals.get_mut(&path) {
+                *covered += 1;
+                self.pending.entry(path.clone()).or_default().insert(line);
+                changed.entry(path).or_default().insert(line);
+            }
+        }
+
+        Ok(if new_files {
+            LiveChange::NeedsRegeneration
+        } else if changed.is_empty() {
+            LiveChange::Unchanged
+        } else {
+            LiveChange::Updated(self.update_json(&changed))
+        })
+    }
+
+    /// Update holding every line covered since the report was generated, for
+    /// reports opened after that; `None` if nothing changed yet
+    pub fn snapshot_json(&self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| self.update_json(&self.pending))
+    }
+
+    /// Builds the JSON update sent to open reports: overall totals plus the
+    /// new lines and totals of each changed file
+    fn update_json(&self, changed: &BTreeMap<String, BTreeSet<u32>>) -> String {
+        let covered: usize = self.file_totals.values().map(|(covered, _)| covered).sum();
+        let total: usize = self.file_totals.values().map(|(_, total)| total).sum();
+
+        let files = changed.iter()
+            .map(|(path, lines)| {
+                let (file_covered, file_total) = self.file_totals[path];
+                let file_id = file_id(path);
+                let lines = lines.iter().map(|line| line.to_string()).collect::<Vec<String>>().join(",");
+                format!(
+                    "{}:{{\"lines\":[{}],\"coveredCount\":{},\"totalLines\":{}}}",
+                    json_string(&file_id), lines, file_covered, file_total
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+
+        format!("{{\"covered\":{},\"total\":{},\"files\":{{{}}}}}", covered, total, files)
+    }
+}
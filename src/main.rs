@@ -1,27 +1,1282 @@
-use clap::Parser;
-use cov2html::coverage::generate_report_from_file;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::process::{self, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap::ArgAction;
+use clap::error::ErrorKind;
+use clap_complete::Shell;
+use cov2html::{LineSet, ReportBuilder};
+use cov2html::config::Config;
+use cov2html::executable::{Denominator, KernelConfig, LineTables};
+use cov2html::live::{LiveChange, LiveReport};
+use cov2html::parser::{normalize_separators, Checksums, InputFormat};
+use cov2html::renderer::{parse_renderer, ReportRenderer};
+use cov2html::serve::{serve_directory, LiveUpdates};
+use cov2html::symbolize::{read_line_tables, symbolize_pc_file};
+use log::{error, info, warn, Level, LevelFilter, Log, Metadata, Record};
+use cov2html::coverage::{
+    detect_build_prefixes, diff_coverage, generate_report, merge_aliased_counts, merge_aliased_files, resolve_source, intersect_coverage, merge_coverage_counts_parallel, read_coverage_counts, parse_coverage_file_with_warnings,
+    remap_coverage, remap_coverage_counts, subtract_coverage, summarize_coverage, summarize_coverage_cached, unique_line_contributions, write_coverage_counts, write_coverage_diff,
+    write_coverage_file, write_diff_html, write_viewer_html, validate_coverage_file, include_uncovered_files, focus_coverage, subtree_roots, ExclusionPreset,
+    evaluate_goals, ratchet_regressions, read_ratchet, update_ratchet, write_ratchet, CACHE_DIR, parse_size, CoverageSummary, CoverageThresholds, OutputFormat, PathMapping, ReportOptions, SummaryFormat,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Options shared by all subcommands
+#[derive(Args, Debug)]
+struct GlobalArgs {
+    /// Source code path; repeat to search several roots in order
+    #[arg(short, long, global = true)]
+    source: Vec<String>,
+
+    /// Coverage percentages for the good and medium classes, as GOOD,MEDIUM
+    #[arg(long, global = true, default_value = "80,50")]
+    thresholds: CoverageThresholds,
+
+    /// Configuration file with per-file thresholds and other settings
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<String>,
+
+    /// Log more detail (-v for debug, -vv for trace); COV2HTML_LOG overrides the level, e.g. COV2HTML_LOG=cov2html::coverage=error
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Log less (-q for warnings and errors only, -qq for errors only)
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    quiet: u8,
+
+    /// Fail on malformed coverage lines and missing or unreadable source files instead of skipping them
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Also count the source files matching this glob that were never covered (repeatable), e.g. 'fs/**/*.c'
+    #[arg(long, value_name = "GLOB", global = true)]
+    include_uncovered: Vec<String>,
+
+    /// Leave out files matched by a built-in preset (no-tests, no-generated); repeatable
+    #[arg(long = "preset", value_name = "PRESET", global = true)]
+    presets: Vec<ExclusionPreset>,
+
+    /// Lines each file's coverage is taken over: all, executable to leave out blank, comment and preprocessor lines, or dwarf for the lines with instructions in the line tables of --binary
+    #[arg(long, default_value = "executable", global = true)]
+    denominator: Denominator,
+
+    /// Kernel .config whose unset options mark `#ifdef CONFIG_X` blocks as not compiled, like `#if 0` ones (report, serve, summary)
+    #[arg(long, value_name = "PATH", global = true)]
+    kernel_config: Option<String>,
+
+    /// Report only on the files below this directory, with paths and totals relative to it (report, serve, summary)
+    #[arg(long, value_name = "DIR", global = true)]
+    root: Option<String>,
+
+    /// How report and summary print their totals on stdout (text, json)
+    #[arg(long, default_value = "text", global = true)]
+    summary_format: SummaryFormat,
+
+    /// Rewrite coverage paths starting with OLD to start with NEW (repeatable; the first match wins)
+    #[arg(long = "path-map", value_name = "OLD=NEW", global = true)]
+    path_maps: Vec<PathMapping>,
+
+    /// Don't strip the build directory of absolute coverage paths, which is otherwise detected from which paths name files under --source
+    #[arg(long, global = true)]
+    no_detect_prefix: bool,
+
+    /// Match coverage paths to source files without regard to letter case, for coverage recorded on a case-insensitive file system (needs --source)
+    #[arg(long, global = true)]
+    ignore_case_paths: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate an HTML coverage report
+    Report(Box<ReportArgs>),
+
+    /// Merge several coverage files into one
+    Merge {
+        /// Coverage files to merge, or directories whose files are all merged
+        #[arg(required = true)]
+        inputs: Vec<String>,
+
+        /// Merged coverage output path
+        #[arg(short, long)]
+        output: String,
+
+        /// Output format (text, lcov, json)
+        #[arg(short, long, default_value = "text")]
+        format: OutputFormat,
+
+        /// Keep per-line hit counts (summed across inputs) in the output
+        #[arg(long)]
+        counts: bool,
+
+        /// Worker threads used to read and merge the inputs [default: logical CPUs]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<NonZeroUsize>,
+    },
+
+    /// Keep only the lines covered by every input
+    Intersect {
+        /// Coverage files to intersect
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<String>,
+
+        /// Resulting coverage output path
+        #[arg(short, long)]
+        output: String,
+
+        /// Output format (text, lcov, json)
+        #[arg(short, long, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// Keep the lines covered by the first input but by none of the others
+    Subtract {
+        /// Coverage file to subtract from
+        base: String,
+
+        /// Coverage files whose lines are removed
+        #[arg(required = true)]
+        others: Vec<String>,
+
+        /// Resulting coverage output path
+        #[arg(short, long)]
+        output: String,
+
+        /// Output format (text, lcov, json)
+        #[arg(short, long, default_value = "text")]
+        format: OutputFormat,
+    },
+
+    /// Show the lines gained and lost between two coverage files
+    Diff {
+        /// Coverage file of the earlier run
+        old: String,
+
+        /// Coverage file of the later run
+        new: String,
+
+        /// Write the gained and lost lines to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the exported diff (text, json)
+        #[arg(short, long, default_value = "text", value_parser = parse_diff_format)]
+        format: OutputFormat,
+
+        /// Also render an HTML diff report to this path (with source text if --source is given)
+        #[arg(long, value_name = "PATH")]
+        html: Option<String>,
+    },
+
+    /// Generate a report into a temporary directory and serve it over HTTP
+    Serve(ServeArgs),
+
+    /// Print coverage totals without generating a report
+    Summary {
+        /// Input coverage file path
+        #[arg(short, long)]
+        input: String,
+
+        /// Input format (auto, text, lcov, cobertura)
+        #[arg(long, default_value = "auto")]
+        format: InputFormat,
+
+        /// Count unchanged sources from the index kept in this directory instead of reading them,
+        /// e.g. the .cov2html-cache of an incremental report; created if missing. Without it every
+        /// covered source is read, so fast repeated runs need this option
+        #[arg(long, value_name = "DIR")]
+        cache_dir: Option<String>,
+
+        /// Binary with debug info the coverage was collected from, e.g. vmlinux, for
+        /// --denominator dwarf to count files in its line tables over the lines with instructions
+        #[arg(long, value_name = "PATH")]
+        binary: Option<String>,
+
+        #[command(flatten)]
+        gates: GateArgs,
+    },
+
+    /// Convert a coverage file to another format
+    Convert {
+        /// Input coverage file path
+        #[arg(short, long)]
+        input: String,
+
+        /// Converted coverage output path
+        #[arg(short, long)]
+        output: String,
+
+        /// Output format (text, lcov, json)
+        #[arg(short, long)]
+        format: OutputFormat,
+    },
+
+    /// Turn a file of raw kcov PCs into coverage of source lines, using the DWARF of the kernel they were collected from
+    Symbolize {
+        /// File of hex PCs, one per line, each optionally followed by a hit count
+        #[arg(short, long)]
+        input: String,
+
+        /// Kernel image with debug info the PCs were collected from, e.g. vmlinux
+        #[arg(short, long)]
+        binary: String,
+
+        /// Symbolized coverage output path
+        #[arg(short, long)]
+        output: String,
+
+        /// Output format (text, lcov, json)
+        #[arg(short, long, default_value = "text")]
+        format: OutputFormat,
+
+        /// Look up the PCs themselves rather than the call before each, for PCs that are not kcov return addresses
+        #[arg(long)]
+        exact_pcs: bool,
+
+        /// Worker threads used to look up the PCs [default: logical CPUs]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<NonZeroUsize>,
+
+        /// Directory the lines found are kept in for later runs against the same build of the
+        /// binary; created if missing [default: .cov2html-cache beside the output]
+        #[arg(long, value_name = "DIR")]
+        cache_dir: Option<String>,
+    },
+
+    /// Check a coverage file for problems without generating a report
+    Validate {
+        /// Input coverage file path
+        input: String,
+
+        /// Input format (auto, text, lcov, cobertura)
+        #[arg(long, default_value = "auto")]
+        format: InputFormat,
+    },
+
+    /// Write the drag-and-drop viewer page, which renders reports in the browser
+    Viewer {
+        /// WebAssembly build of the `wasm` feature to embed
+        #[arg(long, value_name = "PATH")]
+        wasm: String,
+
+        /// Viewer page output path
+        #[arg(short, long, default_value = "viewer.html")]
+        output: String,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: Shell,
+    },
+}
+
+#[derive(Args, Debug)]
+struct ReportArgs {
     /// Input binary file path
     #[arg(short, long)]
     input: String,
 
+    /// Input format (auto, text, lcov, cobertura); also used for the baseline
+    #[arg(long, default_value = "auto")]
+    format: InputFormat,
+
+    /// Binary with debug info the coverage was collected from, e.g. vmlinux, for
+    /// --denominator dwarf to count files in its line tables over the lines with instructions
+    #[arg(long, value_name = "PATH")]
+    binary: Option<String>,
+
     /// HTML output path
     #[arg(short, long)]
     output: String,
 
-    /// Source code path
+    /// Soft-wrap long source lines by default
+    #[arg(long)]
+    wrap: bool,
+
+    /// Default tab width used when displaying source code
+    #[arg(long, default_value_t = 8)]
+    tab_width: usize,
+
+    /// Write per-file data chunks next to the report and load them on demand
+    #[arg(long)]
+    split_data: bool,
+
+    /// Emit CSS, JavaScript and data as external files with a strict Content-Security-Policy
+    #[arg(long)]
+    external_assets: bool,
+
+    /// Pre-render every file into static HTML pages that work without JavaScript
+    #[arg(long)]
+    no_js: bool,
+
+    /// Minify the bundled CSS/JS and strip whitespace between tags from the generated HTML
+    #[arg(long)]
+    minify: bool,
+
+    /// Register a service worker that caches the report for browsing offline once opened over HTTP
+    /// (split and sharded reports always get one)
+    #[arg(long)]
+    service_worker: bool,
+
+    /// Split the report into a page per directory and an index once its file data exceeds this size (e.g. 200M; 0 never splits)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "256M")]
+    shard_size: u64,
+
+    /// Largest self-contained report (e.g. 200M); larger ones are written with --split-data and --external-assets instead
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_report_size: Option<u64>,
+
+    /// Keep the sources and file data in flight within about this much memory (e.g. 2G),
+    /// spilling file data to temporary files past it
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_memory: Option<u64>,
+
+    /// Link covered lines to an external code browser instead of embedding sources,
+    /// e.g. 'https://elixir.bootlin.com/linux/{rev}/source/{path}#L{line}'
+    #[arg(long)]
+    source_url_template: Option<String>,
+
+    /// Revision substituted for {rev} in the source URL template
+    #[arg(long, default_value = "latest")]
+    source_rev: String,
+
+    /// Render tabs and trailing whitespace visibly
+    #[arg(long)]
+    show_whitespace: bool,
+
+    /// Elide lines longer than this many characters behind an expand control
+    #[arg(long, value_name = "CHARS")]
+    truncate_lines: Option<usize>,
+
+    /// Highlight lines gained and lost against this earlier coverage file
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<String>,
+
+    /// Keep running and regenerate the report whenever the coverage file changes
+    #[arg(long)]
+    watch: bool,
+
+    /// With --watch, also regenerate when a covered source file changes
+    #[arg(long, requires = "watch")]
+    watch_sources: bool,
+
+    /// Open the generated report in the default browser
+    #[arg(long)]
+    open: bool,
+
+    /// Title shown in the report's page title and headings
+    #[arg(long, default_value = "Coverage Report")]
+    title: String,
+
+    /// Directory with report.html, report.css or report.js templates replacing the built-in ones
+    #[arg(long, value_name = "DIR")]
+    template_dir: Option<String>,
+
+    /// Output formats to write (html, json, lcov, text), comma-separated or repeated
+    #[arg(long, value_name = "FORMAT", value_parser = parse_renderer, value_delimiter = ',', default_value = "html")]
+    emit: Vec<&'static dyn ReportRenderer>,
+
+    /// Worker threads used to read sources and render pages [default: logical CPUs]
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Cache rendered files in the output directory and only render the files whose source or coverage changed since the last run
+    #[arg(long)]
+    incremental: bool,
+
+    /// Print wall time and memory per generation phase and the output size to stderr
+    #[arg(long)]
+    stats: bool,
+
+    #[command(flatten)]
+    gates: GateArgs,
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Input coverage file path
     #[arg(short, long)]
-    source: String,
+    input: String,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Write per-file data chunks and load them on demand
+    #[arg(long)]
+    split_data: bool,
+
+    /// Follow the coverage file as it grows and push new coverage to open reports
+    #[arg(long)]
+    live: bool,
+
+    /// Open the served report in the default browser
+    #[arg(long)]
+    open: bool,
+
+    /// Binary with debug info the coverage was collected from, e.g. vmlinux, for
+    /// --denominator dwarf to count files in its line tables over the lines with instructions
+    #[arg(long, value_name = "PATH")]
+    binary: Option<String>,
+
+    /// Directory to generate the served report in, which is kept; by default a temporary
+    /// directory removed when serving stops
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<String>,
+}
+
+/// Coverage requirements checked after a report or summary
+#[derive(Args, Debug)]
+struct GateArgs {
+    /// Exit with a non-zero status if overall coverage is below this percentage
+    #[arg(long, value_name = "PERCENT")]
+    fail_under: Option<f64>,
+
+    /// Exit with a non-zero status if any file's coverage is below this percentage
+    #[arg(long, value_name = "PERCENT")]
+    fail_under_file: Option<f64>,
+
+    /// Exit with a non-zero status if any file's coverage dropped below the value recorded in this file
+    #[arg(long, value_name = "PATH")]
+    ratchet: Option<String>,
+
+    /// Record improved and new per-file coverage in the ratchet file
+    #[arg(long, requires = "ratchet")]
+    update_ratchet: bool,
 }
 
 fn main() {
-    let args = Args::parse();
-    
-    match generate_report_from_file(&args.input, &args.source, &args.output) {
-        Ok(_) => println!("Coverage report generated successfully"),
-        Err(e) => println!("Error generating coverage report: {}", e),
+    let cli = Cli::parse();
+    init_logging(&cli.global);
+    let config = load_config(&cli.global);
+
+    match cli.command {
+        Command::Report(args) => run_report(&cli.global, &config, *args),
+        Command::Merge { inputs, output, format, counts, jobs } => {
+            run_merge(&cli.global, &inputs, &output, format, counts, jobs.map_or(0, NonZeroUsize::get))
+        }
+        Command::Intersect { inputs, output, format } => run_intersect(&cli.global, &inputs, &output, format),
+        Command::Subtract { base, others, output, format } => run_subtract(&cli.global, &base, &others, &output, format),
+        Command::Diff { old, new, output, format, html } => {
+            run_diff(&cli.global, &old, &new, output.as_deref(), format, html.as_deref())
+        }
+        Command::Serve(args) => run_serve(&cli.global, &config, args),
+        Command::Summary { input, format, cache_dir, binary, gates } => {
+            run_summary(&cli.global, &config, &input, format, cache_dir.as_deref(), binary.as_deref(), &gates)
+        }
+        Command::Convert { input, output, format } => run_convert(&cli.global, &input, &output, format),
+        Command::Symbolize { input, binary, output, format, exact_pcs, jobs, cache_dir } => {
+            let cache_dir = cache_dir.unwrap_or_else(|| {
+                let output_dir = Path::new(&output).parent().unwrap_or(Path::new(""));
+                output_dir.join(CACHE_DIR).to_string_lossy().into_owned()
+            });
+            run_symbolize(&cli.global, &input, &binary, &output, format, !exact_pcs, jobs.map_or(0, NonZeroUsize::get), &cache_dir)
+        }
+        Command::Validate { input, format } => run_validate(&cli.global, &input, format),
+        Command::Viewer { wasm, output } => run_viewer(&wasm, &output),
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "cov2html", &mut io::stdout());
+        }
+    }
+}
+
+/// Number of warnings logged so far, including ones filtered from the output
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts warnings on their way to env_logger
+struct CountingLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for CountingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Warn {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Sends log records to stderr at the level chosen with -v/-q or COV2HTML_LOG
+fn init_logging(global: &GlobalArgs) {
+    let level = match global.verbose as i16 - global.quiet as i16 {
+        ..=-2 => LevelFilter::Error,
+        -1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    let inner = env_logger::Builder::new()
+        .filter_level(level)
+        .parse_env("COV2HTML_LOG")
+        .format_timestamp(None)
+        .build();
+
+    // Warnings are always let through so they can be counted
+    log::set_max_level(inner.filter().max(LevelFilter::Warn));
+    log::set_boxed_logger(Box::new(CountingLogger { inner })).expect("logger already initialized");
+}
+
+/// Returns the source roots, exiting with a usage error if none were given
+fn require_source(global: &GlobalArgs) -> &[String] {
+    if global.source.is_empty() {
+        Cli::command()
+            .error(ErrorKind::MissingRequiredArgument, "the following required argument was not provided: --source <SOURCE>")
+            .exit();
+    }
+
+    &global.source
+}
+
+/// The --root subtree without surrounding slashes; `None` for the whole tree
+fn subtree(global: &GlobalArgs) -> Option<String> {
+    global.root.as_deref()
+        .map(|root| normalize_separators(root).trim_matches('/').to_string())
+        .filter(|root| !root.is_empty())
+}
+
+/// Loads the configuration file if one was given, exiting with an error message on failure
+fn load_config(global: &GlobalArgs) -> Config {
+    match global.config.as_deref().map(Config::load) {
+        None => Config::default(),
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            error!("Error reading config file: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Reads the kernel .config given with --kernel-config, exiting with an
+/// error message on failure
+fn load_kernel_config(global: &GlobalArgs) -> Option<KernelConfig> {
+    global.kernel_config.as_deref().map(|path| KernelConfig::read(path).unwrap_or_else(|e| {
+        error!("Error reading kernel config {}: {}", path, e);
+        process::exit(1);
+    }))
+}
+
+/// Parses and remaps a coverage file, exiting with an error message on failure
+fn load_coverage(global: &GlobalArgs, path: &str, format: InputFormat) -> HashMap<String, LineSet> {
+    match parse_coverage_file_with_warnings(path, format, global.strict, &mut Vec::new()) {
+        Ok(coverage_map) => {
+            let coverage_map = remap_coverage(coverage_map, &global.path_maps);
+            let prefixes = build_prefixes(global, &coverage_map);
+            let coverage_map = remap_coverage(coverage_map, &prefixes);
+            match merge_aliased_files(&coverage_map, &global.source, global.ignore_case_paths, 0) {
+                Some(merged) => merged,
+                None => coverage_map,
+            }
+        }
+        Err(e) => {
+            error!("Error reading coverage file {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Reads the line tables of the binary `--denominator dwarf` counts over,
+/// with the paths remapped like those of the coverage, exiting with an error
+/// message on failure or when --binary and the denominator disagree
+fn load_line_tables(global: &GlobalArgs, binary: Option<&str>) -> LineTables {
+    let binary = match (global.denominator, binary) {
+        (Denominator::Dwarf, Some(binary)) => binary,
+        (Denominator::Dwarf, None) => {
+            error!("--denominator dwarf needs --binary to read the line tables from (report, summary, serve)");
+            process::exit(1);
+        }
+        (_, Some(_)) => {
+            error!("--binary is only read with --denominator dwarf");
+            process::exit(1);
+        }
+        (_, None) => return LineTables::new(),
+    };
+    match read_line_tables(binary) {
+        Ok(line_tables) => {
+            let line_tables = remap_coverage(line_tables, &global.path_maps);
+            let prefixes = build_prefixes(global, &line_tables);
+            let line_tables = remap_coverage(line_tables, &prefixes);
+            match subtree(global) {
+                Some(root) => focus_coverage(line_tables, &root),
+                None => line_tables,
+            }
+        }
+        Err(e) => {
+            error!("Error reading line tables: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Mappings stripping the build prefixes detected under --source, if any
+fn build_prefixes(global: &GlobalArgs, coverage_map: &HashMap<String, LineSet>) -> Vec<PathMapping> {
+    if global.no_detect_prefix || global.source.is_empty() {
+        return Vec::new();
+    }
+
+    let paths: Vec<&str> = coverage_map.keys().map(String::as_str).collect();
+    detect_build_prefixes(&paths, &global.source, 0)
+}
+
+fn run_report(global: &GlobalArgs, config: &Config, args: ReportArgs) {
+    let source = require_source(global);
+
+    let options = ReportOptions {
+        wrap_lines: args.wrap,
+        tab_width: args.tab_width,
+        split_data: args.split_data,
+        external_assets: args.external_assets,
+        no_js: args.no_js,
+        service_worker: args.service_worker,
+        minify: args.minify,
+        shard_size: (args.shard_size > 0).then_some(args.shard_size),
+        max_report_size: args.max_report_size,
+        max_memory: args.max_memory,
+        thresholds: global.thresholds,
+        source_url_template: args.source_url_template.clone(),
+        source_rev: args.source_rev.clone(),
+        show_whitespace: args.show_whitespace,
+        max_line_length: args.truncate_lines,
+        path_mappings: global.path_maps.clone(),
+        detect_build_prefix: !global.no_detect_prefix,
+        ignore_case_paths: global.ignore_case_paths,
+        goals: config.goals.clone(),
+        baseline: args.baseline.clone(),
+        coverage_modified: None,
+        checksums: Checksums::new(),
+        live: false,
+        jobs: args.jobs.map_or(0, NonZeroUsize::get),
+        strict: global.strict,
+        include_uncovered: global.include_uncovered.clone(),
+        root: subtree(global),
+        exclusions: global.presets.clone(),
+        denominator: global.denominator,
+        kernel_config: load_kernel_config(global),
+        line_tables: load_line_tables(global, args.binary.as_deref()),
+        title: args.title.clone(),
+        input_format: args.format,
+        // Watching regenerates into the same directory, so it always caches
+        cache_dir: (args.incremental || args.watch).then(|| format!("{}/{}", args.output, CACHE_DIR)),
+        template_dir: args.template_dir.clone(),
+        renderers: args.emit.clone(),
+        progress: Default::default(),
+        cancel: None,
+    };
+
+    if args.watch {
+        watch_report(global, source, &args, &options);
+    }
+
+    let report = ReportBuilder::new()
+        .coverage(&args.input)
+        .source_roots(source)
+        .output(&args.output)
+        .options(options);
+    match report.generate() {
+        Ok(summary) => {
+            info!("Coverage report generated successfully");
+            if args.stats {
+                print_stats(&summary, &args.output);
+            }
+            if global.summary_format == SummaryFormat::Json {
+                println!("{}", summary.to_json(&args.input, WARNINGS.load(Ordering::Relaxed)));
+            }
+            if args.open
+                && let Some(page) = summary.outputs.iter().find(|path| path.ends_with(".html"))
+            {
+                open_in_browser(page);
+            }
+            check_coverage_gates(config, &summary, &args.gates);
+        }
+        Err(e) => {
+            error!("Error generating coverage report: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints the time and memory spent per phase and the size of the report
+fn print_stats(summary: &CoverageSummary, output: &str) {
+    let mebibytes = |kib: Option<u64>| kib.map_or_else(|| "-".to_string(), |kib| format!("{:.1} MiB", kib as f64 / 1024.0));
+
+    eprintln!("{:<14}  {:>10}  {:>12}  {:>12}", "Phase", "Time", "RSS", "Peak RSS");
+    for phase in &summary.phases {
+        eprintln!("{:<14}  {:>9.3}s  {:>12}  {:>12}", phase.name, phase.elapsed.as_secs_f64(), mebibytes(phase.rss_kib), mebibytes(phase.peak_rss_kib));
+    }
+    let total: f64 = summary.phases.iter().map(|phase| phase.elapsed.as_secs_f64()).sum();
+    eprintln!("{:<14}  {:>9.3}s", "total", total);
+
+    let (files, bytes) = directory_size(Path::new(output));
+    eprintln!("Output: {:.1} MiB in {} files", bytes as f64 / (1024.0 * 1024.0), files);
+}
+
+/// Number of files below a directory and their total size in bytes
+fn directory_size(path: &Path) -> (usize, u64) {
+    let Ok(entries) = fs::read_dir(path) else {
+        return (0, 0);
+    };
+
+    entries.flatten().fold((0, 0), |(files, bytes), entry| match entry.metadata() {
+        Ok(metadata) if metadata.is_dir() => {
+            let (sub_files, sub_bytes) = directory_size(&entry.path());
+            (files + sub_files, bytes + sub_bytes)
+        }
+        Ok(metadata) => (files + 1, bytes + metadata.len()),
+        Err(_) => (files, bytes),
+    })
+}
+
+/// Opens a coverage file for incremental reading, exiting with an error message on failure
+fn open_live_report(global: &GlobalArgs, input: &str) -> LiveReport {
+    // Live records are remapped one at a time, so the build prefixes are
+    // detected from the file as it is now and mapped along with --path-map
+    let mut path_maps = global.path_maps.clone();
+    if !global.no_detect_prefix
+        && let Ok(coverage_map) = parse_coverage_file_with_warnings(input, InputFormat::Auto, false, &mut Vec::new())
+    {
+        path_maps.extend(build_prefixes(global, &remap_coverage(coverage_map, &global.path_maps)));
+    }
+
+    match LiveReport::open(input, &path_maps, subtree(global).as_deref()) {
+        Ok(report) => {
+            info!("Parsed coverage data for {} files", report.coverage_map().len());
+            report
+        }
+        Err(e) => {
+            error!("Error reading coverage file {}: {}", input, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_serve(global: &GlobalArgs, config: &Config, args: ServeArgs) {
+    let source = require_source(global);
+
+    let (work_dir, temp_dir) = match &args.output {
+        Some(output) => (output.clone(), None),
+        None => {
+            let temp_dir = TempDir::new(std::env::temp_dir().join(format!("cov2html-{}", process::id())).to_string_lossy().into_owned());
+            (temp_dir.0.clone(), Some(temp_dir))
+        }
+    };
+
+    let options = ReportOptions {
+        split_data: args.split_data,
+        thresholds: global.thresholds,
+        path_mappings: global.path_maps.clone(),
+        detect_build_prefix: !global.no_detect_prefix,
+        ignore_case_paths: global.ignore_case_paths,
+        goals: config.goals.clone(),
+        live: args.live,
+        strict: global.strict,
+        include_uncovered: global.include_uncovered.clone(),
+        root: subtree(global),
+        exclusions: global.presets.clone(),
+        denominator: global.denominator,
+        kernel_config: load_kernel_config(global),
+        line_tables: load_line_tables(global, args.binary.as_deref()),
+        cache_dir: Some(format!("{}/{}", work_dir, CACHE_DIR)),
+        coverage_modified: modification_times(std::slice::from_ref(&args.input))[0],
+        checksums: Checksums::new(),
+        service_worker: true,
+        ..ReportOptions::default()
+    };
+    let mut report = open_live_report(global, &args.input);
+    match generate_report(report.coverage_map(), &source, &work_dir, &options) {
+        Ok(summary) => report.set_generated(&summary),
+        Err(e) => {
+            error!("Error generating coverage report: {}", e);
+            drop(temp_dir);
+            process::exit(1);
+        }
+    }
+
+    let updates = args.live.then(LiveUpdates::default);
+    if let Some(updates) = updates.clone() {
+        let source = source.to_vec();
+        let work_dir = work_dir.clone();
+        thread::spawn(move || follow_coverage(report, &updates, &source, &work_dir, &options));
+    }
+
+    let address = format!("{}:{}", args.host, args.port);
+    println!("Serving coverage report at http://{}/ (press Ctrl+C to stop)", address);
+    if args.open {
+        open_in_browser(&format!("http://{}/", address));
+    }
+    if let Err(e) = serve_directory(Path::new(&work_dir), &address, updates) {
+        error!("Error serving coverage report on {}: {}", address, e);
+        drop(temp_dir);
+        process::exit(1);
+    }
+}
+
+/// Report directory `serve` generates into when not given one, removed when
+/// dropped or when Ctrl+C or a termination signal stops the server
+struct TempDir(String);
+
+impl TempDir {
+    fn new(path: String) -> TempDir {
+        let removed = path.clone();
+        if let Err(e) = ctrlc::set_handler(move || {
+            let _ = fs::remove_dir_all(&removed);
+            process::exit(130);
+        }) {
+            warn!("Could not handle Ctrl+C, so {} will be left behind: {}", path, e);
+        }
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// How often watched and live coverage files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Pushes coverage appended to the input to the served reports, generating
+/// the report again when new files show up; never returns
+fn follow_coverage(mut report: LiveReport, updates: &LiveUpdates, source: &[String], work_dir: &str, options: &ReportOptions) -> ! {
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+
+        match report.poll() {
+            Ok(LiveChange::Unchanged) => {}
+            Ok(LiveChange::Updated(update)) => {
+                updates.publish(&update);
+                updates.set_snapshot(report.snapshot_json());
+            }
+            Ok(LiveChange::NeedsRegeneration) => match generate_report(report.coverage_map(), &source, work_dir, options) {
+                Ok(summary) => {
+                    report.set_generated(&summary);
+                    updates.set_snapshot(None);
+                    updates.publish("{\"reload\":true}");
+                }
+                Err(e) => error!("Error generating coverage report: {}", e),
+            },
+            Err(e) => error!("Error reading coverage file: {}", e),
+        }
+    }
+}
+
+/// Regenerates the report every time one of the watched files changes,
+/// reading only the records appended to the coverage file; never returns
+fn watch_report(global: &GlobalArgs, source: &[String], args: &ReportArgs, options: &ReportOptions) -> ! {
+    let mut report = open_live_report(global, &args.input);
+    let mut watched: Vec<String> = std::iter::once(args.input.clone()).chain(args.baseline.clone()).collect();
+    let mut last_stamps = None;
+    let mut opened = false;
+
+    // Report paths are relative to the subtree, if any
+    let source_roots = match &options.root {
+        Some(root) => subtree_roots(source, root),
+        None => source.to_vec(),
+    };
+
+    loop {
+        let stamps = modification_times(&watched);
+        if last_stamps.as_ref() != Some(&stamps) {
+            let previously_watched = watched.clone();
+            if let Err(e) = report.poll() {
+                error!("Error reading coverage file {}: {}", args.input, e);
+            }
+
+            // Sources are stale only if they changed after the current coverage
+            let options = ReportOptions { coverage_modified: stamps[0], ..options.clone() };
+            match generate_report(report.coverage_map(), &source, &args.output, &options) {
+                Ok(summary) => {
+                    info!("Coverage report regenerated: {:.2}% covered; watching for changes", summary.coverage_pct());
+                    if args.open
+                        && !opened
+                        && let Some(page) = summary.outputs.iter().find(|path| path.ends_with(".html"))
+                    {
+                        open_in_browser(page);
+                        opened = true;
+                    }
+
+                    // Watch the sources that ended up in the report as well
+                    if args.watch_sources {
+                        watched.truncate(1 + args.baseline.is_some() as usize);
+                        watched.extend(summary.file_totals.keys()
+                            .filter_map(|path| resolve_source(&source_roots, path).map(|(_, full_path)| full_path)));
+                    }
+                }
+                Err(e) => error!("Error generating coverage report: {}; watching for changes", e),
+            }
+
+            // Keep the times from before generating, so changes made meanwhile trigger another run
+            last_stamps = Some(if watched == previously_watched { stamps } else { modification_times(&watched) });
+        }
+
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Opens a file or URL with the platform's default handler; failures are only reported
+fn open_in_browser(target: &str) {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        process::Command::new("open")
+    } else {
+        process::Command::new("xdg-open")
+    };
+
+    let result = command.arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(e) = result {
+        warn!("Failed to open {} in a browser: {}", target, e);
+    }
+}
+
+/// Returns the modification time of each path, or `None` for paths that cannot be read
+fn modification_times(paths: &[String]) -> Vec<Option<SystemTime>> {
+    paths.iter()
+        .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
+
+/// Prints every overall, per-file, directory goal or ratchet coverage
+/// minimum that is not met and exits with status 2 if there was any
+fn check_coverage_gates(config: &Config, summary: &CoverageSummary, gates: &GateArgs) {
+    let mut failed = false;
+
+    if let Some(minimum) = gates.fail_under
+        && summary.coverage_pct() < minimum
+    {
+        error!("Coverage {:.2}% is below the required {:.2}%", summary.coverage_pct(), minimum);
+        failed = true;
+    }
+
+    for (path, (covered, total)) in &summary.file_totals {
+        let Some(minimum) = config.file_threshold(path, gates.fail_under_file) else {
+            continue;
+        };
+        let coverage_pct = if *total > 0 { (*covered as f64 / *total as f64) * 100.0 } else { 0.0 };
+        if coverage_pct < minimum {
+            error!("{}: coverage {:.2}% is below the required {:.2}%", path, coverage_pct, minimum);
+            failed = true;
+        }
+    }
+
+    for result in evaluate_goals(&config.goals, &summary.file_totals) {
+        if !result.passed() {
+            let directory = if result.directory.is_empty() { "(all files)" } else { &result.directory };
+            error!("{}: coverage {:.2}% is below its goal of {:.2}%", directory, result.coverage_pct(), result.goal);
+            failed = true;
+        }
+    }
+
+    if let Some(ratchet_path) = gates.ratchet.as_deref() {
+        // A missing ratchet file is started from scratch when updating
+        let mut ratchet = match read_ratchet(ratchet_path) {
+            Ok(ratchet) => ratchet,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && gates.update_ratchet => Default::default(),
+            Err(e) => {
+                error!("Error reading ratchet file {}: {}", ratchet_path, e);
+                process::exit(1);
+            }
+        };
+
+        for regression in ratchet_regressions(&ratchet, &summary.file_totals) {
+            error!("{}: coverage {:.2}% regressed below the recorded {:.2}%", regression.path, regression.current, regression.recorded);
+            failed = true;
+        }
+
+        if gates.update_ratchet {
+            update_ratchet(&mut ratchet, &summary.file_totals);
+            if let Err(e) = write_ratchet(&ratchet, ratchet_path) {
+                error!("Error writing ratchet file {}: {}", ratchet_path, e);
+                process::exit(1);
+            }
+            info!("Updated ratchet file {}", ratchet_path);
+        }
+    }
+
+    if failed {
+        process::exit(2);
+    }
+}
+
+fn run_merge(global: &GlobalArgs, inputs: &[String], output: &str, format: OutputFormat, counts: bool, jobs: usize) {
+    let inputs = &expand_input_directories(inputs);
+    let coverage_counts = match read_coverage_counts(inputs, global.strict, &global.path_maps, jobs) {
+        Ok(coverage_counts) => coverage_counts,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+    let merged = merge_coverage_counts_parallel(&coverage_counts, jobs);
+    let merged = merge_aliased_counts(merged, &global.source, global.ignore_case_paths, jobs);
+
+    if let Err(e) = write_coverage_counts(&merged, output, format, counts) {
+        error!("Error writing merged coverage to {}: {}", output, e);
+        process::exit(1);
+    }
+
+    // Report how much each input adds on top of all the others
+    let unique = unique_line_contributions(&coverage_counts);
+    let width = inputs.iter().map(|input| input.len()).max().unwrap_or(0);
+    println!("{:<width$}  {:>10}  {:>10}", "Input", "Lines", "Unique", width = width);
+    for ((input, counts), unique) in inputs.iter().zip(&coverage_counts).zip(unique) {
+        let lines: usize = counts.values().map(|lines| lines.len()).sum();
+        println!("{:<width$}  {:>10}  {:>10}", input, lines, unique, width = width);
+    }
+
+    let total: usize = merged.values().map(|lines| lines.len()).sum();
+    info!("Merged {} inputs: {} files, {} covered lines", inputs.len(), merged.len(), total);
+}
+
+/// Replaces every directory among the inputs by the files in it, in path order
+fn expand_input_directories(inputs: &[String]) -> Vec<String> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        let Ok(entries) = fs::read_dir(input) else {
+            expanded.push(input.clone());
+            continue;
+        };
+        let mut files: Vec<String> = entries.flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        expanded.extend(files);
+    }
+    expanded
+}
+
+/// Writes the result of a set operation, exiting with an error message on failure
+fn write_result(coverage_map: &HashMap<String, LineSet>, output: &str, format: OutputFormat) {
+    if let Err(e) = write_coverage_file(coverage_map, output, format) {
+        error!("Error writing coverage to {}: {}", output, e);
+        process::exit(1);
+    }
+
+    let total: usize = coverage_map.values().map(|lines| lines.len()).sum();
+    info!("Wrote {} files, {} covered lines to {}", coverage_map.len(), total, output);
+}
+
+fn run_intersect(global: &GlobalArgs, inputs: &[String], output: &str, format: OutputFormat) {
+    let coverage_maps: Vec<_> = inputs.iter().map(|input| load_coverage(global, input, InputFormat::Auto)).collect();
+    write_result(&intersect_coverage(&coverage_maps), output, format);
+}
+
+fn run_subtract(global: &GlobalArgs, base: &str, others: &[String], output: &str, format: OutputFormat) {
+    let base_map = load_coverage(global, base, InputFormat::Auto);
+    let other_maps: Vec<_> = others.iter().map(|other| load_coverage(global, other, InputFormat::Auto)).collect();
+    write_result(&subtract_coverage(&base_map, &other_maps), output, format);
+}
+
+/// Parses the format of an exported diff, which LCOV cannot express
+fn parse_diff_format(format: &str) -> Result<OutputFormat, String> {
+    match format.parse()? {
+        OutputFormat::Lcov => Err("a coverage diff cannot be written as LCOV (expected text or json)".to_string()),
+        format => Ok(format),
+    }
+}
+
+fn run_diff(global: &GlobalArgs, old: &str, new: &str, output: Option<&str>, format: OutputFormat, html: Option<&str>) {
+    let diff = diff_coverage(&load_coverage(global, old, InputFormat::Auto), &load_coverage(global, new, InputFormat::Auto));
+
+    let result = match output {
+        Some(path) => File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write_coverage_diff(&diff, &mut out, format)?;
+            out.flush()
+        }),
+        None => write_coverage_diff(&diff, &mut io::stdout().lock(), format),
+    };
+    if let Err(e) = result {
+        error!("Error writing coverage diff: {}", e);
+        process::exit(1);
+    }
+
+    if let Some(html_path) = html {
+        if let Err(e) = write_diff_html(&diff, &global.source, html_path) {
+            error!("Error writing HTML diff report to {}: {}", html_path, e);
+            process::exit(1);
+        }
+        info!("Generated HTML diff report at {}", html_path);
+    }
+
+    info!("{} lines gained, {} lines lost in {} files", diff.gained(), diff.lost(), diff.files.len());
+}
+
+fn run_summary(global: &GlobalArgs, config: &Config, input: &str, format: InputFormat, cache_dir: Option<&str>, binary: Option<&str>, gates: &GateArgs) {
+    let source = require_source(global);
+    let line_tables = load_line_tables(global, binary);
+    let kernel_config = load_kernel_config(global);
+    let (coverage_map, source) = match subtree(global) {
+        Some(root) => (focus_coverage(load_coverage(global, input, format), &root), subtree_roots(source, &root)),
+        None => (load_coverage(global, input, format), source.to_vec()),
+    };
+    let coverage_map = include_uncovered_files(&coverage_map, &source, &global.include_uncovered);
+    let summary = match cache_dir {
+        Some(cache_dir) => summarize_coverage_cached(&coverage_map, &source, &global.presets, global.denominator, kernel_config.as_ref(), &line_tables, 0, cache_dir).unwrap_or_else(|e| {
+            error!("Error using source index in {}: {}", cache_dir, e);
+            process::exit(1);
+        }),
+        None => summarize_coverage(&coverage_map, &source, &global.presets, global.denominator, kernel_config.as_ref(), &line_tables, 0),
+    };
+    if global.strict && summary.missing_files > 0 {
+        error!("{} source files are missing or unreadable", summary.missing_files);
+        process::exit(1);
+    }
+
+    match global.summary_format {
+        SummaryFormat::Text => {
+            println!("Files: {} ({} missing sources)", summary.files, summary.missing_files);
+            println!("Coverage: {} of {} lines covered ({:.2}%)",
+                summary.covered_lines, summary.total_lines, summary.coverage_pct());
+        }
+        SummaryFormat::Json => println!("{}", summary.to_json(input, WARNINGS.load(Ordering::Relaxed))),
+    }
+    check_coverage_gates(config, &summary, gates);
+}
+
+fn run_convert(global: &GlobalArgs, input: &str, output: &str, format: OutputFormat) {
+    let coverage_map = load_coverage(global, input, InputFormat::Auto);
+
+    if let Err(e) = write_coverage_file(&coverage_map, output, format) {
+        error!("Error writing converted coverage to {}: {}", output, e);
+        process::exit(1);
+    }
+
+    info!("Converted {} files to {}", coverage_map.len(), output);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_symbolize(global: &GlobalArgs, input: &str, binary: &str, output: &str, format: OutputFormat, return_addresses: bool, jobs: usize, cache_dir: &str) {
+    let coverage_counts = match symbolize_pc_file(input, binary, return_addresses, global.strict, jobs, Some(cache_dir), &mut Vec::new()) {
+        Ok(coverage_counts) => remap_coverage_counts(coverage_counts, &global.path_maps),
+        Err(e) => {
+            error!("Error symbolizing {}: {}", input, e);
+            process::exit(1);
+        }
+    };
+    // Headers are recorded once per translation unit, under whichever include
+    // path each one used
+    let coverage_counts = merge_aliased_counts(coverage_counts, &global.source, global.ignore_case_paths, jobs);
+
+    if let Err(e) = write_coverage_counts(&coverage_counts, output, format, false) {
+        error!("Error writing symbolized coverage to {}: {}", output, e);
+        process::exit(1);
+    }
+
+    let lines: usize = coverage_counts.values().map(|lines| lines.len()).sum();
+    info!("Symbolized {} into {} files, {} covered lines in {}", input, coverage_counts.len(), lines, output);
+}
+
+fn run_viewer(wasm_path: &str, output: &str) {
+    let wasm = match fs::read(wasm_path) {
+        Ok(wasm) => wasm,
+        Err(e) => {
+            error!("Error reading {}: {}", wasm_path, e);
+            process::exit(1);
+        }
+    };
+
+    let written = File::create(output).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write_viewer_html(&wasm, &mut out)?;
+        out.flush()
+    });
+    if let Err(e) = written {
+        error!("Error writing viewer to {}: {}", output, e);
+        process::exit(1);
+    }
+
+    info!("Wrote viewer to {}", output);
+}
+
+fn run_validate(global: &GlobalArgs, input: &str, format: InputFormat) {
+    let validation = match validate_coverage_file(input, format, &global.source, &global.path_maps) {
+        Ok(validation) => validation,
+        Err(e) => {
+            error!("Error reading coverage file {}: {}", input, e);
+            process::exit(1);
+        }
+    };
+
+    for problem in &validation.problems {
+        match problem.line {
+            Some(line) => println!("{}:{}: {}", input, line, problem.message),
+            None => println!("{}", problem.message),
+        }
+    }
+
+    for (root, count) in global.source.iter().zip(&validation.resolved) {
+        println!("{}: resolved {} files", root, count);
+    }
+
+    println!("{}: {} files, {} records ({} duplicates), {} problems",
+        input, validation.files, validation.records, validation.duplicates, validation.problems.len());
+
+    if !validation.problems.is_empty() {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serve_reads_the_line_tables_of_its_binary() {
+        // The test binary carries the DWARF of this file
+        let binary = std::env::current_exe().unwrap().to_string_lossy().into_owned();
+        let cli = Cli::try_parse_from(["cov2html", "serve", "--input", "cov.txt", "--denominator", "dwarf", "--binary", &binary]).unwrap();
+        let Command::Serve(args) = cli.command else {
+            panic!("parsed {:?}", cli.command);
+        };
+
+        let line_tables = load_line_tables(&cli.global, args.binary.as_deref());
+        assert!(line_tables.keys().any(|path| path.ends_with("src/main.rs")), "{:?}", line_tables.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn diffs_are_not_exported_as_lcov() {
+        assert!(Cli::try_parse_from(["cov2html", "diff", "old.txt", "new.txt", "--format", "json"]).is_ok());
+        let error = Cli::try_parse_from(["cov2html", "diff", "old.txt", "new.txt", "--format", "lcov"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+        assert!(error.to_string().contains("cannot be written as LCOV"), "{}", error);
     }
 }
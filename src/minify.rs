@@ -0,0 +1,184 @@
+//! Whitespace stripping for `--minify`: the report's CSS and JavaScript are
+//! shortened once per run, and pages are streamed through `MinifyHtml`.
+//! Only whitespace that cannot change how a page looks or behaves is
+//! removed, so none of this needs a parser.
+
+use std::io::{self, Write};
+
+/// Removes comments and the whitespace around punctuation from CSS
+pub(crate) fn minify_css(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    let mut space = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = '\0';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                space = true;
+            }
+            c if c.is_whitespace() => space = true,
+            c => {
+                // A space before `:` is a descendant selector, so only the
+                // space after it goes
+                if space && !out.is_empty() && !out.ends_with(['{', '}', ';', ',', '>', ':']) && !matches!(c, '{' | '}' | ';' | ',' | '>') {
+                    out.push(' ');
+                }
+                space = false;
+                if c == '}' && out.ends_with(';') {
+                    out.pop();
+                }
+                out.push(c);
+                if c == '"' || c == '\'' {
+                    let mut escaped = false;
+                    for s in chars.by_ref() {
+                        out.push(s);
+                        if s == c && !escaped {
+                            break;
+                        }
+                        escaped = s == '\\' && !escaped;
+                    }
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Removes indentation, blank lines and whole-line comments from
+/// JavaScript. Line breaks are kept, so automatic semicolon insertion sees
+/// the same code, and lines inside template literals are left alone.
+pub(crate) fn minify_js(js: &str) -> String {
+    let mut out = String::with_capacity(js.len());
+    let mut in_template = false;
+
+    for line in js.lines() {
+        let starts_in_template = in_template;
+        let mut escaped = false;
+        for c in line.chars() {
+            if c == '`' && !escaped {
+                in_template = !in_template;
+            }
+            escaped = c == '\\' && !escaped;
+        }
+
+        if starts_in_template {
+            out.push_str(line);
+        } else {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Elements whose contents are written as they are
+const RAW_ELEMENTS: [&[u8]; 4] = [b"pre", b"script", b"style", b"textarea"];
+
+/// Where `MinifyHtml` is in the markup written so far
+enum HtmlState {
+    Text,
+    /// Inside a tag, with its name (`/name` for end tags), whether the
+    /// name is complete and the quote of the attribute value it is in
+    Tag { name: Vec<u8>, named: bool, quote: Option<u8> },
+    /// Inside a raw element, with how much of its end tag has been seen
+    Raw { end: Vec<u8>, matched: usize },
+}
+
+/// Writes HTML to `out`, dropping the line breaks and indentation between
+/// tags (outside `pre`, `script`, `style` and `textarea`), or passes it
+/// through unchanged when not `enabled`
+pub(crate) struct MinifyHtml<W: Write> {
+    out: W,
+    enabled: bool,
+    state: HtmlState,
+    /// Whitespace right after a tag, dropped if only another tag follows
+    pending: Vec<u8>,
+    after_tag: bool,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> MinifyHtml<W> {
+    pub(crate) fn new(out: W, enabled: bool) -> MinifyHtml<W> {
+        MinifyHtml { out, enabled, state: HtmlState::Text, pending: Vec::new(), after_tag: false, buf: Vec::new() }
+    }
+}
+
+impl<W: Write> Write for MinifyHtml<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        if !self.enabled {
+            return self.out.write(bytes);
+        }
+
+        for &b in bytes {
+            match &mut self.state {
+                HtmlState::Text => match b {
+                    b'<' => {
+                        // Whitespace between two tags goes if it breaks a line
+                        if !(self.after_tag && self.pending.contains(&b'\n')) {
+                            self.buf.append(&mut self.pending);
+                        }
+                        self.pending.clear();
+                        self.buf.push(b);
+                        self.state = HtmlState::Tag { name: Vec::new(), named: false, quote: None };
+                    }
+                    b' ' | b'\t' | b'\r' | b'\n' if self.after_tag => self.pending.push(b),
+                    _ => {
+                        self.buf.append(&mut self.pending);
+                        self.buf.push(b);
+                        self.after_tag = false;
+                    }
+                },
+                HtmlState::Tag { name, named, quote } => {
+                    self.buf.push(b);
+                    match (*quote, b) {
+                        (Some(q), b) if b == q => *quote = None,
+                        (Some(_), _) => {}
+                        (None, b'"' | b'\'') => *quote = Some(b),
+                        (None, b'>') => {
+                            self.state = match RAW_ELEMENTS.iter().find(|raw| name.eq_ignore_ascii_case(raw)) {
+                                Some(raw) => HtmlState::Raw { end: [b"</", *raw].concat(), matched: 0 },
+                                None => HtmlState::Text,
+                            };
+                            self.after_tag = true;
+                        }
+                        (None, b) if !*named && (b.is_ascii_alphanumeric() || (b == b'/' && name.is_empty())) => name.push(b),
+                        (None, _) => *named = true,
+                    }
+                }
+                HtmlState::Raw { end, matched } => {
+                    self.buf.push(b);
+                    if b.eq_ignore_ascii_case(&end[*matched]) {
+                        *matched += 1;
+                    } else {
+                        *matched = usize::from(b == b'<');
+                    }
+                    if *matched == end.len() {
+                        self.state = HtmlState::Tag { name: end[1..].to_vec(), named: true, quote: None };
+                    }
+                }
+            }
+        }
+
+        self.out.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
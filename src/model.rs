@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead};
+use std::ops::{Bound, RangeBounds};
+#[cfg(feature = "async")]
+use std::panic;
+
+use crate::coverage::{count_lines, parse_coverage_counts, CoverageCounts, Language};
+use crate::executable::{line_counts, non_code_lines, uncounted_lines, Denominator};
+use crate::lines::LineSet;
+#[cfg(feature = "async")]
+use crate::parser::parse_coverage_bytes;
+use crate::parser::{parse_coverage_reader, InputFormat};
+use crate::source::SourceTree;
+
+/// Coverage of one source line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCoverage {
+    /// 1-based line number
+    pub line: u32,
+    /// Times the line was hit; 1 for coverage data without counts
+    pub hits: u64,
+}
+
+/// Coverage of one source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCoverage {
+    /// Path relative to the source root
+    pub path: String,
+    /// Covered lines, sorted by line number
+    pub lines: Vec<LineCoverage>,
+    /// Number of lines in the source file, once resolved against a source tree
+    pub total_lines: Option<usize>,
+    /// Number of those lines with code, the denominator of its coverage:
+    /// blank, comment and preprocessor lines are left out unless covered
+    pub code_lines: Option<usize>,
+    /// Number of covered lines counted toward its coverage, once resolved:
+    /// those excluded by marker comments are left out
+    pub counted_lines: Option<usize>,
+}
+
+impl FileCoverage {
+    /// Creates the coverage of a file without covered lines
+    pub fn new(path: &str) -> FileCoverage {
+        FileCoverage {
+            path: path.to_string(),
+            lines: Vec::new(),
+            total_lines: None,
+            code_lines: None,
+            counted_lines: None,
+        }
+    }
+
+    /// Number of covered lines
+    pub fn covered_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Hit count of a line, or `None` if it was not covered
+    pub fn hits(&self, line: u32) -> Option<u64> {
+        self.lines.binary_search_by_key(&line, |covered| covered.line)
+            .ok()
+            .map(|index| self.lines[index].hits)
+    }
+
+    /// Whether a line was covered
+    pub fn is_covered(&self, line: u32) -> bool {
+        self.hits(line).is_some()
+    }
+
+    /// Covered lines within a range of line numbers
+    pub fn lines_in<R: RangeBounds<u32>>(&self, range: R) -> &[LineCoverage] {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.lines.partition_point(|covered| covered.line < *start),
+            Bound::Excluded(start) => self.lines.partition_point(|covered| covered.line <= *start),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.lines.partition_point(|covered| covered.line <= *end),
+            Bound::Excluded(end) => self.lines.partition_point(|covered| covered.line < *end),
+            Bound::Unbounded => self.lines.len(),
+        };
+
+        &self.lines[start..end.max(start)]
+    }
+
+    /// Numbers of the covered lines, in order
+    pub fn covered_line_numbers(&self) -> impl Iterator<Item = u32> + '_ {
+        self.lines.iter().map(|covered| covered.line)
+    }
+
+    /// Numbers of the lines that were not covered, in order, including blank
+    /// and comment lines. Empty until the total number of lines is known.
+    pub fn uncovered_lines(&self) -> impl Iterator<Item = u32> + '_ {
+        let total = self.total_lines.unwrap_or(0) as u32;
+        let mut covered = self.lines.iter().map(|covered| covered.line).peekable();
+        (1..=total).filter(move |line| {
+            while covered.next_if(|covered| covered < line).is_some() {}
+            covered.peek() != Some(line)
+        })
+    }
+
+    /// Coverage percentage of the lines with code, once their number is known
+    pub fn coverage_pct(&self) -> Option<f64> {
+        let mut totals = CoverageTotals::default();
+        totals.add(self)?;
+        Some(totals.coverage_pct())
+    }
+
+    /// Adds hits to a line, inserting it if it was not covered yet
+    pub fn add_hits(&mut self, line: u32, hits: u64) {
+        match self.lines.binary_search_by_key(&line, |covered| covered.line) {
+            Ok(index) => self.lines[index].hits += hits,
+            Err(index) => self.lines.insert(index, LineCoverage { line, hits }),
+        }
+    }
+}
+
+/// Covered and total lines of a set of files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoverageTotals {
+    /// Files counted
+    pub files: usize,
+    /// Covered lines in the counted files
+    pub covered_lines: usize,
+    /// Lines with code in the counted files
+    pub total_lines: usize,
+    /// All lines of the counted files, the non-code ones included
+    pub source_lines: usize,
+}
+
+impl CoverageTotals {
+    /// Coverage percentage
+    pub fn coverage_pct(&self) -> f64 {
+        if self.total_lines > 0 {
+            (self.covered_lines as f64 / self.total_lines as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Counts a file whose number of lines is known
+    fn add(&mut self, file: &FileCoverage) -> Option<()> {
+        let (source_lines, code_lines) = file.total_lines.zip(file.code_lines)?;
+        self.files += 1;
+        self.covered_lines += file.counted_lines.unwrap_or(file.covered_lines());
+        self.total_lines += code_lines;
+        self.source_lines += source_lines;
+        Some(())
+    }
+}
+
+/// Coverage of a set of source files, keyed by path.
+///
+/// This is the structured form of the `path:line[:count]` data the rest of
+/// the crate works on as plain maps; use `from_counts`/`to_counts` and
+/// `from_map`/`to_map` to convert between the two.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    files: BTreeMap<String, FileCoverage>,
+}
+
+impl CoverageReport {
+    /// Creates an empty report
+    pub fn new() -> CoverageReport {
+        CoverageReport::default()
+    }
+
+    /// Parses a coverage file; malformed lines are skipped with a warning,
+    /// or fail the parse when `strict`
+    pub fn parse(file_path: &str, strict: bool) -> io::Result<CoverageReport> {
+        Ok(CoverageReport::from_counts(&parse_coverage_counts(file_path, strict)?))
+    }
+
+    /// Parses coverage data already in memory, e.g. where there is no file
+    /// system; `name` labels warnings and helps detect the format
+    pub fn parse_reader(input: &mut dyn BufRead, name: &str, format: InputFormat, strict: bool) -> io::Result<CoverageReport> {
+        Ok(CoverageReport::from_counts(&parse_coverage_reader(input, name, format, strict, &mut Vec::new())?))
+    }
+
+    /// Like `parse`, reading the file without blocking and parsing it on
+    /// tokio's blocking thread pool
+    #[cfg(feature = "async")]
+    pub async fn parse_async(file_path: &str, strict: bool) -> io::Result<CoverageReport> {
+        let contents = tokio::fs::read(file_path).await?;
+        let name = file_path.to_string();
+        let parse = move || {
+            parse_coverage_bytes(&contents, &name, InputFormat::Auto, strict, &mut Vec::new()).map(|counts| CoverageReport::from_counts(&counts))
+        };
+        tokio::task::spawn_blocking(parse)
+            .await
+            .unwrap_or_else(|e| panic::resume_unwind(e.into_panic()))
+    }
+
+    /// Builds a report from hit counts per line
+    pub fn from_counts(coverage_counts: &CoverageCounts) -> CoverageReport {
+        let mut report = CoverageReport::new();
+        for (path, lines) in coverage_counts {
+            let file = report.file_entry(path);
+            for (line, hits) in lines {
+                file.add_hits(*line, *hits);
+            }
+        }
+
+        report
+    }
+
+    /// Builds a report from covered lines, counting one hit per line
+    pub fn from_map(coverage_map: &HashMap<String, LineSet>) -> CoverageReport {
+        let mut report = CoverageReport::new();
+        for (path, lines) in coverage_map {
+            let file = report.file_entry(path);
+            for line in lines {
+                file.add_hits(line, 1);
+            }
+        }
+
+        report
+    }
+
+    /// Hit counts per line, as used by the merge and export functions
+    pub fn to_counts(&self) -> CoverageCounts {
+        self.files.values()
+            .map(|file| (file.path.clone(), file.lines.iter().map(|covered| (covered.line, covered.hits)).collect()))
+            .collect()
+    }
+
+    /// Covered lines per file, as used by report generation
+    pub fn to_map(&self) -> HashMap<String, LineSet> {
+        self.files.values()
+            .map(|file| (file.path.clone(), file.lines.iter().map(|covered| covered.line).collect()))
+            .collect()
+    }
+
+    /// Files in path order
+    pub fn files(&self) -> impl Iterator<Item = &FileCoverage> {
+        self.files.values()
+    }
+
+    /// Path and coverage of every covered line, in path and line order
+    pub fn iter_covered(&self) -> impl Iterator<Item = (&str, LineCoverage)> {
+        self.files().flat_map(|file| file.lines.iter().map(move |covered| (file.path.as_str(), *covered)))
+    }
+
+    /// `(path, line)` of every line that was not covered, in path and line
+    /// order; only files whose number of lines is known contribute
+    pub fn iter_uncovered(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.files().flat_map(|file| file.uncovered_lines().map(move |line| (file.path.as_str(), line)))
+    }
+
+    /// Coverage of one file
+    pub fn file(&self, path: &str) -> Option<&FileCoverage> {
+        self.files.get(path)
+    }
+
+    /// Whether a line of a file was covered
+    pub fn is_covered(&self, path: &str, line: u32) -> bool {
+        self.file(path).is_some_and(|file| file.is_covered(line))
+    }
+
+    /// Covered lines of a file within a range of line numbers, e.g. the
+    /// lines of a function; `None` if the file is not in the report
+    pub fn coverage_for_range<R: RangeBounds<u32>>(&self, path: &str, range: R) -> Option<&[LineCoverage]> {
+        self.file(path).map(|file| file.lines_in(range))
+    }
+
+    /// Coverage of one file, added without covered lines if it is not in the report yet
+    pub fn file_entry(&mut self, path: &str) -> &mut FileCoverage {
+        if !self.files.contains_key(path) {
+            self.files.insert(path.to_string(), FileCoverage::new(path));
+        }
+        self.files.get_mut(path).expect("file was just added")
+    }
+
+    /// Number of files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the report has no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Adds the hits of another report to this one
+    pub fn merge(&mut self, other: &CoverageReport) {
+        for other_file in other.files() {
+            let file = self.file_entry(&other_file.path);
+            for covered in &other_file.lines {
+                file.add_hits(covered.line, covered.hits);
+            }
+            if file.total_lines.is_none() {
+                file.total_lines = other_file.total_lines;
+                file.code_lines = other_file.code_lines;
+                file.counted_lines = other_file.counted_lines;
+            }
+        }
+    }
+
+    /// Counts the lines, and those with code, of each file in the source
+    /// tree, e.g. the first of a list of source roots containing it. Returns
+    /// the paths of the files that could not be read.
+    pub fn resolve_totals(&mut self, sources: &dyn SourceTree, denominator: Denominator) -> Vec<String> {
+        let mut missing = Vec::new();
+        for file in self.files.values_mut() {
+            let content = sources.find(&file.path)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+                .and_then(|(_, full_path)| sources.read(&full_path));
+            match content {
+                Ok(content) => {
+                    let total_lines = count_lines(&content);
+                    let language = Language::detect(&file.path);
+                    let non_code = non_code_lines(&content, language, denominator, None);
+                    let covered_lines: LineSet = file.covered_line_numbers().collect();
+                    let (counted_lines, code_lines) = line_counts(total_lines, &non_code, &uncounted_lines(&content, language, None), &covered_lines);
+                    file.total_lines = Some(total_lines);
+                    file.code_lines = Some(code_lines);
+                    file.counted_lines = Some(counted_lines);
+                }
+                Err(_) => missing.push(file.path.clone()),
+            }
+        }
+
+        missing
+    }
+
+    /// Totals over the files whose number of lines is known
+    pub fn totals(&self) -> CoverageTotals {
+        let mut totals = CoverageTotals::default();
+        for file in self.files() {
+            totals.add(file);
+        }
+
+        totals
+    }
+
+    /// Totals of every directory, including all the files below it, over
+    /// the files whose number of lines is known; the whole tree is `""`
+    pub fn directory_totals(&self) -> BTreeMap<String, CoverageTotals> {
+        let mut directories: BTreeMap<String, CoverageTotals> = BTreeMap::new();
+        for file in self.files() {
+            if directories.entry(String::new()).or_default().add(file).is_none() {
+                continue;
+            }
+            for (index, _) in file.path.match_indices('/') {
+                directories.entry(file.path[..index].to_string()).or_default().add(file);
+            }
+        }
+
+        directories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::MemorySources;
+
+    fn report(records: &[(&str, u32, u64)]) -> CoverageReport {
+        let mut coverage_counts = CoverageCounts::new();
+        for (path, line, hits) in records {
+            *coverage_counts.entry(path.to_string()).or_default().entry(*line).or_insert(0) += hits;
+        }
+        CoverageReport::from_counts(&coverage_counts)
+    }
+
+    #[test]
+    fn file_lines_are_kept_sorted() {
+        let mut file = FileCoverage::new("a.c");
+        file.add_hits(5, 1);
+        file.add_hits(2, 3);
+        file.add_hits(5, 2);
+        file.add_hits(9, 1);
+
+        assert_eq!(file.covered_line_numbers().collect::<Vec<u32>>(), [2, 5, 9]);
+        assert_eq!(file.hits(5), Some(3));
+        assert_eq!(file.hits(3), None);
+        assert_eq!(file.lines_in(3..=9).iter().map(|covered| covered.line).collect::<Vec<u32>>(), [5, 9]);
+        assert_eq!(file.lines_in(6..9), []);
+        assert_eq!(file.uncovered_lines().count(), 0);
+
+        file.total_lines = Some(6);
+        assert_eq!(file.uncovered_lines().collect::<Vec<u32>>(), [1, 3, 4, 6]);
+    }
+
+    #[test]
+    fn reports_convert_to_and_from_maps() {
+        let report = report(&[("b.c", 2, 1), ("a.c", 7, 4), ("a.c", 1, 1)]);
+
+        assert_eq!(report.iter_covered().map(|(path, covered)| (path, covered.line)).collect::<Vec<_>>(), [("a.c", 1), ("a.c", 7), ("b.c", 2)]);
+        assert_eq!(CoverageReport::from_counts(&report.to_counts()), report);
+        assert_eq!(report.to_map()["a.c"].iter().collect::<Vec<u32>>(), [1, 7]);
+        assert!(report.is_covered("a.c", 7));
+        assert!(!report.is_covered("c.c", 1));
+    }
+
+    #[test]
+    fn merged_reports_sum_their_hits() {
+        let mut merged = report(&[("a.c", 1, 2)]);
+        merged.merge(&report(&[("a.c", 1, 3), ("b.c", 4, 1)]));
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged.file("a.c").unwrap().hits(1), Some(5));
+        assert_eq!(merged.file("b.c").unwrap().hits(4), Some(1));
+    }
+
+    #[test]
+    fn totals_count_the_resolved_files_of_each_directory() {
+        let mut sources = MemorySources::new();
+        sources.insert("lib/a.c", "a();\n#if 0\nb();\n#endif\nc();\n");
+        sources.insert("lib/sub/b.c", "g();\nh();\n");
+        let mut report = report(&[("lib/a.c", 1, 1), ("lib/a.c", 3, 1), ("lib/sub/b.c", 1, 1), ("lib/sub/b.c", 2, 1), ("gone.c", 1, 1)]);
+
+        assert_eq!(report.totals(), CoverageTotals::default());
+        assert_eq!(report.resolve_totals(&sources, Denominator::All), ["gone.c"]);
+
+        let totals = report.totals();
+        assert_eq!((totals.files, totals.covered_lines, totals.total_lines, totals.source_lines), (2, 3, 6, 7));
+        // The covered line the preprocessor disables counts toward neither
+        let file = report.file("lib/a.c").unwrap();
+        assert_eq!((file.total_lines, file.code_lines, file.counted_lines), (Some(5), Some(4), Some(1)));
+        assert_eq!(report.file("gone.c").unwrap().coverage_pct(), None);
+
+        let directories = report.directory_totals();
+        assert_eq!(directories.keys().collect::<Vec<_>>(), ["", "lib", "lib/sub"]);
+        assert_eq!(directories[""], totals);
+        assert_eq!(directories["lib/sub"].coverage_pct(), 100.0);
+    }
+}
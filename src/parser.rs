@@ -0,0 +1,586 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use log::warn;
+
+use crate::error::Warning;
+use crate::lines::LineSet;
+
+/// Hit counts per covered line, keyed by file path
+pub type CoverageCounts = HashMap<String, HashMap<u32, u64>>;
+
+/// One thing a parser read from its input
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoverageRecord<'a> {
+    /// A file the coverage data lists, whether or not any of its lines were hit
+    File(&'a str),
+    /// Hits of a covered line of a file
+    Line { path: &'a str, line: u32, count: u64 },
+    /// A record that could not be parsed, and what is wrong with it
+    Malformed(String),
+}
+
+/// Reads coverage data in one input format into per-line hit counts.
+///
+/// Implementations are listed in `PARSERS`, which is what `--format` and
+/// format detection choose from; a new input format only needs a parser
+/// added there.
+pub trait CoverageParser: Send + Sync {
+    /// Name the parser is selected by with `--format`
+    fn name(&self) -> &'static str;
+
+    /// Whether a file looks like this format, judging by its path and the
+    /// first few kilobytes of its contents
+    fn detect(&self, file_path: &str, head: &str) -> bool;
+
+    /// Reads the records of `input` in order, passing each to `record` along
+    /// with the line of the input it is on; stops at the first error
+    /// `record` returns. Paths have their separators normalized, and lines
+    /// without hits are left out.
+    fn read_records(&self, input: &mut dyn BufRead, record: &mut dyn FnMut(usize, CoverageRecord) -> io::Result<()>) -> io::Result<()>;
+
+    /// Parses coverage data read from `input`, the file `file_path`. Malformed
+    /// records are skipped and collected as warnings, or fail the parse when
+    /// `strict`.
+    fn parse(&self, input: &mut dyn BufRead, file_path: &str, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<CoverageCounts> {
+        let mut coverage_counts = CoverageCounts::new();
+        self.read_records(input, &mut |line, record| {
+            match record {
+                CoverageRecord::File(path) => {
+                    if !coverage_counts.contains_key(path) {
+                        coverage_counts.insert(path.to_string(), HashMap::new());
+                    }
+                }
+                CoverageRecord::Line { path, line, count } => add_hits(&mut coverage_counts, path, line, count),
+                CoverageRecord::Malformed(message) => malformed(file_path, line, message, strict, warnings)?,
+            }
+            Ok(())
+        })?;
+
+        Ok(coverage_counts)
+    }
+
+    /// Like `parse` for data already in memory, e.g. a memory-mapped file;
+    /// parsers can override it to avoid copying lines out of `input`
+    fn parse_bytes(&self, input: &[u8], file_path: &str, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<CoverageCounts> {
+        self.parse(&mut &input[..], file_path, strict, warnings)
+    }
+}
+
+impl fmt::Debug for dyn CoverageParser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Registered parsers, in the order formats are detected; the plain text
+/// parser comes last as it accepts anything
+pub static PARSERS: &[&dyn CoverageParser] = &[
+    #[cfg(feature = "lcov")]
+    &LcovParser,
+    &CoberturaParser,
+    &TextParser,
+];
+
+/// Looks up a registered parser by name
+pub fn find_parser(name: &str) -> Option<&'static dyn CoverageParser> {
+    PARSERS.iter().copied().find(|parser| parser.name() == name)
+}
+
+/// Byte order mark some Windows tools write at the start of text files
+const BOM: char = '\u{feff}';
+
+/// Picks the first registered parser that recognizes the file
+pub fn detect_parser(file_path: &str, head: &str) -> &'static dyn CoverageParser {
+    let head = head.strip_prefix(BOM).unwrap_or(head);
+    PARSERS.iter().copied().find(|parser| parser.detect(file_path, head)).unwrap_or(&TextParser)
+}
+
+/// Format of a coverage input file
+#[derive(Debug, Clone, Copy, Default)]
+pub enum InputFormat {
+    /// Detected from the file name and contents
+    #[default]
+    Auto,
+    /// Read with the given parser
+    Parser(&'static dyn CoverageParser),
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(InputFormat::Auto);
+        }
+        find_parser(s).map(InputFormat::Parser).ok_or_else(|| {
+            let names = PARSERS.iter().map(|parser| parser.name()).collect::<Vec<&str>>().join(", ");
+            format!("unknown input format '{}' (expected auto, {})", s, names)
+        })
+    }
+}
+
+/// Parses a coverage file with the parser for `format`. Regular files are
+/// memory-mapped and parsed in place; pipes and the like are read through a
+/// buffer.
+pub fn parse_coverage_input(file_path: &str, format: InputFormat, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<CoverageCounts> {
+    let file = File::open(file_path)?;
+    #[cfg(not(target_family = "wasm"))]
+    if file.metadata()?.is_file() {
+        // SAFETY: the map is only read while parsing; coverage files are not
+        // expected to be truncated or rewritten while they are parsed
+        if let Ok(map) = unsafe { memmap2::Mmap::map(&file) } {
+            return parse_coverage_bytes(&map, file_path, format, strict, warnings);
+        }
+    }
+
+    parse_coverage_reader(&mut io::BufReader::new(file), file_path, format, strict, warnings)
+}
+
+/// Bytes of in-memory input looked at to detect its format, as much as a
+/// default `BufReader` holds
+const DETECT_BYTES: usize = 8 * 1024;
+
+/// Parses coverage data already in memory with the parser for `format`;
+/// `file_path` labels warnings and is used to detect the format
+pub fn parse_coverage_bytes(input: &[u8], file_path: &str, format: InputFormat, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<CoverageCounts> {
+    let parser = match format {
+        InputFormat::Parser(parser) => parser,
+        InputFormat::Auto => detect_parser(file_path, &String::from_utf8_lossy(&input[..input.len().min(DETECT_BYTES)])),
+    };
+
+    parser.parse_bytes(input, file_path, strict, warnings)
+}
+
+/// Parses coverage data read from `input` with the parser for `format`;
+/// `file_path` labels warnings and is used to detect the format
+pub fn parse_coverage_reader(
+    input: &mut dyn BufRead,
+    file_path: &str,
+    format: InputFormat,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> io::Result<CoverageCounts> {
+    let parser = match format {
+        InputFormat::Parser(parser) => parser,
+        InputFormat::Auto => detect_parser(file_path, &String::from_utf8_lossy(input.fill_buf()?)),
+    };
+
+    parser.parse(input, file_path, strict, warnings)
+}
+
+/// Parse the coverage file into a map of file paths to covered line numbers,
+/// detecting its format. Malformed lines are skipped with a warning, or fail
+/// the parse when `strict`.
+pub fn parse_coverage_file(file_path: &str, strict: bool) -> io::Result<HashMap<String, LineSet>> {
+    parse_coverage_file_with_warnings(file_path, InputFormat::Auto, strict, &mut Vec::new())
+}
+
+/// Like `parse_coverage_file` for a given input format, also collecting the
+/// skipped malformed lines
+pub fn parse_coverage_file_with_warnings(file_path: &str, format: InputFormat, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<HashMap<String, LineSet>> {
+    let counts = parse_coverage_counts_with_warnings(file_path, format, strict, warnings)?;
+    
+    Ok(counts
+        .into_iter()
+        .map(|(path, lines)| (path, lines.into_keys().collect()))
+        .collect())
+}
+
+/// Parse the coverage file into per-line hit counts, detecting its format.
+/// Text records are either `path:line` (one hit) or `path:line:count`;
+/// repeated records are summed.
+pub fn parse_coverage_counts(file_path: &str, strict: bool) -> io::Result<CoverageCounts> {
+    parse_coverage_counts_with_warnings(file_path, InputFormat::Auto, strict, &mut Vec::new())
+}
+
+/// Like `parse_coverage_counts` for a given input format, also collecting
+/// the skipped malformed lines
+pub fn parse_coverage_counts_with_warnings(file_path: &str, format: InputFormat, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<CoverageCounts> {
+    parse_coverage_input(file_path, format, strict, warnings)
+}
+
+/// Parses one `path:line[:count]` record, with the path's separators
+/// normalized. Surrounding whitespace, a trailing `\r` and a byte order
+/// mark are ignored. Returns `None` for blank lines, `#` comments and
+/// checksum records and a description of the problem for malformed ones.
+pub(crate) fn parse_coverage_record(line: &str) -> Result<Option<(Cow<'_, str>, u32, u64)>, String> {
+    let Some((path, line_part, count_part)) = split_record(line)? else {
+        return Ok(None);
+    };
+    
+    if line_part.trim() == CHECKSUM_ALGORITHM {
+        return match count_part.map(str::trim) {
+            Some(sum) if is_checksum(sum) => Ok(None),
+            _ => Err(format!("Invalid checksum in line: {}", line.trim())),
+        };
+    }
+    
+    let line_number = line_part.trim().parse::<u32>()
+        .map_err(|_| format!("Invalid line number: {}", line_part))?;
+    
+    let count = match count_part {
+        Some(count) => count.trim().parse::<u64>()
+            .map_err(|_| format!("Invalid hit count: {}", count))?,
+        None => 1,
+    };
+    
+    Ok(Some((normalize_separators(path), line_number, count)))
+}
+
+/// Path, second and optional third field of a text record
+type RecordFields<'a> = (&'a str, &'a str, Option<&'a str>);
+
+/// Splits a text record into its fields; the colon of a Windows drive
+/// letter belongs to the path
+fn split_record(line: &str) -> Result<Option<RecordFields<'_>>, String> {
+    let line = line.strip_prefix(BOM).unwrap_or(line).trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    
+    let drive = match line.as_bytes() {
+        [letter, b':', b'\\' | b'/', ..] if letter.is_ascii_alphabetic() => 2,
+        _ => 0,
+    };
+    let mut parts = line[drive..].split(':');
+    let (Some(path), Some(second), third, None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(format!("Invalid format in line: {}", line));
+    };
+    
+    Ok(Some((line[..drive + path.len()].trim_end(), second, third)))
+}
+
+/// Checksums of the sources coverage was recorded against, as lowercase
+/// hex SHA-256 keyed by file path
+pub type Checksums = HashMap<String, String>;
+
+/// Second field of a `path:sha256:<hex>` checksum record
+const CHECKSUM_ALGORITHM: &str = "sha256";
+
+fn is_checksum(sum: &str) -> bool {
+    sum.len() == 64 && sum.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Reads the `path:sha256:<hex>` checksum records of a text coverage file,
+/// with the paths' separators normalized. Other records are skipped, so
+/// files in other formats have no checksums.
+pub fn parse_checksums(file_path: &str) -> io::Result<Checksums> {
+    let mut checksums = Checksums::new();
+    let marker = format!(":{}:", CHECKSUM_ALGORITHM);
+    for line in io::BufReader::new(File::open(file_path)?).split(b'\n') {
+        let line = line?;
+        // Only the few checksum records are worth decoding
+        if !line.windows(marker.len()).any(|window| window == marker.as_bytes()) {
+            continue;
+        }
+        if let Ok(line) = std::str::from_utf8(&line)
+            && let Ok(Some((path, algorithm, Some(sum)))) = split_record(line)
+            && algorithm.trim() == CHECKSUM_ALGORITHM
+            && is_checksum(sum.trim())
+        {
+            checksums.insert(normalize_separators(path).into_owned(), sum.trim().to_ascii_lowercase());
+        }
+    }
+    
+    Ok(checksums)
+}
+
+/// Turns the `\` separators of paths recorded on Windows into `/`, so
+/// files land in the same tree whichever separators recorded them
+pub fn normalize_separators(path: &str) -> Cow<'_, str> {
+    if path.contains('\\') {
+        Cow::Owned(path.replace('\\', "/"))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Adds the hits of a record, allocating the path only for its first record
+pub(crate) fn add_hits(coverage_counts: &mut CoverageCounts, path: &str, line_number: u32, count: u64) {
+    let lines = match coverage_counts.get_mut(path) {
+        Some(lines) => lines,
+        None => coverage_counts.entry(path.to_string()).or_default(),
+    };
+    *lines.entry(line_number).or_insert(0) += count;
+}
+
+/// Handles a malformed record: an error when `strict`, otherwise a logged
+/// and collected warning
+pub(crate) fn malformed(file_path: &str, line: usize, message: String, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<()> {
+    if strict {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message)));
+    }
+
+    let warning = Warning::MalformedRecord { file: file_path.to_string(), line, message };
+    warn!("{}", warning);
+    warnings.push(warning);
+    Ok(())
+}
+
+/// `path:line` or `path:line:count` records, one per line, with blank
+/// lines and `#` comments in between; repeated records add up
+#[derive(Debug, Clone, Copy)]
+pub struct TextParser;
+
+impl CoverageParser for TextParser {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn detect(&self, _file_path: &str, _head: &str) -> bool {
+        true
+    }
+
+    fn read_records(&self, input: &mut dyn BufRead, record: &mut dyn FnMut(usize, CoverageRecord) -> io::Result<()>) -> io::Result<()> {
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            match parse_coverage_record(&line) {
+                Ok(Some((path, line_number, count))) => record(index + 1, CoverageRecord::Line { path: &path, line: line_number, count })?,
+                Ok(None) => {}
+                Err(message) => record(index + 1, CoverageRecord::Malformed(message))?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_bytes(&self, input: &[u8], file_path: &str, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<CoverageCounts> {
+        let mut coverage_counts = CoverageCounts::new();
+        let input = input.strip_suffix(b"\n").unwrap_or(input);
+        if input.is_empty() {
+            return Ok(coverage_counts);
+        }
+
+        // Lines are parsed where they are, split the way `BufRead::lines` does
+        for (index, line) in input.split(|&byte| byte == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = std::str::from_utf8(line)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))?;
+            match parse_coverage_record(line) {
+                Ok(Some((path, line_number, count))) => add_hits(&mut coverage_counts, &path, line_number, count),
+                Ok(None) => {}
+                Err(message) => malformed(file_path, index + 1, message, strict, warnings)?,
+            }
+        }
+
+        Ok(coverage_counts)
+    }
+}
+
+/// LCOV tracefiles as written by `geninfo` and `cov2html convert --format lcov`.
+/// Only `SF` and `DA` records are read; files with no hit lines are kept
+/// with zero covered lines.
+#[cfg(feature = "lcov")]
+#[derive(Debug, Clone, Copy)]
+pub struct LcovParser;
+
+#[cfg(feature = "lcov")]
+impl CoverageParser for LcovParser {
+    fn name(&self) -> &'static str {
+        "lcov"
+    }
+
+    fn detect(&self, file_path: &str, head: &str) -> bool {
+        file_path.ends_with(".info") || file_path.ends_with(".lcov")
+            || head.lines().find(|line| !line.trim().is_empty()).is_some_and(|line| line.starts_with("TN:") || line.starts_with("SF:"))
+    }
+
+    fn read_records(&self, input: &mut dyn BufRead, record: &mut dyn FnMut(usize, CoverageRecord) -> io::Result<()>) -> io::Result<()> {
+        let mut current: Option<String> = None;
+
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.strip_prefix(BOM).unwrap_or(&line).trim();
+            if let Some(path) = line.strip_prefix("SF:") {
+                let path = normalize_separators(path.trim()).into_owned();
+                record(index + 1, CoverageRecord::File(&path))?;
+                current = Some(path);
+            } else if line == "end_of_record" {
+                current = None;
+            } else if let Some(fields) = line.strip_prefix("DA:") {
+                let Some(path) = &current else {
+                    record(index + 1, CoverageRecord::Malformed(format!("DA record outside of a file: {}", line)))?;
+                    continue;
+                };
+                let mut fields = fields.split(',');
+                let line_number = fields.next().and_then(|field| field.trim().parse::<u32>().ok()).filter(|line_number| *line_number > 0);
+                let count = fields.next().and_then(|field| field.trim().parse::<u64>().ok());
+                match (line_number, count) {
+                    (Some(line_number), Some(count)) if count > 0 => {
+                        record(index + 1, CoverageRecord::Line { path, line: line_number, count })?;
+                    }
+                    (Some(_), Some(_)) => {}
+                    _ => record(index + 1, CoverageRecord::Malformed(format!("invalid DA record: {}", line)))?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Cobertura XML reports as written by gcovr and coverage.py. Lines are
+/// read from the `<lines>` of each `<class>`; the per-method copies are
+/// skipped so hits are not counted twice.
+#[derive(Debug, Clone, Copy)]
+pub struct CoberturaParser;
+
+impl CoverageParser for CoberturaParser {
+    fn name(&self) -> &'static str {
+        "cobertura"
+    }
+
+    fn detect(&self, file_path: &str, head: &str) -> bool {
+        file_path.ends_with(".xml") || head.trim_start().starts_with("<?xml") || head.contains("<coverage")
+    }
+
+    fn read_records(&self, input: &mut dyn BufRead, record: &mut dyn FnMut(usize, CoverageRecord) -> io::Result<()>) -> io::Result<()> {
+        let mut xml = String::new();
+        input.read_to_string(&mut xml)?;
+
+        let mut current: Option<String> = None;
+        let mut in_methods = false;
+        let mut rest = xml.as_str();
+        // Line of the input the tags are on, counted up to `counted`
+        let mut line = 1;
+        let mut counted = 0;
+
+        while let Some(start) = rest.find('<') {
+            let Some(end) = rest[start..].find('>') else {
+                break;
+            };
+            let tag = &rest[start + 1..start + end];
+            let offset = xml.len() - rest.len() + start;
+            line += xml[counted..offset].matches('\n').count();
+            counted = offset;
+            rest = &rest[start + end + 1..];
+
+            let name = tag.split_whitespace().next().unwrap_or("").trim_end_matches('/');
+            match name {
+                "class" => {
+                    let path = xml_attribute(tag, "filename").map(|path| normalize_separators(&path).into_owned());
+                    if let Some(path) = &path {
+                        record(line, CoverageRecord::File(path))?;
+                    }
+                    current = path;
+                }
+                "/class" => current = None,
+                "methods" => in_methods = !tag.ends_with('/'),
+                "/methods" => in_methods = false,
+                "line" if !in_methods => {
+                    let line_number = xml_attribute(tag, "number").and_then(|number| number.parse::<u32>().ok()).filter(|number| *number > 0);
+                    let hits = xml_attribute(tag, "hits").and_then(|hits| hits.parse::<u64>().ok());
+                    match (&current, line_number, hits) {
+                        (Some(path), Some(line_number), Some(hits)) if hits > 0 => {
+                            record(line, CoverageRecord::Line { path, line: line_number, count: hits })?;
+                        }
+                        (Some(_), Some(_), Some(_)) => {}
+                        _ => record(line, CoverageRecord::Malformed(format!("invalid line element: <{}>", tag)))?,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Value of an attribute of an XML start tag, with entities decoded
+fn xml_attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(index) = rest.find(name) {
+        let before = rest[..index].chars().next_back();
+        let after = rest[index + name.len()..].trim_start();
+        rest = &rest[index + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        let value = &value[..value.find(quote)?];
+        return Some(value
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(parser: &dyn CoverageParser, input: &str) -> (CoverageCounts, Vec<Warning>) {
+        let mut warnings = Vec::new();
+        let counts = parser.parse(&mut input.as_bytes(), "input", false, &mut warnings).unwrap();
+        (counts, warnings)
+    }
+
+    #[test]
+    fn text_records_add_up() {
+        let input = "\u{feff}# comment\nsrc/a.c:1\nsrc/a.c:1:4\n\nsrc\\b.c:2:3\r\nC:\\src\\c.c:7\nsrc/a.c:x\n";
+        let (counts, warnings) = parse(&TextParser, input);
+        assert_eq!(counts["src/a.c"], HashMap::from([(1, 5)]));
+        assert_eq!(counts["src/b.c"], HashMap::from([(2, 3)]));
+        assert_eq!(counts["C:/src/c.c"], HashMap::from([(7, 1)]));
+        assert!(matches!(warnings.as_slice(), [Warning::MalformedRecord { line: 7, .. }]));
+
+        let mut warnings = Vec::new();
+        assert_eq!(TextParser.parse_bytes(input.as_bytes(), "input", false, &mut warnings).unwrap(), counts);
+        assert!(TextParser.parse(&mut input.as_bytes(), "input", true, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn checksum_records_are_not_coverage() {
+        let sum = "0123456789abcdef".repeat(4);
+        assert_eq!(parse_coverage_record(&format!("src/a.c:sha256:{}", sum)), Ok(None));
+        assert!(parse_coverage_record("src/a.c:sha256:abc").is_err());
+    }
+
+    #[cfg(feature = "lcov")]
+    #[test]
+    fn lcov_keeps_files_without_hits() {
+        let input = "TN:\nSF:/src/a.c\nDA:1,2\nDA:2,0\nend_of_record\nSF:/src/b.c\nDA:3,0\nend_of_record\nDA:4,1\n";
+        let (counts, warnings) = parse(&LcovParser, input);
+        assert_eq!(counts["/src/a.c"], HashMap::from([(1, 2)]));
+        assert!(counts["/src/b.c"].is_empty());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(detect_parser("coverage.txt", input).name(), "lcov");
+    }
+
+    #[test]
+    fn cobertura_skips_method_lines() {
+        let input = r#"<?xml version="1.0"?>
+<coverage>
+  <class filename="src/a&amp;b.c">
+    <methods><method><lines><line number="1" hits="1"/></lines></method></methods>
+    <lines><line number="1" hits="1"/><line number="2" hits="0"/><line number="3" hits='2'/></lines>
+  </class>
+  <class filename="src/c.c">
+    <lines>
+      <line number="x" hits="1"/>
+    </lines>
+  </class>
+</coverage>
+"#;
+        let (counts, warnings) = parse(&CoberturaParser, input);
+        assert_eq!(counts["src/a&b.c"], HashMap::from([(1, 1), (3, 2)]));
+        assert!(counts["src/c.c"].is_empty());
+        assert!(matches!(warnings.as_slice(), [Warning::MalformedRecord { line: 9, .. }]));
+        assert_eq!(detect_parser("coverage.txt", input).name(), "cobertura");
+        assert_eq!(detect_parser("coverage.txt", "src/a.c:1\n").name(), "text");
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+/// A step of report generation, passed to the progress callback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Progress {
+    /// The files to report on are known
+    FilesDiscovered { files: usize },
+    /// The source of a file was read and counted; `done` of `total` files are
+    /// processed. Files are processed in parallel, so they arrive in no
+    /// particular order.
+    FileProcessed { path: String, done: usize, total: usize },
+    /// An output file was written
+    BytesWritten { path: String, bytes: u64 },
+}
+
+/// Callback invoked with the progress of report generation, from worker
+/// threads as well; does nothing by default
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    callback: Option<Arc<dyn Fn(Progress) + Send + Sync>>,
+}
+
+impl ProgressReporter {
+    /// Reports progress to `callback`
+    pub fn new(callback: impl Fn(Progress) + Send + Sync + 'static) -> ProgressReporter {
+        ProgressReporter { callback: Some(Arc::new(callback)) }
+    }
+
+    /// Sends progress over a channel; progress is dropped once the receiver
+    /// is gone
+    pub fn channel(sender: Sender<Progress>) -> ProgressReporter {
+        ProgressReporter::new(move |progress| {
+            let _ = sender.send(progress);
+        })
+    }
+
+    /// Reports a step, if anyone listens
+    pub fn report(&self, progress: Progress) {
+        if let Some(callback) = &self.callback {
+            callback(progress);
+        }
+    }
+
+    /// Whether a callback is set
+    pub fn is_enabled(&self) -> bool {
+        self.callback.is_some()
+    }
+}
+
+impl fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(if self.is_enabled() { "ProgressReporter(enabled)" } else { "ProgressReporter(disabled)" })
+    }
+}
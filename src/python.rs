@@ -0,0 +1,167 @@
+//! The `cov2html` Python module, built with `maturin` from `pyproject.toml`.
+//!
+//! ```python
+//! import cov2html
+//!
+//! report = cov2html.merge([cov2html.parse("run1.txt"), cov2html.parse("run2.txt")])
+//! report.resolve_totals(["linux"])
+//! print(report.totals())
+//! summary = cov2html.render(report, ["linux"], "report", title="ext4 coverage")
+//! ```
+
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::builder::ReportBuilder;
+use crate::executable::Denominator;
+use crate::model::CoverageReport;
+use crate::renderer::parse_renderer;
+
+/// Per-file, per-line hit counts, as returned by `parse` and `merge`
+#[pyclass(name = "CoverageReport", module = "cov2html")]
+#[derive(Debug, Clone, Default)]
+pub struct PyCoverageReport {
+    report: CoverageReport,
+}
+
+#[pymethods]
+impl PyCoverageReport {
+    /// Creates an empty report
+    #[new]
+    fn new() -> PyCoverageReport {
+        PyCoverageReport::default()
+    }
+
+    /// Paths of the files, in order
+    fn files(&self) -> Vec<String> {
+        self.report.files().map(|file| file.path.clone()).collect()
+    }
+
+    /// Hit count of a line, or `None` if it was not covered
+    fn hits(&self, path: &str, line: u32) -> Option<u64> {
+        self.report.file(path).and_then(|file| file.hits(line))
+    }
+
+    /// Whether a line of a file was covered
+    fn is_covered(&self, path: &str, line: u32) -> bool {
+        self.report.is_covered(path, line)
+    }
+
+    /// `(path, line, hits)` of every covered line
+    fn covered_lines(&self) -> Vec<(String, u32, u64)> {
+        self.report.iter_covered().map(|(path, covered)| (path.to_string(), covered.line, covered.hits)).collect()
+    }
+
+    /// `(path, line)` of every line that was not covered, once totals are resolved
+    fn uncovered_lines(&self) -> Vec<(String, u32)> {
+        self.report.iter_uncovered().map(|(path, line)| (path.to_string(), line)).collect()
+    }
+
+    /// Adds the hits of another report to this one
+    fn merge(&mut self, other: &PyCoverageReport) {
+        self.report.merge(&other.report);
+    }
+
+    /// Counts the lines of each file in the first source root containing it
+    /// and returns the paths of the files that could not be read.
+    /// `denominator` is "executable" (the default) or "all".
+    #[pyo3(signature = (source_roots, denominator = "executable"))]
+    fn resolve_totals(&mut self, source_roots: Vec<String>, denominator: &str) -> PyResult<Vec<String>> {
+        let denominator = denominator.parse::<Denominator>().map_err(PyValueError::new_err)?;
+        Ok(self.report.resolve_totals(&source_roots, denominator))
+    }
+
+    /// `(files, covered_lines, total_lines)` over the files whose number of
+    /// lines is known
+    fn totals(&self) -> (usize, usize, usize) {
+        let totals = self.report.totals();
+        (totals.files, totals.covered_lines, totals.total_lines)
+    }
+
+    /// Coverage percentage over the files whose number of lines is known
+    fn coverage_pct(&self) -> f64 {
+        self.report.totals().coverage_pct()
+    }
+
+    fn __len__(&self) -> usize {
+        self.report.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<CoverageReport with {} files>", self.report.len())
+    }
+}
+
+/// Parses a coverage file in any supported format
+#[pyfunction]
+#[pyo3(signature = (path, strict = false))]
+fn parse(path: &str, strict: bool) -> PyResult<PyCoverageReport> {
+    let report = CoverageReport::parse(path, strict).map_err(|e| PyOSError::new_err(format!("{}: {}", path, e)))?;
+    Ok(PyCoverageReport { report })
+}
+
+/// Adds up the hits of several reports
+#[pyfunction]
+fn merge(reports: Vec<PyRef<PyCoverageReport>>) -> PyCoverageReport {
+    let mut merged = PyCoverageReport::default();
+    for report in reports {
+        merged.merge(&report);
+    }
+
+    merged
+}
+
+/// Generates a report of a coverage file or `CoverageReport` into `output`
+/// and returns its totals, the files written and the warnings raised
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (coverage, source_roots, output, title = None, formats = None, strict = false, denominator = "executable"))]
+fn render<'py>(
+    py: Python<'py>,
+    coverage: &Bound<'py, PyAny>,
+    source_roots: Vec<String>,
+    output: &str,
+    title: Option<&str>,
+    formats: Option<Vec<String>>,
+    strict: bool,
+    denominator: &str,
+) -> PyResult<Bound<'py, PyDict>> {
+    let denominator = denominator.parse::<Denominator>().map_err(PyValueError::new_err)?;
+    let mut builder = if let Ok(report) = coverage.downcast::<PyCoverageReport>() {
+        ReportBuilder::new().coverage_report(&report.borrow().report)
+    } else {
+        ReportBuilder::new().coverage(&coverage.extract::<String>()?)
+    };
+    builder = builder.source_roots(source_roots).output(output).strict(strict).denominator(denominator);
+    if let Some(title) = title {
+        builder = builder.title(title);
+    }
+    if let Some(formats) = formats {
+        let renderers = formats.iter().map(|name| parse_renderer(name)).collect::<Result<Vec<_>, String>>().map_err(PyValueError::new_err)?;
+        builder = builder.emit(&renderers);
+    }
+
+    let summary = py.allow_threads(|| builder.generate()).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    let result = PyDict::new(py);
+    result.set_item("files", summary.files)?;
+    result.set_item("missing_files", summary.missing_files)?;
+    result.set_item("missing_sources", &summary.missing_sources)?;
+    result.set_item("covered_lines", summary.covered_lines)?;
+    result.set_item("total_lines", summary.total_lines)?;
+    result.set_item("source_lines", summary.source_lines)?;
+    result.set_item("coverage_pct", summary.coverage_pct())?;
+    result.set_item("outputs", &summary.outputs)?;
+    result.set_item("warnings", summary.warnings.iter().map(|warning| warning.to_string()).collect::<Vec<String>>())?;
+
+    Ok(result)
+}
+
+#[pymodule]
+fn cov2html(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCoverageReport>()?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(merge, m)?)?;
+    m.add_function(wrap_pyfunction!(render, m)?)?;
+    Ok(())
+}
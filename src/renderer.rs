@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::coverage::{json_string, summarize_coverage, verify_checksums, CoverageDiff, CoverageSummary, ReportOptions};
+#[cfg(feature = "lcov")]
+use crate::coverage::{write_coverage_file, OutputFormat};
+use crate::error::Cov2HtmlError;
+#[cfg(feature = "html")]
+use crate::html::generate_combined_html;
+use crate::lines::LineSet;
+use crate::source::SourceTree;
+
+/// Coverage data and settings a report is rendered from
+#[derive(Debug, Clone, Copy)]
+pub struct RenderContext<'a> {
+    /// Coverage data, already remapped, focused and with uncovered files added
+    pub coverage_map: &'a HashMap<String, LineSet>,
+    /// Tree the sources are read from
+    pub sources: &'a dyn SourceTree,
+    /// Report options
+    pub options: &'a ReportOptions,
+    /// Lines gained and lost against the baseline, if one was given
+    pub baseline: Option<&'a CoverageDiff>,
+    /// Totals shared by the formats that only write totals, counted once
+    /// on first use
+    pub(crate) totals: &'a OnceLock<CoverageSummary>,
+}
+
+/// Writes a report in one output format into the report directory.
+///
+/// Implementations are listed in `RENDERERS`, which is what `--emit`
+/// chooses from; a new output format only needs a renderer added there.
+/// Every format chosen is rendered from the same parsed coverage data.
+pub trait ReportRenderer: Send + Sync {
+    /// Name the renderer is selected by with `--emit`
+    fn name(&self) -> &'static str;
+
+    /// Extension of the file the renderer writes, named `coverage.<extension>`
+    /// unless the renderer names it itself
+    fn extension(&self) -> &'static str;
+
+    /// Renders the report into `work_dir` and returns the totals it shows,
+    /// with the files written in `outputs`
+    fn render(&self, context: &RenderContext, work_dir: &str) -> Result<CoverageSummary, Cov2HtmlError>;
+}
+
+impl fmt::Debug for dyn ReportRenderer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Registered renderers
+pub static RENDERERS: &[&dyn ReportRenderer] = &[
+    #[cfg(feature = "html")]
+    &HtmlRenderer,
+    &JsonRenderer,
+    #[cfg(feature = "lcov")]
+    &LcovRenderer,
+    &TextRenderer,
+];
+
+/// Renderer used when none is chosen: HTML, or text without the `html` feature
+#[cfg(feature = "html")]
+pub static DEFAULT_RENDERER: &dyn ReportRenderer = &HtmlRenderer;
+#[cfg(not(feature = "html"))]
+pub static DEFAULT_RENDERER: &dyn ReportRenderer = &TextRenderer;
+
+/// Looks up a registered renderer by name, for use as a clap value parser
+pub fn parse_renderer(name: &str) -> Result<&'static dyn ReportRenderer, String> {
+    RENDERERS.iter().copied().find(|renderer| renderer.name() == name).ok_or_else(|| {
+        let names = RENDERERS.iter().map(|renderer| renderer.name()).collect::<Vec<&str>>().join(", ");
+        format!("unknown report format '{}' (expected {})", name, names)
+    })
+}
+
+/// Runs `write` on the totals of the covered sources, mapping write errors
+/// to `Cov2HtmlError::WriteReport`. The sources are only read for the first
+/// format of a report that needs them.
+fn render_totals(
+    context: &RenderContext,
+    output: String,
+    write: impl FnOnce(&CoverageSummary, &str) -> io::Result<()>,
+) -> Result<CoverageSummary, Cov2HtmlError> {
+    let mut summary = match context.totals.get() {
+        Some(totals) => totals.clone(),
+        None => {
+            let mismatches = verify_checksums(context.coverage_map, context.sources, context.options)?;
+            let mut summary = summarize_coverage(context.coverage_map, context.sources, &context.options.exclusions, context.options.denominator, context.options.kernel_config.as_ref(), &context.options.line_tables, context.options.jobs);
+            summary.warnings.extend(mismatches);
+            context.totals.get_or_init(|| summary).clone()
+        }
+    };
+    write(&summary, &output).map_err(|source| Cov2HtmlError::WriteReport { path: output.clone(), source })?;
+    summary.outputs.push(output);
+
+    Ok(summary)
+}
+
+/// Path of the `coverage.<extension>` file a renderer writes into the report directory
+fn output_path(work_dir: &str, renderer: &dyn ReportRenderer) -> String {
+    Path::new(work_dir).join(format!("coverage.{}", renderer.extension())).to_string_lossy().into_owned()
+}
+
+/// The interactive (or, with `no_js`, static) HTML report
+#[cfg(feature = "html")]
+#[derive(Debug, Clone, Copy)]
+pub struct HtmlRenderer;
+
+#[cfg(feature = "html")]
+impl ReportRenderer for HtmlRenderer {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn render(&self, context: &RenderContext, work_dir: &str) -> Result<CoverageSummary, Cov2HtmlError> {
+        let mut summary = generate_combined_html(context.coverage_map, context.sources, work_dir, context.options, context.baseline)?;
+        summary.outputs.push(format!("{}/coverage_report.html", work_dir));
+
+        Ok(summary)
+    }
+}
+
+/// Overall and per-file totals as `coverage.json`
+#[derive(Debug, Clone, Copy)]
+pub struct JsonRenderer;
+
+impl ReportRenderer for JsonRenderer {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn render(&self, context: &RenderContext, work_dir: &str) -> Result<CoverageSummary, Cov2HtmlError> {
+        render_totals(context, output_path(work_dir, self), |summary, path| {
+            let mut out = BufWriter::new(File::create(path)?);
+            writeln!(out, "{{")?;
+            writeln!(out, "  \"title\": {},", json_string(&context.options.title))?;
+            writeln!(out, "  \"files\": {},", summary.files)?;
+            writeln!(out, "  \"missing_files\": {},", summary.missing_files)?;
+            writeln!(out, "  \"missing_sources\": {{")?;
+            for (i, (path, covered)) in summary.missing_sources.iter().enumerate() {
+                let separator = if i + 1 < summary.missing_sources.len() { "," } else { "" };
+                writeln!(out, "    {}: {{\"covered\": {}}}{}", json_string(path), covered, separator)?;
+            }
+            writeln!(out, "  }},")?;
+            writeln!(out, "  \"covered_lines\": {},", summary.covered_lines)?;
+            writeln!(out, "  \"total_lines\": {},", summary.total_lines)?;
+            writeln!(out, "  \"source_lines\": {},", summary.source_lines)?;
+            writeln!(out, "  \"coverage_pct\": {:.2},", summary.coverage_pct())?;
+            writeln!(out, "  \"file_totals\": {{")?;
+            for (i, (path, (covered, total))) in summary.file_totals.iter().enumerate() {
+                let separator = if i + 1 < summary.file_totals.len() { "," } else { "" };
+                writeln!(out, "    {}: {{\"covered\": {}, \"total\": {}}}{}", json_string(path), covered, total, separator)?;
+            }
+            writeln!(out, "  }}")?;
+            writeln!(out, "}}")?;
+            out.flush()
+        })
+    }
+}
+
+/// The covered lines of the counted files as an LCOV tracefile, `coverage.info`
+#[cfg(feature = "lcov")]
+#[derive(Debug, Clone, Copy)]
+pub struct LcovRenderer;
+
+#[cfg(feature = "lcov")]
+impl ReportRenderer for LcovRenderer {
+    fn name(&self) -> &'static str {
+        "lcov"
+    }
+
+    fn extension(&self) -> &'static str {
+        "info"
+    }
+
+    fn render(&self, context: &RenderContext, work_dir: &str) -> Result<CoverageSummary, Cov2HtmlError> {
+        render_totals(context, output_path(work_dir, self), |summary, path| {
+            let counted: HashMap<String, LineSet> = context.coverage_map.iter()
+                .filter(|(file_path, _)| summary.file_totals.contains_key(*file_path))
+                .map(|(file_path, lines)| (file_path.clone(), lines.clone()))
+                .collect();
+            write_coverage_file(&counted, path, OutputFormat::Lcov)
+        })
+    }
+}
+
+/// A plain text table of per-file and overall coverage, `coverage.txt`
+#[derive(Debug, Clone, Copy)]
+pub struct TextRenderer;
+
+impl ReportRenderer for TextRenderer {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn render(&self, context: &RenderContext, work_dir: &str) -> Result<CoverageSummary, Cov2HtmlError> {
+        render_totals(context, output_path(work_dir, self), |summary, path| {
+            let mut out = BufWriter::new(File::create(path)?);
+            let width = summary.file_totals.keys().chain(summary.missing_sources.keys()).map(|path| path.len()).max().unwrap_or(0).max(5);
+            writeln!(out, "{}", context.options.title)?;
+            writeln!(out)?;
+            writeln!(out, "{:<width$}  {:>8}  {:>8}  {:>7}", "File", "Covered", "Lines", "Percent", width = width)?;
+            for (path, (covered, total)) in &summary.file_totals {
+                let coverage_pct = if *total > 0 { (*covered as f64 / *total as f64) * 100.0 } else { 0.0 };
+                writeln!(out, "{:<width$}  {:>8}  {:>8}  {:>6.2}%", path, covered, total, coverage_pct, width = width)?;
+            }
+            writeln!(out, "{:<width$}  {:>8}  {:>8}  {:>6.2}%", "Total", summary.covered_lines, summary.total_lines, summary.coverage_pct(), width = width)?;
+            if summary.missing_files > 0 {
+                writeln!(out)?;
+                writeln!(out, "{} files without sources:", summary.missing_files)?;
+                for (path, covered) in &summary.missing_sources {
+                    writeln!(out, "{:<width$}  {:>8}", path, covered, width = width)?;
+                }
+            }
+            out.flush()
+        })
+    }
+}
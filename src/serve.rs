@@ -0,0 +1,231 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::warn;
+
+/// Page served for requests to `/`
+const INDEX_PAGE: &str = "coverage_report.html";
+
+/// Path of the Server-Sent Events stream carrying live updates
+const EVENTS_PATH: &str = "/events";
+
+/// How often an idle event stream is kept alive with a comment
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Live updates pushed to every open report over Server-Sent Events
+#[derive(Debug, Clone, Default)]
+pub struct LiveUpdates {
+    inner: Arc<Mutex<LiveUpdatesState>>,
+}
+
+#[derive(Debug, Default)]
+struct LiveUpdatesState {
+    subscribers: Vec<Sender<String>>,
+    /// Event sent first to newly connected reports
+    snapshot: Option<String>,
+}
+
+impl LiveUpdates {
+    /// Sends an event to every connected report
+    pub fn publish(&self, event: &str) {
+        let mut state = self.inner.lock().unwrap();
+        state.subscribers.retain(|subscriber| subscriber.send(event.to_string()).is_ok());
+    }
+
+    /// Sets the event that reports connecting from now on receive first
+    pub fn set_snapshot(&self, snapshot: Option<String>) {
+        self.inner.lock().unwrap().snapshot = snapshot;
+    }
+
+    fn subscribe(&self) -> (Option<String>, Receiver<String>) {
+        let (sender, receiver) = mpsc::channel();
+        let mut state = self.inner.lock().unwrap();
+        state.subscribers.push(sender);
+        (state.snapshot.clone(), receiver)
+    }
+}
+
+/// Serves the files of a generated report over HTTP until the process is
+/// stopped, handling each connection on its own thread. With live updates,
+/// reports can subscribe to them at `/events`.
+pub fn serve_directory(root: &Path, address: &str, live: Option<LiveUpdates>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let root = root.to_path_buf();
+        let live = live.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &root, live.as_ref()) {
+                warn!("Failed to answer request: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Answers a single request; connections are closed after the response
+fn handle_connection(stream: TcpStream, root: &Path, live: Option<&LiveUpdates>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("/");
+
+    // Only the headers matter to us; the request has no body
+    let mut accepts_gzip = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("accept-encoding")
+        {
+            accepts_gzip = value.split(',').any(|encoding| encoding.trim().starts_with("gzip"));
+        }
+    }
+
+    let mut stream = stream;
+    if method != "GET" && method != "HEAD" {
+        return write_response(&mut stream, "405 Method Not Allowed", "text/plain", b"Method not allowed\n", false, method == "HEAD");
+    }
+
+    if let Some(live) = live
+        && method == "GET"
+        && target.split('?').next() == Some(EVENTS_PATH)
+    {
+        return stream_events(&mut stream, live);
+    }
+
+    let Some(path) = resolve_request_path(root, target) else {
+        return write_response(&mut stream, "404 Not Found", "text/plain", b"Not found\n", false, method == "HEAD");
+    };
+
+    match fs::read(&path) {
+        Ok(body) => {
+            let content_type = content_type(&path);
+            let compress = accepts_gzip && is_compressible(content_type);
+            write_response(&mut stream, "200 OK", content_type, &body, compress, method == "HEAD")
+        }
+        Err(_) => write_response(&mut stream, "404 Not Found", "text/plain", b"Not found\n", false, method == "HEAD"),
+    }
+}
+
+/// Streams live updates to a report until it disconnects
+fn stream_events(stream: &mut TcpStream, live: &LiveUpdates) -> io::Result<()> {
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n")?;
+
+    let (snapshot, receiver) = live.subscribe();
+    if let Some(snapshot) = snapshot {
+        write!(stream, "data: {}\n\n", snapshot)?;
+    }
+    stream.flush()?;
+
+    loop {
+        match receiver.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(event) => write!(stream, "data: {}\n\n", event)?,
+            Err(RecvTimeoutError::Timeout) => stream.write_all(b": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        stream.flush()?;
+    }
+}
+
+/// Maps a request target to a file below the root, refusing paths that
+/// would escape it
+fn resolve_request_path(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or("");
+    let path = percent_decode(path)?;
+    let relative = path.trim_start_matches('/');
+    let relative = if relative.is_empty() { INDEX_PAGE } else { relative };
+
+    let relative = Path::new(relative);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return None;
+    }
+
+    let full_path = root.join(relative);
+    full_path.is_file().then_some(full_path)
+}
+
+/// Decodes `%XX` escapes in a URL path
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Content type of a report file, from its extension
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Whether a response of this type benefits from gzip compression
+fn is_compressible(content_type: &str) -> bool {
+    content_type.starts_with("text/") || content_type == "application/json" || content_type == "image/svg+xml"
+}
+
+/// Writes a complete response, gzip-compressing the body when asked to
+fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8], compress: bool, head_only: bool) -> io::Result<()> {
+    let compressed;
+    let body = if compress {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body)?;
+        compressed = encoder.finish()?;
+        &compressed[..]
+    } else {
+        body
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len(),
+        if compress { "Content-Encoding: gzip\r\nVary: Accept-Encoding\r\n" } else { "" }
+    )?;
+    if !head_only {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
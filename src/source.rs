@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::config::glob_match;
+use crate::coverage::{collect_matching_files, resolve_source, resolve_source_ignore_case, subtree_roots};
+
+/// Where the sources of the covered files are read from.
+///
+/// Source roots on disk (`Vec<String>` or `&[String]`, searched in order)
+/// are the usual tree; `MemorySources` serves sources from memory where
+/// there is no file system, e.g. in a browser.
+pub trait SourceTree: fmt::Debug + Send + Sync {
+    /// Finds a file, given relative to the tree, and returns the root it
+    /// was found under and its full path
+    fn find(&self, file_path: &str) -> Option<(String, String)>;
+
+    /// Reads a file by the full path `find` returned
+    fn read(&self, full_path: &str) -> io::Result<Vec<u8>>;
+
+    /// Paths, relative to the tree, of the files matching any of the globs
+    fn matching_files(&self, patterns: &[String]) -> Vec<String>;
+
+    /// The tree below a directory, with paths relative to it
+    fn subtree(&self, root: &str) -> Box<dyn SourceTree>;
+
+    /// Modification time of a file by the full path `find` returned, if the
+    /// tree can tell; `CachedSources` only caches files that have one
+    fn modified(&self, _full_path: &str) -> Option<SystemTime> {
+        None
+    }
+
+    /// Size in bytes of a file by the full path `find` returned, if the tree
+    /// can tell without reading it
+    fn size(&self, _full_path: &str) -> Option<u64> {
+        None
+    }
+
+    /// The path a full path `find` returned really names, with symlinks and
+    /// `.` and `..` components resolved, if the tree can tell
+    fn canonical_path(&self, _full_path: &str) -> Option<String> {
+        None
+    }
+
+    /// A file's path as the tree spells it, for a path that only matches it
+    /// when letter case is ignored, if the tree can tell
+    fn find_ignore_case(&self, _file_path: &str) -> Option<String> {
+        None
+    }
+}
+
+impl SourceTree for &[String] {
+    fn find(&self, file_path: &str) -> Option<(String, String)> {
+        resolve_source(self, file_path).map(|(root, full_path)| (root.to_string(), full_path))
+    }
+
+    fn read(&self, full_path: &str) -> io::Result<Vec<u8>> {
+        fs::read(full_path)
+    }
+
+    fn matching_files(&self, patterns: &[String]) -> Vec<String> {
+        let mut paths = Vec::new();
+        for root in self.iter() {
+            collect_matching_files(Path::new(root), "", patterns, &mut paths);
+        }
+
+        paths
+    }
+
+    fn subtree(&self, root: &str) -> Box<dyn SourceTree> {
+        Box::new(subtree_roots(self, root))
+    }
+
+    fn modified(&self, full_path: &str) -> Option<SystemTime> {
+        fs::metadata(full_path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    fn size(&self, full_path: &str) -> Option<u64> {
+        fs::metadata(full_path).map(|metadata| metadata.len()).ok()
+    }
+
+    fn canonical_path(&self, full_path: &str) -> Option<String> {
+        fs::canonicalize(full_path).ok().map(|path| path.to_string_lossy().into_owned())
+    }
+
+    fn find_ignore_case(&self, file_path: &str) -> Option<String> {
+        resolve_source_ignore_case(self, file_path)
+    }
+}
+
+impl SourceTree for Vec<String> {
+    fn find(&self, file_path: &str) -> Option<(String, String)> {
+        self.as_slice().find(file_path)
+    }
+
+    fn read(&self, full_path: &str) -> io::Result<Vec<u8>> {
+        self.as_slice().read(full_path)
+    }
+
+    fn matching_files(&self, patterns: &[String]) -> Vec<String> {
+        self.as_slice().matching_files(patterns)
+    }
+
+    fn subtree(&self, root: &str) -> Box<dyn SourceTree> {
+        self.as_slice().subtree(root)
+    }
+
+    fn modified(&self, full_path: &str) -> Option<SystemTime> {
+        self.as_slice().modified(full_path)
+    }
+
+    fn size(&self, full_path: &str) -> Option<u64> {
+        self.as_slice().size(full_path)
+    }
+
+    fn canonical_path(&self, full_path: &str) -> Option<String> {
+        self.as_slice().canonical_path(full_path)
+    }
+
+    fn find_ignore_case(&self, file_path: &str) -> Option<String> {
+        self.as_slice().find_ignore_case(file_path)
+    }
+}
+
+impl<T: SourceTree + ?Sized> SourceTree for Arc<T> {
+    fn find(&self, file_path: &str) -> Option<(String, String)> {
+        self.as_ref().find(file_path)
+    }
+
+    fn read(&self, full_path: &str) -> io::Result<Vec<u8>> {
+        self.as_ref().read(full_path)
+    }
+
+    fn matching_files(&self, patterns: &[String]) -> Vec<String> {
+        self.as_ref().matching_files(patterns)
+    }
+
+    fn subtree(&self, root: &str) -> Box<dyn SourceTree> {
+        self.as_ref().subtree(root)
+    }
+
+    fn modified(&self, full_path: &str) -> Option<SystemTime> {
+        self.as_ref().modified(full_path)
+    }
+
+    fn size(&self, full_path: &str) -> Option<u64> {
+        self.as_ref().size(full_path)
+    }
+
+    fn canonical_path(&self, full_path: &str) -> Option<String> {
+        self.as_ref().canonical_path(full_path)
+    }
+
+    fn find_ignore_case(&self, file_path: &str) -> Option<String> {
+        self.as_ref().find_ignore_case(file_path)
+    }
+}
+
+/// Source files held in memory, keyed by their path relative to the tree
+#[derive(Debug, Clone, Default)]
+pub struct MemorySources {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySources {
+    /// Creates a tree without files
+    pub fn new() -> MemorySources {
+        MemorySources::default()
+    }
+
+    /// Adds a file, replacing any earlier contents
+    pub fn insert(&mut self, file_path: &str, contents: impl Into<Vec<u8>>) {
+        self.files.insert(file_path.trim_start_matches('/').to_string(), contents.into());
+    }
+
+    /// Number of files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the tree has no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl SourceTree for MemorySources {
+    fn find(&self, file_path: &str) -> Option<(String, String)> {
+        self.files.contains_key(file_path).then(|| ("<memory>".to_string(), file_path.to_string()))
+    }
+
+    fn read(&self, full_path: &str) -> io::Result<Vec<u8>> {
+        self.files.get(full_path).cloned().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn matching_files(&self, patterns: &[String]) -> Vec<String> {
+        self.files.keys()
+            .filter(|path| patterns.iter().any(|pattern| glob_match(pattern, path)))
+            .cloned()
+            .collect()
+    }
+
+    fn subtree(&self, root: &str) -> Box<dyn SourceTree> {
+        let prefix = format!("{}/", root);
+        let files = self.files.iter()
+            .filter_map(|(path, contents)| path.strip_prefix(&prefix).map(|path| (path.to_string(), contents.clone())))
+            .collect();
+        Box::new(MemorySources { files })
+    }
+
+    fn size(&self, full_path: &str) -> Option<u64> {
+        self.files.get(full_path).map(|contents| contents.len() as u64)
+    }
+
+    fn find_ignore_case(&self, file_path: &str) -> Option<String> {
+        let lowercase = file_path.to_lowercase();
+        self.files.keys().filter(|path| path.to_lowercase() == lowercase).min().cloned()
+    }
+}
+
+/// Contents of cached files by full path, with the modification time they were read at
+type SourceCache = HashMap<String, (SystemTime, Vec<u8>)>;
+
+/// A source tree that keeps the files it read in memory, so reports
+/// generated again from the same tree only read the files that changed
+/// since. Files are revalidated by their modification time on every read.
+#[derive(Debug)]
+pub struct CachedSources {
+    inner: Box<dyn SourceTree>,
+    cache: Arc<Mutex<SourceCache>>,
+}
+
+impl CachedSources {
+    /// Caches the files read from `inner`
+    pub fn new(inner: impl SourceTree + 'static) -> CachedSources {
+        CachedSources { inner: Box::new(inner), cache: Arc::default() }
+    }
+
+    /// Number of files in the cache
+    pub fn cached_files(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Forgets every cached file
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SourceCache> {
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl SourceTree for CachedSources {
+    fn find(&self, file_path: &str) -> Option<(String, String)> {
+        self.inner.find(file_path)
+    }
+
+    fn read(&self, full_path: &str) -> io::Result<Vec<u8>> {
+        let Some(modified) = self.inner.modified(full_path) else {
+            self.lock().remove(full_path);
+            return self.inner.read(full_path);
+        };
+        if let Some((_, contents)) = self.lock().get(full_path).filter(|(cached_modified, _)| *cached_modified == modified) {
+            return Ok(contents.clone());
+        }
+
+        let contents = self.inner.read(full_path)?;
+        self.lock().insert(full_path.to_string(), (modified, contents.clone()));
+        Ok(contents)
+    }
+
+    fn matching_files(&self, patterns: &[String]) -> Vec<String> {
+        self.inner.matching_files(patterns)
+    }
+
+    fn subtree(&self, root: &str) -> Box<dyn SourceTree> {
+        // Full paths are the same below a subtree, so it shares the cache
+        Box::new(CachedSources { inner: self.inner.subtree(root), cache: self.cache.clone() })
+    }
+
+    fn modified(&self, full_path: &str) -> Option<SystemTime> {
+        self.inner.modified(full_path)
+    }
+
+    fn size(&self, full_path: &str) -> Option<u64> {
+        self.inner.size(full_path)
+    }
+
+    fn canonical_path(&self, full_path: &str) -> Option<String> {
+        self.inner.canonical_path(full_path)
+    }
+
+    fn find_ignore_case(&self, file_path: &str) -> Option<String> {
+        self.inner.find_ignore_case(file_path)
+    }
+}
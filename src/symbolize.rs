@@ -0,0 +1,267 @@
+//! Coverage collected as raw kernel PCs, e.g. dumped from kcov, turned into
+//! per-line hit counts with the DWARF line tables of the binary the PCs were
+//! collected from (usually `vmlinux`). Lookups run in process on the worker
+//! threads, so no binutils are needed. The lines found are kept in a cache
+//! directory under the build-id of the binary, so later runs against the same
+//! build only look up PCs they have not seen.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use addr2line::Loader;
+use log::{debug, warn};
+use object::Object;
+
+use crate::checksum::sha256_hex;
+use crate::coverage::{parallel_map, worker_count};
+use crate::error::Warning;
+use crate::executable::LineTables;
+use crate::parser::{add_hits, malformed, normalize_separators, CoverageCounts};
+
+/// Hit counts per PC
+pub type PcCounts = HashMap<u64, u64>;
+
+/// Reads raw PCs, one per line in hex with or without `0x`, each optionally
+/// followed by whitespace and a hit count; repeated PCs are summed. Malformed
+/// lines are skipped and collected as warnings, or fail the parse when
+/// `strict`.
+pub fn parse_pcs(input: &mut dyn BufRead, file_path: &str, strict: bool, warnings: &mut Vec<Warning>) -> io::Result<PcCounts> {
+    let mut pcs = PcCounts::new();
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let mut fields = line.split_whitespace();
+        let Some(pc) = fields.next() else {
+            continue;
+        };
+        let pc = pc.strip_prefix("0x").or_else(|| pc.strip_prefix("0X")).unwrap_or(pc);
+        let count = match fields.next() {
+            Some(count) => count.parse::<u64>().ok(),
+            None => Some(1),
+        };
+        match (u64::from_str_radix(pc, 16), count, fields.next()) {
+            (Ok(pc), Some(count), None) => *pcs.entry(pc).or_insert(0) += count,
+            _ => malformed(file_path, index + 1, format!("Invalid PC record: {}", line), strict, warnings)?,
+        }
+    }
+
+    Ok(pcs)
+}
+
+/// Source file and line of a looked-up address, `None` when the DWARF has no
+/// line information for it
+type Location = Option<(String, u32)>;
+
+/// Prefix of the files in a cache directory that keep the lookups of a
+/// binary, followed by its build key
+const LOOKUPS_PREFIX: &str = "pcs-";
+
+/// Lookups of earlier runs against one build of a binary
+struct LookupCache {
+    /// File the lookups are kept in
+    path: PathBuf,
+    /// Location of every address looked up so far
+    locations: HashMap<u64, Location>,
+    /// Number of locations read from the file
+    known: usize,
+}
+
+impl LookupCache {
+    /// Reads the lookups kept in `cache_dir` for the build of `binary`,
+    /// creating the directory if missing
+    fn open(cache_dir: &str, binary: &str) -> io::Result<LookupCache> {
+        fs::create_dir_all(cache_dir)?;
+        let path = Path::new(cache_dir).join(format!("{}{}", LOOKUPS_PREFIX, build_key(binary)?));
+        let locations = match fs::read_to_string(&path) {
+            Ok(lookups) => parse_lookups(&lookups),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        debug!("{} cached lookups for {} in {}", locations.len(), binary, path.display());
+        Ok(LookupCache { path, known: locations.len(), locations })
+    }
+
+    /// Writes the lookups back if any were added
+    fn save(&self) -> io::Result<()> {
+        if self.locations.len() == self.known {
+            return Ok(());
+        }
+        let mut lookups = String::from("cov2html-pcs 1\n");
+        for (address, location) in &self.locations {
+            match location {
+                Some((path, line)) => lookups.push_str(&format!("{:x} {} {}\n", address, line, path)),
+                None => lookups.push_str(&format!("{:x} -\n", address)),
+            }
+        }
+        let partial = self.path.with_extension("tmp");
+        fs::write(&partial, lookups)?;
+        fs::rename(&partial, &self.path)
+    }
+}
+
+/// Names the build of `binary` for the lookup cache: the build-id from its
+/// `.note.gnu.build-id`, or the SHA-256 of the file when it has none
+fn build_key(binary: &str) -> io::Result<String> {
+    let file = File::open(binary)?;
+    // SAFETY: the map is only read while looking for the note and hashing;
+    // the binary is not expected to be rewritten while it is symbolized
+    let data = unsafe { memmap2::Mmap::map(&file) }?;
+    let build_id = object::File::parse(&*data).ok().and_then(|object| object.build_id().ok().flatten());
+    Ok(match build_id {
+        Some(build_id) if !build_id.is_empty() => build_id.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        _ => format!("sha256-{}", sha256_hex(&data)),
+    })
+}
+
+/// Reads the lookups written by `LookupCache::save`; lines that are not
+/// understood are skipped and looked up again, and a file in another
+/// format is ignored
+fn parse_lookups(lookups: &str) -> HashMap<u64, Location> {
+    let mut lines = lookups.lines();
+    if lines.next() != Some("cov2html-pcs 1") {
+        return HashMap::new();
+    }
+
+    lines.filter_map(|line| {
+        let mut fields = line.splitn(3, ' ');
+        let address = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let location = match fields.next()? {
+            "-" => None,
+            line => Some((fields.next()?.to_string(), line.parse().ok()?)),
+        };
+        Some((address, location))
+    }).collect()
+}
+
+/// Looks up the source line of every PC in the DWARF of `binary` on up to
+/// `jobs` worker threads (one per logical CPU when `jobs` is 0) and sums the
+/// hits per line. kcov records the return address of each coverage callback,
+/// so with `return_addresses` the call instruction before each PC is looked
+/// up instead. PCs without line information are left out with a warning.
+/// With a `cache_dir`, the lines found are kept there under the build of
+/// `binary`, so addresses earlier runs looked up are not looked up again.
+pub fn symbolize_pcs(pcs: &PcCounts, binary: &str, return_addresses: bool, jobs: usize, cache_dir: Option<&str>) -> io::Result<CoverageCounts> {
+    let mut cache = cache_dir.map(|cache_dir| LookupCache::open(cache_dir, binary)).transpose()?;
+    let mut locations = cache.as_mut().map(|cache| std::mem::take(&mut cache.locations)).unwrap_or_default();
+
+    // Addresses are looked up in order, a batch per worker with a loader of
+    // its own, so nearby addresses reuse the compilation units already parsed
+    let probe = |pc: u64| if return_addresses { pc.saturating_sub(1) } else { pc };
+    let mut missing: Vec<u64> = pcs.keys().map(|pc| probe(*pc)).filter(|address| !locations.contains_key(address)).collect();
+    missing.sort_unstable();
+    missing.dedup();
+    let batch = missing.len().div_ceil(worker_count(jobs)).max(1);
+    let batches: Vec<&[u64]> = missing.chunks(batch).collect();
+
+    let results = parallel_map(&batches, jobs, |batch| -> io::Result<Vec<(u64, Location)>> {
+        let loader = Loader::new(binary).map_err(|e| io::Error::other(format!("{}: {}", binary, e)))?;
+        batch.iter().map(|&address| {
+            let location = loader.find_location(address).map_err(|e| io::Error::other(format!("{}: {}", binary, e)))?;
+            let location = location.and_then(|location| Some((normalize_separators(location.file?).into_owned(), location.line.filter(|line| *line > 0)?)));
+            Ok((address, location))
+        }).collect()
+    });
+    for result in results {
+        locations.extend(result?);
+    }
+    debug!("Looked up {} of {} PCs in {}", missing.len(), pcs.len(), binary);
+
+    let mut coverage_counts = CoverageCounts::new();
+    let mut unresolved = 0;
+    for (pc, count) in pcs {
+        match &locations[&probe(*pc)] {
+            Some((path, line)) => add_hits(&mut coverage_counts, path, *line, *count),
+            None => unresolved += 1,
+        }
+    }
+    if unresolved > 0 {
+        warn!("{} of {} PCs have no line information in {}", unresolved, pcs.len(), binary);
+    }
+
+    if let Some(mut cache) = cache {
+        cache.locations = locations;
+        cache.save()?;
+    }
+
+    Ok(coverage_counts)
+}
+
+/// Reads a file of raw PCs with `parse_pcs` and symbolizes them against
+/// `binary` with `symbolize_pcs`
+pub fn symbolize_pc_file(file_path: &str, binary: &str, return_addresses: bool, strict: bool, jobs: usize, cache_dir: Option<&str>, warnings: &mut Vec<Warning>) -> io::Result<CoverageCounts> {
+    let pcs = parse_pcs(&mut io::BufReader::new(File::open(file_path)?), file_path, strict, warnings)?;
+    symbolize_pcs(&pcs, binary, return_addresses, jobs, cache_dir)
+}
+
+/// Reads the lines each source file has instructions on from the DWARF line
+/// tables of `binary`, the lines llvm-cov and gcov consider coverable
+pub fn read_line_tables(binary: &str) -> io::Result<LineTables> {
+    let loader = Loader::new(binary).map_err(|e| io::Error::other(format!("{}: {}", binary, e)))?;
+    let rows = loader.find_location_range(0, u64::MAX).map_err(|e| io::Error::other(format!("{}: {}", binary, e)))?;
+    let mut lines: HashMap<String, Vec<u32>> = HashMap::new();
+    for (_, _, location) in rows {
+        if let Some(path) = location.file
+            && let Some(line) = location.line.filter(|line| *line > 0)
+        {
+            lines.entry(normalize_separators(path).into_owned()).or_default().push(line);
+        }
+    }
+
+    Ok(lines.into_iter().map(|(path, lines)| (path, lines.into_iter().collect())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cov2html-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn binaries_without_a_build_id_are_keyed_by_their_hash() {
+        let dir = temp_dir("build-key");
+        let binary = dir.join("image");
+        fs::write(&binary, "abc").unwrap();
+        assert_eq!(
+            build_key(&binary.to_string_lossy()).unwrap(),
+            "sha256-ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cached_lookups_are_reused_for_the_same_build() {
+        let dir = temp_dir("lookups");
+        let binary = dir.join("image").to_string_lossy().into_owned();
+        let cache_dir = dir.join("cache").to_string_lossy().into_owned();
+        fs::write(&binary, "not an object file").unwrap();
+
+        let mut cache = LookupCache::open(&cache_dir, &binary).unwrap();
+        cache.locations.insert(0xfff, Some(("kernel/fork.c".to_string(), 12)));
+        cache.locations.insert(0x1fff, None);
+        cache.save().unwrap();
+
+        // Every address is cached, so the binary is never loaded
+        let pcs = PcCounts::from([(0x1000, 2), (0x2000, 1)]);
+        let coverage_counts = symbolize_pcs(&pcs, &binary, true, 1, Some(&cache_dir)).unwrap();
+        assert_eq!(coverage_counts, CoverageCounts::from([("kernel/fork.c".to_string(), HashMap::from([(12, 2)]))]));
+
+        // Another build of the binary gets lookups of its own
+        fs::write(&binary, "rebuilt").unwrap();
+        assert!(LookupCache::open(&cache_dir, &binary).unwrap().locations.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_lookups_are_skipped() {
+        let lookups = parse_lookups("cov2html-pcs 1\nfff 12 a dir/file name.c\nzz 1 x.c\n1fff -\n2fff 3\n");
+        assert_eq!(lookups, HashMap::from([(0xfff, Some(("a dir/file name.c".to_string(), 12))), (0x1fff, None)]));
+        assert!(parse_lookups("cov2html-pcs 0\nfff 12 a.c\n").is_empty());
+    }
+}
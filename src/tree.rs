@@ -0,0 +1,140 @@
+//! The directory tree shown in the report sidebar, built from the covered
+//! files independently of how it is rendered.
+//!
+//! Every path is allocated once, when its file or directory is added to the
+//! `FileTree`; the `DirectoryTree` and the report share those allocations.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// `(covered_lines, total_lines)` of every file, and `(0, 0)` for every
+/// directory, keyed by path
+pub type FileTree = HashMap<Arc<str>, (usize, usize)>;
+
+/// `(path, covered_lines, total_lines)` entries of each directory, keyed by
+/// the directory path
+pub type DirectoryTree = HashMap<Arc<str>, Vec<(Arc<str>, usize, usize)>>;
+
+/// Sorted directory names and `(name, covered, total)` file entries of a tree node
+pub type TreeChildren<'a> = (Vec<&'a str>, Vec<(&'a str, usize, usize)>);
+
+/// Builds file tree entries for a given file path: the file with its line
+/// counts, and each of its parent directories as `(0, 0)`. Returns the path
+/// of the file as stored in the tree.
+pub fn build_file_tree_entries(file_path: &str, covered_lines: usize, total_lines: usize, file_tree: &mut FileTree) -> Arc<str> {
+    // Directories shared by several files are only allocated for the first
+    for (index, _) in file_path.match_indices('/') {
+        let directory = &file_path[..index];
+        if !file_tree.contains_key(directory) {
+            file_tree.insert(Arc::from(directory), (0, 0));
+        }
+    }
+    
+    let path: Arc<str> = Arc::from(file_path);
+    file_tree.insert(path.clone(), (covered_lines, total_lines));
+    path
+}
+
+/// Builds a directory tree structure from file entries, listing every
+/// entry under its parent directory (`""` for the root)
+pub fn build_directory_tree(file_tree: &FileTree, tree: &mut DirectoryTree) {
+    let root: Arc<str> = Arc::from("");
+    for (path, (covered, total)) in file_tree {
+        // Parent directories are entries of the file tree as well, so their
+        // paths are shared rather than copied
+        let parent = match path.rfind('/') {
+            Some(index) => file_tree.get_key_value(&path[..index])
+                .map_or_else(|| Arc::from(&path[..index]), |(parent, _)| parent.clone()),
+            None => root.clone(),
+        };
+        tree.entry(parent)
+            .or_default()
+            .push((path.clone(), *covered, *total));
+    }
+}
+
+/// Splits the children of a tree node into sorted directories and files
+pub fn sorted_tree_children<'a>(
+    tree: &'a DirectoryTree,
+    current_path: &str
+) -> Option<TreeChildren<'a>> {
+    let children = tree.get(current_path)?;
+    
+    let mut dirs: Vec<&str> = Vec::new();
+    let mut files: Vec<(&str, usize, usize)> = Vec::new();
+    
+    for (path, covered, total) in children {
+        if *total == 0 {
+            // This is a directory
+            let name = if current_path.is_empty() {
+                &path[..]
+            } else {
+                &path[current_path.len() + 1..]
+            };
+            
+            if !name.contains('/') {
+                dirs.push(name);
+            }
+        } else {
+            // This is a file
+            let name = path.split('/').next_back().unwrap_or(path);
+            files.push((name, *covered, *total));
+        }
+    }
+    
+    dirs.sort();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+    
+    Some((dirs, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(files: &[(&str, usize, usize)]) -> DirectoryTree {
+        let mut file_tree = FileTree::new();
+        for (path, covered, total) in files {
+            build_file_tree_entries(path, *covered, *total, &mut file_tree);
+        }
+        let mut tree = DirectoryTree::new();
+        build_directory_tree(&file_tree, &mut tree);
+        tree
+    }
+
+    #[test]
+    fn files_are_listed_under_each_of_their_directories() {
+        let tree = tree(&[("kernel/sched/core.c", 3, 10), ("kernel/fork.c", 1, 4), ("init/main.c", 2, 2)]);
+
+        assert_eq!(sorted_tree_children(&tree, ""), Some((vec!["init", "kernel"], vec![])));
+        assert_eq!(sorted_tree_children(&tree, "kernel"), Some((vec!["sched"], vec![("fork.c", 1, 4)])));
+        assert_eq!(sorted_tree_children(&tree, "kernel/sched"), Some((vec![], vec![("core.c", 3, 10)])));
+        assert_eq!(sorted_tree_children(&tree, "kernel/fork.c"), None);
+    }
+
+    #[test]
+    fn files_at_the_root_are_listed_with_their_totals() {
+        let tree = tree(&[("b.c", 1, 5), ("a.c", 0, 3), ("lib/x.c", 4, 4)]);
+
+        assert_eq!(sorted_tree_children(&tree, ""), Some((vec!["lib"], vec![("a.c", 0, 3), ("b.c", 1, 5)])));
+    }
+
+    #[test]
+    fn directories_share_the_paths_of_the_file_tree() {
+        let mut file_tree = FileTree::new();
+        let path = build_file_tree_entries("a/b/c.c", 1, 2, &mut file_tree);
+        build_file_tree_entries("a/b/d.c", 2, 2, &mut file_tree);
+
+        assert_eq!(&*path, "a/b/c.c");
+        assert_eq!(file_tree.len(), 4);
+        assert_eq!(file_tree["a"], (0, 0));
+        assert_eq!(file_tree["a/b"], (0, 0));
+        assert_eq!(file_tree["a/b/d.c"], (2, 2));
+
+        let mut tree = DirectoryTree::new();
+        build_directory_tree(&file_tree, &mut tree);
+        let (directory, _) = file_tree.get_key_value("a/b").unwrap();
+        let (parent, _) = tree.get_key_value("a/b").unwrap();
+        assert!(Arc::ptr_eq(directory, parent));
+    }
+}
@@ -0,0 +1,141 @@
+//! Linting a coverage file against the source tree without generating a
+//! report, for `cov2html validate`.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use log::debug;
+
+use crate::coverage::{count_lines, remap_path, PathMapping};
+use crate::parser::{detect_parser, CoverageRecord, InputFormat};
+
+/// A problem found while validating a coverage file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageProblem {
+    /// Line of the coverage file the problem is on, for problems with a single record
+    pub line: Option<usize>,
+    /// Description of the problem
+    pub message: String,
+}
+
+/// Result of linting a coverage file against the source tree
+#[derive(Debug, Clone, Default)]
+pub struct CoverageValidation {
+    /// Distinct files in the coverage data
+    pub files: usize,
+    /// Well-formed records
+    pub records: usize,
+    /// Records repeating a `path:line` pair seen earlier; harmless, since
+    /// several addresses often map to the same line
+    pub duplicates: usize,
+    /// Files found under each source root, in root order
+    pub resolved: Vec<usize>,
+    /// Malformed records, missing sources and lines beyond the end of their file
+    pub problems: Vec<CoverageProblem>,
+}
+
+/// Lints a coverage file in `format` without generating a report: malformed
+/// records, duplicate records and, when source roots are given, paths that
+/// are not found and covered lines beyond the end of their file. Records
+/// are read by the same parser a report would use.
+pub fn validate_coverage_file(file_path: &str, format: InputFormat, source_roots: &[String], path_mappings: &[PathMapping]) -> io::Result<CoverageValidation> {
+    let mut reader = io::BufReader::new(File::open(file_path)?);
+    let parser = match format {
+        InputFormat::Parser(parser) => parser,
+        InputFormat::Auto => detect_parser(file_path, &String::from_utf8_lossy(reader.fill_buf()?)),
+    };
+    let mut validation = CoverageValidation {
+        resolved: vec![0; source_roots.len()],
+        ..CoverageValidation::default()
+    };
+    
+    // Coverage file line of the first record for each covered line, per path
+    let mut covered: BTreeMap<String, BTreeMap<u32, usize>> = BTreeMap::new();
+    parser.read_records(&mut reader, &mut |record_line, record| {
+        match record {
+            CoverageRecord::File(path) => {
+                covered.entry(remap_path(path, path_mappings)).or_default();
+            }
+            CoverageRecord::Line { path, line: line_number, .. } => {
+                validation.records += 1;
+                let lines = covered.entry(remap_path(path, path_mappings)).or_default();
+                if let Some(first) = lines.get(&line_number) {
+                    debug!("line {}: duplicate of the record on line {}", record_line, first);
+                    validation.duplicates += 1;
+                } else {
+                    lines.insert(line_number, record_line);
+                }
+            }
+            CoverageRecord::Malformed(message) => validation.problems.push(CoverageProblem { line: Some(record_line), message }),
+        }
+        Ok(())
+    })?;
+    validation.files = covered.len();
+    
+    if source_roots.is_empty() {
+        return Ok(validation);
+    }
+    
+    for (path, lines) in &covered {
+        let Some(root) = source_roots.iter().position(|root| Path::new(root).join(path).is_file()) else {
+            validation.problems.push(CoverageProblem { line: None, message: format!("{}: source file not found", path) });
+            continue;
+        };
+        validation.resolved[root] += 1;
+        
+        let full_path = Path::new(&source_roots[root]).join(path);
+        let file_lines = match fs::read(&full_path) {
+            Ok(content) => count_lines(&content),
+            Err(e) => {
+                validation.problems.push(CoverageProblem { line: None, message: format!("{}: failed to read source file: {}", path, e) });
+                continue;
+            }
+        };
+        
+        for (line_number, record_line) in lines {
+            if *line_number == 0 || *line_number as usize > file_lines {
+                validation.problems.push(CoverageProblem {
+                    line: Some(*record_line),
+                    message: format!("{}:{} is beyond the end of the file ({} lines)", path, line_number, file_lines),
+                });
+            }
+        }
+    }
+    
+    // Keep the problems in coverage file order, with whole-file problems last
+    validation.problems.sort_by_key(|problem| problem.line.unwrap_or(usize::MAX));
+    
+    Ok(validation)
+}
+
+#[cfg(all(test, feature = "lcov"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lcov_records_are_validated_by_the_lcov_parser() {
+        let dir = std::env::temp_dir().join(format!("cov2html-test-{}-validate-lcov", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/kernel")).unwrap();
+        fs::write(dir.join("src/kernel/fork.c"), "a\nb\nc\n").unwrap();
+        let input = dir.join("coverage.info");
+        fs::write(&input, "TN:\nSF:/build/kernel/fork.c\nDA:1,2\nDA:2,0\nDA:1,1\nDA:9,1\nDA:x,1\nend_of_record\nSF:/build/kernel/gone.c\nend_of_record\n").unwrap();
+
+        let input = input.to_string_lossy();
+        let source_roots = [dir.join("src").to_string_lossy().into_owned()];
+        let mappings = ["/build/=".parse::<PathMapping>().unwrap()];
+        for format in [InputFormat::Auto, "lcov".parse().unwrap()] {
+            let validation = validate_coverage_file(&input, format, &source_roots, &mappings).unwrap();
+            assert_eq!((validation.files, validation.records, validation.duplicates, validation.resolved.as_slice()), (2, 3, 1, &[1][..]));
+            let problems: Vec<(Option<usize>, &str)> = validation.problems.iter().map(|problem| (problem.line, problem.message.as_str())).collect();
+            assert_eq!(problems, [
+                (Some(6), "kernel/fork.c:9 is beyond the end of the file (3 lines)"),
+                (Some(7), "invalid DA record: DA:x,1"),
+                (None, "kernel/gone.c: source file not found"),
+            ]);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
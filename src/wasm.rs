@@ -0,0 +1,122 @@
+//! Exports used by the drag-and-drop viewer page (`cov2html viewer`), built
+//! with `cargo build --lib --release --target wasm32-unknown-unknown
+//! --no-default-features --features wasm`.
+//!
+//! Strings and buffers are passed as pointer and length pairs into memory
+//! obtained from `cov2html_alloc`. Results are left in an output buffer read
+//! with `cov2html_output_ptr` and `cov2html_output_len`.
+
+use std::cell::RefCell;
+use std::slice;
+use std::sync::Arc;
+
+use crate::builder::ReportBuilder;
+use crate::model::CoverageReport;
+use crate::parser::InputFormat;
+use crate::source::MemorySources;
+
+thread_local! {
+    /// Sources added for the next report
+    static SOURCES: RefCell<Arc<MemorySources>> = RefCell::new(Arc::new(MemorySources::new()));
+    /// Report page or error message of the last render
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Reads a buffer passed from JavaScript
+///
+/// # Safety
+///
+/// `ptr` must point to `len` initialized bytes, or `len` must be 0.
+unsafe fn buffer<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        return &[];
+    }
+    // SAFETY: the caller guarantees `ptr` points to `len` bytes.
+    unsafe { slice::from_raw_parts(ptr, len) }
+}
+
+/// Allocates `len` bytes for JavaScript to copy a buffer into
+#[unsafe(no_mangle)]
+pub extern "C" fn cov2html_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// Frees a buffer returned by `cov2html_alloc`
+///
+/// # Safety
+///
+/// `ptr` and `len` must be those of a buffer from `cov2html_alloc` that was
+/// not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cov2html_free(ptr: *mut u8, len: usize) {
+    // SAFETY: the buffer was allocated by `cov2html_alloc` with capacity `len`.
+    drop(unsafe { Vec::from_raw_parts(ptr, 0, len) });
+}
+
+/// Adds a source file, given relative to the source tree, for the next report
+///
+/// # Safety
+///
+/// Both pointers must point to buffers of the given lengths.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cov2html_add_source(path_ptr: *const u8, path_len: usize, data_ptr: *const u8, data_len: usize) {
+    // SAFETY: the caller guarantees the validity of both buffers.
+    let (path, data) = unsafe { (buffer(path_ptr, path_len), buffer(data_ptr, data_len)) };
+    SOURCES.with(|sources| Arc::make_mut(&mut sources.borrow_mut()).insert(&String::from_utf8_lossy(path), data));
+}
+
+/// Removes every source file added so far
+#[unsafe(no_mangle)]
+pub extern "C" fn cov2html_clear_sources() {
+    SOURCES.with(|sources| *sources.borrow_mut() = Arc::new(MemorySources::new()));
+}
+
+/// Renders the report page of a coverage file named `name` with the sources
+/// added so far. Returns 0 with the page in the output buffer, or 1 with an
+/// error message there.
+///
+/// # Safety
+///
+/// Both pointers must point to buffers of the given lengths.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cov2html_render(coverage_ptr: *const u8, coverage_len: usize, name_ptr: *const u8, name_len: usize) -> i32 {
+    // SAFETY: the caller guarantees the validity of both buffers.
+    let (mut coverage, name) = unsafe { (buffer(coverage_ptr, coverage_len), buffer(name_ptr, name_len)) };
+    let name = String::from_utf8_lossy(name);
+
+    let mut page = Vec::new();
+    let rendered = CoverageReport::parse_reader(&mut coverage, &name, InputFormat::Auto, false)
+        .map_err(|e| format!("failed to read coverage file {}: {}", name, e))
+        .and_then(|report| {
+            let sources = SOURCES.with(|sources| sources.borrow().clone());
+            ReportBuilder::new()
+                .coverage_report(&report)
+                .source_tree(sources)
+                .title(&format!("Coverage of {}", name))
+                .jobs(1)
+                .write_html(&mut page)
+                .map_err(|e| e.to_string())
+        });
+
+    let (status, output) = match rendered {
+        Ok(_) => (0, page),
+        Err(message) => (1, message.into_bytes()),
+    };
+    OUTPUT.with(|buffer| *buffer.borrow_mut() = output);
+    status
+}
+
+/// Start of the output buffer
+#[unsafe(no_mangle)]
+pub extern "C" fn cov2html_output_ptr() -> *const u8 {
+    OUTPUT.with(|buffer| buffer.borrow().as_ptr())
+}
+
+/// Length of the output buffer
+#[unsafe(no_mangle)]
+pub extern "C" fn cov2html_output_len() -> usize {
+    OUTPUT.with(|buffer| buffer.borrow().len())
+}
@@ -0,0 +1,1027 @@
+// Function to safely display source code
+function displaySourceSafely(text) {
+  // First encode all HTML entities to prevent XSS attacks
+  const encodedText = text
+    .replace(/&/g, '&amp;')
+    .replace(/</g, '&lt;')
+    .replace(/>/g, '&gt;')
+    .replace(/"/g, '&quot;')
+    .replace(/'/g, '&#39;');
+    
+  // Replace encoded preprocessor directives to display them nicely
+  // This handles #include<xxx> and #include <xxx> formats
+  return encodedText
+    .replace(/(#\s*include\s*)&lt;([^&]+)&gt;/g, '$1<span class="include-brackets">&lt;</span>$2<span class="include-brackets">&gt;</span>')
+    .replace(/(#\s*define\s*[^&\s]+\s*)&lt;([^&]+)&gt;/g, '$1<span class="include-brackets">&lt;</span>$2<span class="include-brackets">&gt;</span>');
+}
+
+// Function to escape text for use in HTML
+function escapeHtml(text) {
+  return text
+    .replace(/&/g, '&amp;')
+    .replace(/</g, '&lt;')
+    .replace(/>/g, '&gt;')
+    .replace(/"/g, '&quot;')
+    .replace(/'/g, '&#39;');
+}
+
+// Syntax rules for the languages that get highlighted
+const LANGUAGE_SYNTAX = {
+  c: {
+    keywords: ['auto', 'break', 'case', 'char', 'const', 'continue', 'default', 'do', 'double', 'else', 'enum',
+      'extern', 'float', 'for', 'goto', 'if', 'inline', 'int', 'long', 'register', 'restrict', 'return', 'short',
+      'signed', 'sizeof', 'static', 'struct', 'switch', 'typedef', 'union', 'unsigned', 'void', 'volatile', 'while',
+      'bool', 'true', 'false', 'NULL'],
+    lineComment: '//',
+    blockComments: true,
+    preprocessor: true
+  },
+  rust: {
+    keywords: ['as', 'async', 'await', 'break', 'const', 'continue', 'crate', 'dyn', 'else', 'enum', 'extern',
+      'false', 'fn', 'for', 'if', 'impl', 'in', 'let', 'loop', 'match', 'mod', 'move', 'mut', 'pub', 'ref',
+      'return', 'self', 'Self', 'static', 'struct', 'super', 'trait', 'true', 'type', 'unsafe', 'use', 'where',
+      'while'],
+    lineComment: '//',
+    blockComments: true
+  },
+  asm: {
+    keywords: [],
+    lineComment: '//',
+    blockComments: true,
+    preprocessor: true,
+    directives: true
+  },
+  makefile: {
+    keywords: ['ifeq', 'ifneq', 'ifdef', 'ifndef', 'else', 'endif', 'include', 'define', 'endef', 'export',
+      'unexport', 'override', 'vpath'],
+    lineComment: '#',
+    variables: true
+  },
+  dts: {
+    keywords: [],
+    lineComment: '//',
+    blockComments: true,
+    preprocessor: true
+  }
+};
+LANGUAGE_SYNTAX.header = LANGUAGE_SYNTAX.c;
+
+// Build the token pattern of every language once. Groups:
+// 1 preprocessor, 2 comment, 3 string, 4 number, 5 directive/variable, 6 word
+Object.values(LANGUAGE_SYNTAX).forEach(syntax => {
+  if (syntax.pattern) return;
+  const comments = [];
+  if (syntax.blockComments) comments.push(String.raw`\/\*[\s\S]*?(?:\*\/|$)`);
+  comments.push(syntax.lineComment === '#' ? '#.*' : String.raw`\/\/.*`);
+  
+  let special = '(?!)';
+  if (syntax.directives) special = String.raw`\.[A-Za-z_]\w*`;
+  if (syntax.variables) special = String.raw`\$\([^)]*\)|\$\{[^}]*\}|\$[@<^+*?%]`;
+  
+  syntax.pattern = new RegExp([
+    syntax.preprocessor ? String.raw`(^\s*#\s*\w+)` : '((?!))',
+    '(' + comments.join('|') + ')',
+    String.raw`("(?:[^"\\]|\\.)*"?|'(?:[^'\\]|\\.){1,2}')`,
+    String.raw`(\b(?:0[xX][0-9a-fA-F]+|\d+(?:\.\d+)?)[uUlLfF]*\b)`,
+    '(' + special + ')',
+    String.raw`([A-Za-z_]\w*)`
+  ].join('|'), 'g');
+  syntax.keywordSet = new Set(syntax.keywords);
+});
+
+// Function to compute, for every line, whether it starts inside a block comment
+function computeCommentStates(data) {
+  const syntax = LANGUAGE_SYNTAX[data.language];
+  const states = new Uint8Array(data.source.length);
+  if (!syntax || !syntax.blockComments) return states;
+  
+  let inComment = false;
+  for (let i = 0; i < data.source.length; i++) {
+    states[i] = inComment ? 1 : 0;
+    const text = data.source[i];
+    let pos = 0;
+    if (inComment) {
+      const end = text.indexOf('*/');
+      if (end < 0) continue;
+      pos = end + 2;
+      inComment = false;
+    }
+    
+    const re = syntax.pattern;
+    re.lastIndex = pos;
+    let m;
+    while ((m = re.exec(text)) !== null) {
+      if (m[0].length === 0) {
+        re.lastIndex++;
+        continue;
+      }
+      if (m[2] && m[2].startsWith('/*') && (m[2].length < 4 || !m[2].endsWith('*/'))) {
+        inComment = true;
+      }
+    }
+  }
+  return states;
+}
+
+// Function to highlight a source line according to its language
+function highlightSource(text, language, inComment) {
+  const syntax = LANGUAGE_SYNTAX[language];
+  if (!syntax) return displaySourceSafely(text);
+  
+  let html = '';
+  let pos = 0;
+  if (inComment) {
+    const end = text.indexOf('*/');
+    pos = end < 0 ? text.length : end + 2;
+    html += `<span class="tok-comment">${escapeHtml(text.slice(0, pos))}</span>`;
+  }
+  
+  const re = syntax.pattern;
+  re.lastIndex = pos;
+  let m;
+  while ((m = re.exec(text)) !== null) {
+    if (m[0].length === 0) {
+      re.lastIndex++;
+      continue;
+    }
+    html += escapeHtml(text.slice(pos, m.index));
+    
+    let tokenClass = null;
+    if (m[1]) tokenClass = 'tok-preprocessor';
+    else if (m[2]) tokenClass = 'tok-comment';
+    else if (m[3]) tokenClass = 'tok-string';
+    else if (m[4]) tokenClass = 'tok-number';
+    else if (m[5]) tokenClass = 'tok-special';
+    else if (syntax.keywordSet.has(m[6])) tokenClass = 'tok-keyword';
+    
+    html += tokenClass ? `<span class="${tokenClass}">${escapeHtml(m[0])}</span>` : escapeHtml(m[0]);
+    pos = re.lastIndex;
+  }
+  
+  return html + escapeHtml(text.slice(pos));
+}
+
+// Function to render a file path as clickable breadcrumbs
+function renderBreadcrumbs(path) {
+  const parts = path.split('/');
+  let dirPath = '';
+  return parts.map((part, i) => {
+    if (i === parts.length - 1) {
+      return `<span class="breadcrumb-file">${part}</span>`;
+    }
+    dirPath = dirPath ? dirPath + '/' + part : part;
+    return `<a href="#" class="breadcrumb" data-path="${dirPath}">${part}</a>`;
+  }).join('<span class="breadcrumb-sep">/</span>');
+}
+
+// Function to expand, scroll to and highlight a directory in the sidebar
+function revealDirectory(dirPath) {
+  // Expand the directory itself and every ancestor
+  expandAncestors(dirPath + '/');
+  expandedDirs.add(dirPath);
+  revealedDir = dirPath;
+  rebuildTreeRows();
+  
+  const index = treeRows.findIndex(row => row.node.children && row.node.path === dirPath);
+  if (index >= 0) {
+    scrollTreeToRow(index, true);
+  }
+  
+  clearTimeout(revealDirectory.timer);
+  revealDirectory.timer = setTimeout(function() {
+    revealedDir = null;
+    renderTree(true);
+  }, 1500);
+}
+
+// Files with more lines than this are rendered with virtual scrolling
+const VIRTUALIZE_THRESHOLD = 5000;
+
+// Extra lines rendered above and below the visible window
+const VIRTUAL_OVERSCAN = 50;
+
+// Function to create the DOM node for a single source line
+function createLineElement(data, coveredSet, i) {
+  const lineNum = i + 1;
+  // Covered lines under exclusion markers or in blocks that are not
+  // compiled don't count, so they show as excluded or not compiled
+  const isExcluded = inRanges(data.excluded, lineNum);
+  const isDisabled = inRanges(data.disabled, lineNum);
+  const isCovered = coveredSet.has(lineNum) && !isExcluded && !isDisabled;
+  const lineDiv = document.createElement('div');
+  lineDiv.className = 'line' + (isCovered ? ' covered' : '');
+  if (data.gainedSet && data.gainedSet.has(lineNum)) {
+    lineDiv.classList.add('gained');
+  } else if (data.lostSet && data.lostSet.has(lineNum)) {
+    lineDiv.classList.add('lost');
+  } else if (isExcluded) {
+    lineDiv.classList.add('excluded');
+  } else if (isDisabled) {
+    lineDiv.classList.add('not-compiled');
+  }
+  
+  const lineNumSpan = document.createElement('span');
+  lineNumSpan.className = 'line-number';
+  lineNumSpan.textContent = lineNum;
+  
+  const lineContentSpan = document.createElement('span');
+  lineContentSpan.className = 'line-content';
+  renderLineContent(lineContentSpan, data, i);
+  
+  lineDiv.appendChild(lineNumSpan);
+  lineDiv.appendChild(lineContentSpan);
+  return lineDiv;
+}
+
+// Function to tell whether a line is in a file's [start, end] ranges, such as its not compiled lines
+function inRanges(ranges, lineNum) {
+  return (ranges || []).some(([start, end]) => lineNum >= start && lineNum <= end);
+}
+
+// Function to fill in the highlighted text of a source line
+function renderLineContent(lineContentSpan, data, i) {
+  if (!data.commentStates) {
+    data.commentStates = computeCommentStates(data);
+  }
+  
+  let text = data.source[i];
+  let elided = 0;
+  const maxLength = reportConfig.maxLineLength;
+  if (maxLength && text.length > maxLength && !(data.expandedLines && data.expandedLines.has(i))) {
+    elided = text.length - maxLength;
+    text = text.slice(0, maxLength);
+  }
+  
+  // Split off trailing whitespace so it can be shown separately
+  let trailing = '';
+  if (reportConfig.showWhitespace && !elided) {
+    const match = text.match(/[ \t]+$/);
+    if (match) {
+      trailing = match[0];
+      text = text.slice(0, match.index);
+    }
+  }
+  
+  // Use our custom function to safely display source code with proper formatting
+  let html = highlightSource(text, data.language, data.commentStates[i]);
+  if (reportConfig.showWhitespace) {
+    html = html.replace(/\t/g, '<span class="ws-tab">\t</span>');
+    if (trailing) {
+      html += '<span class="ws-trailing">' + trailing.replace(/\t/g, '<span class="ws-tab">\t</span>') + '</span>';
+    }
+  }
+  lineContentSpan.innerHTML = html;
+  
+  if (elided) {
+    const expand = document.createElement('a');
+    expand.href = '#';
+    expand.className = 'line-expand';
+    expand.textContent = `\u2026 (+${elided} chars)`;
+    expand.addEventListener('click', function(e) {
+      e.preventDefault();
+      if (!data.expandedLines) data.expandedLines = new Set();
+      data.expandedLines.add(i);
+      renderLineContent(lineContentSpan, data, i);
+    });
+    lineContentSpan.appendChild(expand);
+  }
+}
+
+// Function to create a renderer that only materializes the visible lines
+function createVirtualRenderer(data, coveredSet, pre, viewport) {
+  const content = document.getElementById('content');
+  let lineHeight = 0;
+  let renderedStart = -1;
+  let renderedEnd = -1;
+  
+  return function() {
+    // Measure the line height once the file is actually displayed
+    if (!lineHeight) {
+      const probe = createLineElement(data, coveredSet, 0);
+      viewport.appendChild(probe);
+      lineHeight = probe.getBoundingClientRect().height || 20;
+      viewport.removeChild(probe);
+      pre.style.height = (data.source.length * lineHeight) + 'px';
+    }
+    
+    const offset = content.scrollTop - pre.offsetTop;
+    const visible = Math.ceil(content.clientHeight / lineHeight);
+    const start = Math.max(0, Math.floor(offset / lineHeight) - VIRTUAL_OVERSCAN);
+    const end = Math.min(data.source.length, start + visible + 2 * VIRTUAL_OVERSCAN);
+    if (start === renderedStart && end === renderedEnd) return;
+    
+    const fragment = document.createDocumentFragment();
+    for (let i = start; i < end; i++) {
+      fragment.appendChild(createLineElement(data, coveredSet, i));
+    }
+    viewport.replaceChildren(fragment);
+    viewport.style.transform = `translateY(${start * lineHeight}px)`;
+    renderedStart = start;
+    renderedEnd = end;
+  };
+}
+
+// Re-render the visible window of the currently displayed virtual file
+function setupVirtualScrolling() {
+  const content = document.getElementById('content');
+  let pending = false;
+  content.addEventListener('scroll', function() {
+    if (pending) return;
+    pending = true;
+    requestAnimationFrame(function() {
+      pending = false;
+      document.querySelectorAll('.file-content').forEach(container => {
+        if (container.style.display === 'block' && container.renderWindow) {
+          container.renderWindow();
+        }
+      });
+    });
+  });
+}
+
+// Function to show a specific file
+function showFile(fileId) {
+  // Hide welcome message and all file content
+  document.getElementById('welcome').style.display = 'none';
+  const fileContainers = document.querySelectorAll('.file-content');
+  fileContainers.forEach(container => {
+    container.style.display = 'none';
+  });
+  
+  // Get the file container
+  const fileContainer = document.getElementById('file_' + fileId);
+  if (!fileContainer) return;
+  
+  // If the file hasn't been loaded yet, generate the content
+  if (fileContainer.innerHTML === '') {
+    const data = fileData[fileId];
+    if (!data) return;
+    
+    if (data.missing) {
+      renderMissingFileContent(fileContainer, data);
+    } else if (reportConfig.sourceUrlTemplate) {
+      renderLinkedFileContent(fileContainer, data);
+    } else if (data.source) {
+      renderFileContent(fileContainer, data);
+    } else {
+      loadFileChunk(fileContainer, data);
+    }
+  }
+  
+  // Show the file container
+  fileContainer.style.display = 'block';
+  if (fileContainer.renderWindow) {
+    fileContainer.renderWindow();
+  }
+  
+  // Highlight the selected file in the sidebar and expand parent directories
+  activeFileId = fileId;
+  expandAncestors(fileData[fileId].path);
+  rebuildTreeRows();
+  
+  const index = treeRows.findIndex(row => row.node.id === fileId);
+  if (index >= 0) {
+    scrollTreeToRow(index, false);
+  }
+}
+
+// Function to fetch a file's lines from its data chunk and render them
+function loadFileChunk(fileContainer, data) {
+  const loading = document.createElement('div');
+  loading.className = 'loading';
+  loading.textContent = `Loading ${data.path}...`;
+  fileContainer.appendChild(loading);
+  
+  fetch(data.chunk)
+    .then(response => {
+      if (!response.ok) throw new Error(response.status + ' ' + response.statusText);
+      return response.json();
+    })
+    .then(chunk => {
+      data.covered = data.liveLines ? Array.from(new Set(chunk.covered.concat(data.liveLines))) : chunk.covered;
+      data.source = chunk.source;
+      fileContainer.replaceChildren();
+      renderFileContent(fileContainer, data);
+      if (fileContainer.style.display === 'block' && fileContainer.renderWindow) {
+        fileContainer.renderWindow();
+      }
+    })
+    .catch(err => {
+      loading.className = 'loading load-error';
+      loading.textContent = `Failed to load ${data.chunk}: ${err.message}`;
+    });
+}
+
+// Function to build the URL of a line in the external code browser
+function sourceUrl(path, line) {
+  return reportConfig.sourceUrlTemplate
+    .replaceAll('{rev}', encodeURIComponent(reportConfig.sourceRev))
+    .replaceAll('{path}', path.split('/').map(encodeURIComponent).join('/'))
+    .replaceAll('{line}', line);
+}
+
+// Function to group sorted line numbers into [start, end] ranges
+function lineRanges(lines) {
+  const sorted = Array.from(lines).sort((a, b) => a - b);
+  const ranges = [];
+  for (const line of sorted) {
+    const last = ranges[ranges.length - 1];
+    if (last && line === last[1] + 1) {
+      last[1] = line;
+    } else {
+      ranges.push([line, line]);
+    }
+  }
+  return ranges;
+}
+
+// Function to build the view of a file whose source lives in an external code browser
+function renderLinkedFileContent(fileContainer, data) {
+  fileContainer.appendChild(createFileHeader(data));
+  
+  const list = document.createElement('div');
+  list.className = 'linked-lines';
+  
+  const ranges = lineRanges(data.covered);
+  for (const [start, end] of ranges) {
+    const lineDiv = document.createElement('div');
+    lineDiv.className = 'line covered';
+    
+    const link = document.createElement('a');
+    link.href = sourceUrl(data.path, start);
+    link.target = '_blank';
+    link.rel = 'noopener';
+    link.textContent = start === end ? `Line ${start}` : `Lines ${start}-${end}`;
+    lineDiv.appendChild(link);
+    list.appendChild(lineDiv);
+  }
+  
+  if (ranges.length === 0) {
+    list.textContent = 'No covered lines.';
+  }
+  
+  fileContainer.appendChild(list);
+}
+
+// Function to build the view of a file whose source could not be read, by its covered line numbers only
+function renderMissingFileContent(fileContainer, data) {
+  fileContainer.appendChild(createFileHeader(data));
+  
+  const pre = document.createElement('pre');
+  pre.className = 'source-code missing-source';
+  let last = null;
+  for (const lineNum of Array.from(data.covered).sort((a, b) => a - b)) {
+    // The lines between covered ones are elided
+    if (last !== null && lineNum > last + 1) {
+      const gap = document.createElement('div');
+      gap.className = 'line gap';
+      gap.innerHTML = '<span class="line-number">&#8942;</span><span class="line-content"></span>';
+      pre.appendChild(gap);
+    }
+    const lineDiv = document.createElement('div');
+    lineDiv.className = 'line covered';
+    const lineNumSpan = document.createElement('span');
+    lineNumSpan.className = 'line-number';
+    lineNumSpan.textContent = lineNum;
+    const lineContentSpan = document.createElement('span');
+    lineContentSpan.className = 'line-content';
+    lineDiv.append(lineNumSpan, lineContentSpan);
+    pre.appendChild(lineDiv);
+    last = lineNum;
+  }
+  
+  fileContainer.appendChild(pre);
+}
+
+// Function to create the header shown above a file's content
+function createFileHeader(data) {
+  const header = document.createElement('div');
+  header.className = 'file-header';
+  if (data.missing) {
+    header.innerHTML = `
+    <h2 class="breadcrumbs">${renderBreadcrumbs(data.path)}</h2>
+    <div class="coverage-summary">${data.coveredCount} covered lines <span class="missing-badge">source not found</span></div>
+  `;
+    header.querySelector('.missing-badge').title = data.missing;
+  } else {
+    header.innerHTML = `
+    <h2 class="breadcrumbs">${renderBreadcrumbs(data.path)}</h2>
+    <div class=\"coverage-summary\">Coverage: <span class=\"${getCoverageClass(data.coveragePct)}\">${data.coveragePct.toFixed(1)}%</span> <span title=\"${data.sourceLines} lines in the file, counting blank, comment and preprocessor lines\">(${data.coveredCount} of ${data.totalLines} lines)</span></div>
+  `;
+  }
+  if (data.gained) {
+    header.querySelector('.coverage-summary').appendChild(createDeltaBadge(data));
+  }
+  if (data.stale) {
+    // Covered lines may be misaligned with a source that changed since
+    const stale = document.createElement('span');
+    stale.className = 'stale-badge';
+    stale.title = data.stale;
+    stale.textContent = 'possibly stale';
+    header.querySelector('.coverage-summary').append(' ', stale);
+  }
+  header.querySelectorAll('.breadcrumb').forEach(crumb => {
+    crumb.addEventListener('click', function(e) {
+      e.preventDefault();
+      revealDirectory(this.getAttribute('data-path'));
+    });
+  });
+  return header;
+}
+
+// Function to create the "+gained -lost" badge of a file compared against a baseline
+function createDeltaBadge(data) {
+  const badge = document.createElement('span');
+  badge.className = 'delta-badge';
+  const gained = document.createElement('span');
+  gained.className = 'diff-gained';
+  gained.textContent = '+' + data.gained.length;
+  const lost = document.createElement('span');
+  lost.className = 'diff-lost';
+  lost.textContent = '-' + data.lost.length;
+  badge.append(gained, ' ', lost);
+  return badge;
+}
+
+// Function to build the header and source view of a file
+function renderFileContent(fileContainer, data) {
+  fileContainer.appendChild(createFileHeader(data));
+  
+  // Create source code container
+  const pre = document.createElement('pre');
+  pre.className = 'source-code lang-' + data.language;
+  const coveredSet = new Set(data.covered);
+  if (data.gained) {
+    data.gainedSet = new Set(data.gained);
+    data.lostSet = new Set(data.lost);
+  }
+  
+  if (data.source.length > VIRTUALIZE_THRESHOLD) {
+    // Huge files only get DOM nodes for the lines that are on screen
+    pre.classList.add('virtual');
+    const viewport = document.createElement('div');
+    viewport.className = 'virtual-window';
+    pre.appendChild(viewport);
+    fileContainer.renderWindow = createVirtualRenderer(data, coveredSet, pre, viewport);
+  } else {
+    // Add each line
+    for (let i = 0; i < data.source.length; i++) {
+      pre.appendChild(createLineElement(data, coveredSet, i));
+    }
+  }
+  
+  fileContainer.appendChild(pre);
+}
+
+// Function to get coverage class based on percentage
+function getCoverageClass(percentage) {
+  if (percentage >= reportConfig.thresholds.good) {
+    return 'coverage-good';
+  } else if (percentage >= reportConfig.thresholds.medium) {
+    return 'coverage-medium';
+  } else {
+    return 'coverage-bad';
+  }
+}
+
+// Height of a single row in the virtualized sidebar tree
+const TREE_ROW_HEIGHT = 28;
+
+// Extra rows rendered above and below the visible part of the tree
+const TREE_OVERSCAN = 20;
+
+// Flattened list of the rows currently visible in the tree
+let treeRows = [];
+const expandedDirs = new Set();
+let activeFileId = null;
+let revealedDir = null;
+let treeRenderedStart = -1;
+let treeRenderedEnd = -1;
+
+// Expand every directory above the given path
+function expandAncestors(path) {
+  const parts = path.split('/');
+  let dirPath = '';
+  for (let i = 0; i < parts.length - 1; i++) {
+    dirPath = dirPath ? dirPath + '/' + parts[i] : parts[i];
+    expandedDirs.add(dirPath);
+  }
+}
+
+// Recompute the visible rows after directories were expanded or collapsed
+function rebuildTreeRows() {
+  treeRows = [];
+  (function walk(nodes, depth) {
+    for (const node of nodes) {
+      treeRows.push({ node: node, depth: depth });
+      if (node.children && expandedDirs.has(node.path)) {
+        walk(node.children, depth + 1);
+      }
+    }
+  })(treeData, 0);
+  
+  document.getElementById('tree').style.height = (treeRows.length * TREE_ROW_HEIGHT) + 'px';
+  renderTree(true);
+}
+
+// Function to create the DOM node for a single tree row
+function createTreeRow(row) {
+  const node = row.node;
+  const rowDiv = document.createElement('div');
+  rowDiv.style.paddingLeft = (row.depth * 15) + 'px';
+  
+  if (node.children) {
+    rowDiv.className = 'tree-row directory' + (selectedDirs.has(node.path) ? ' selected' : '');
+    rowDiv.setAttribute('data-path', node.path);
+    
+    const toggle = document.createElement('div');
+    toggle.className = 'tree-toggle'
+      + (expandedDirs.has(node.path) ? ' expanded' : '')
+      + (revealedDir === node.path ? ' revealed' : '');
+    toggle.textContent = node.name + '/';
+    rowDiv.appendChild(toggle);
+  } else {
+    rowDiv.className = 'tree-row file-entry'
+      + (node.id === activeFileId ? ' active' : '')
+      + (selectedFiles.has(node.id) ? ' selected' : '');
+    
+    const coveragePct = node.total > 0 ? (node.covered / node.total) * 100.0 : 0.0;
+    const link = document.createElement('a');
+    link.href = '#';
+    link.className = 'file-link';
+    link.setAttribute('data-id', node.id);
+    link.textContent = node.name + ' ';
+    
+    const badge = document.createElement('span');
+    badge.className = 'coverage-badge ' + getCoverageClass(coveragePct);
+    badge.textContent = `(${coveragePct.toFixed(1)}%)`;
+    link.appendChild(badge);
+    
+    // Show how the file changed against the baseline, if there is one
+    const data = fileData[node.id];
+    if (data && data.gained && (data.gained.length || data.lost.length)) {
+      link.appendChild(createDeltaBadge(data));
+    }
+    rowDiv.appendChild(link);
+  }
+  
+  return rowDiv;
+}
+
+// Render the rows of the tree that are inside the sidebar's viewport
+function renderTree(force) {
+  const sidebar = document.getElementById('sidebar');
+  const tree = document.getElementById('tree');
+  const treeWindow = document.getElementById('tree-window');
+  
+  const offset = sidebar.scrollTop - tree.offsetTop;
+  const visible = Math.ceil(sidebar.clientHeight / TREE_ROW_HEIGHT);
+  const start = Math.max(0, Math.floor(offset / TREE_ROW_HEIGHT) - TREE_OVERSCAN);
+  const end = Math.min(treeRows.length, start + visible + 2 * TREE_OVERSCAN);
+  if (!force && start === treeRenderedStart && end === treeRenderedEnd) return;
+  
+  const fragment = document.createDocumentFragment();
+  for (let i = start; i < end; i++) {
+    fragment.appendChild(createTreeRow(treeRows[i]));
+  }
+  treeWindow.replaceChildren(fragment);
+  treeWindow.style.transform = `translateY(${start * TREE_ROW_HEIGHT}px)`;
+  treeRenderedStart = start;
+  treeRenderedEnd = end;
+}
+
+// Scroll the sidebar so the given row is visible
+function scrollTreeToRow(index, alignTop) {
+  const sidebar = document.getElementById('sidebar');
+  const tree = document.getElementById('tree');
+  const rowTop = tree.offsetTop + index * TREE_ROW_HEIGHT;
+  
+  if (alignTop) {
+    sidebar.scrollTop = rowTop;
+  } else if (rowTop < sidebar.scrollTop || rowTop + TREE_ROW_HEIGHT > sidebar.scrollTop + sidebar.clientHeight) {
+    sidebar.scrollTop = rowTop - sidebar.clientHeight / 2;
+  }
+  renderTree(true);
+}
+
+// Files and directories selected with ctrl/cmd-click
+const selectedFiles = new Set();
+const selectedDirs = new Set();
+
+// Add or remove a tree row from the selection
+function toggleSelection(row) {
+  if (row.classList.contains('directory')) {
+    const path = row.getAttribute('data-path');
+    if (!selectedDirs.delete(path)) selectedDirs.add(path);
+  } else {
+    const id = row.querySelector('.file-link').getAttribute('data-id');
+    if (!selectedFiles.delete(id)) selectedFiles.add(id);
+  }
+  renderTree(true);
+  updateSelectionSummary();
+}
+
+// Show the combined coverage of the current selection
+function updateSelectionSummary() {
+  const summary = document.getElementById('selection-summary');
+  const count = selectedFiles.size + selectedDirs.size;
+  if (count === 0) {
+    summary.replaceChildren();
+    return;
+  }
+  
+  // Collect every selected file once, even if its directory is selected too
+  const files = new Map();
+  (function collect(nodes, all) {
+    for (const node of nodes) {
+      if (node.children) {
+        collect(node.children, all || selectedDirs.has(node.path));
+      } else if (all || selectedFiles.has(node.id)) {
+        files.set(node.id, node);
+      }
+    }
+  })(treeData, false);
+  
+  let covered = 0;
+  let total = 0;
+  files.forEach(node => {
+    covered += node.covered;
+    total += node.total;
+  });
+  const coveragePct = total > 0 ? (covered / total) * 100.0 : 0.0;
+  
+  summary.innerHTML = `Selection (${count} item${count === 1 ? '' : 's'}, ${files.size} file${files.size === 1 ? '' : 's'}): `
+    + `<span class="${getCoverageClass(coveragePct)}">${coveragePct.toFixed(1)}%</span> (${covered} of ${total} lines) `
+    + `<a href="#" id="clear-selection">Clear</a>`;
+  document.getElementById('clear-selection').addEventListener('click', function(e) {
+    e.preventDefault();
+    selectedFiles.clear();
+    selectedDirs.clear();
+    renderTree(true);
+    updateSelectionSummary();
+  });
+}
+
+// Set up the virtualized sidebar tree
+function setupTree() {
+  // Top-level directories start out expanded
+  treeData.forEach(node => {
+    if (node.children) {
+      expandedDirs.add(node.path);
+    }
+  });
+  
+  const tree = document.getElementById('tree');
+  tree.addEventListener('click', function(e) {
+    // Ctrl/Cmd-click adds files and directories to the selection
+    if (e.ctrlKey || e.metaKey) {
+      const row = e.target.closest('.tree-row');
+      if (row) {
+        e.preventDefault();
+        toggleSelection(row);
+      }
+      return;
+    }
+    
+    const link = e.target.closest('.file-link');
+    if (link) {
+      e.preventDefault();
+      showFile(link.getAttribute('data-id'));
+      return;
+    }
+    
+    const dir = e.target.closest('.directory');
+    if (dir) {
+      const path = dir.getAttribute('data-path');
+      if (expandedDirs.has(path)) {
+        expandedDirs.delete(path);
+      } else {
+        expandedDirs.add(path);
+      }
+      rebuildTreeRows();
+    }
+  });
+  
+  let pending = false;
+  function scheduleRender() {
+    if (pending) return;
+    pending = true;
+    requestAnimationFrame(function() {
+      pending = false;
+      renderTree(false);
+    });
+  }
+  document.getElementById('sidebar').addEventListener('scroll', scheduleRender);
+  window.addEventListener('resize', scheduleRender);
+  
+  // Files with missing sources are not in the tree, only listed on the welcome page
+  document.querySelectorAll('.missing-sources a[data-file]').forEach(link => {
+    link.addEventListener('click', function(e) {
+      e.preventDefault();
+      showFile(this.getAttribute('data-file'));
+    });
+  });
+  
+  rebuildTreeRows();
+}
+
+// Set up the drag handle between the sidebar and the content pane
+function setupSidebarResizer() {
+  const sidebar = document.getElementById('sidebar');
+  const resizer = document.getElementById('sidebar-resizer');
+  if (!sidebar || !resizer) return;
+  
+  // Restore the previously chosen width
+  const savedWidth = parseInt(localStorage.getItem('cov2html.sidebarWidth'), 10);
+  if (!isNaN(savedWidth)) {
+    sidebar.style.width = savedWidth + 'px';
+  }
+  
+  resizer.addEventListener('mousedown', function(e) {
+    e.preventDefault();
+    resizer.classList.add('dragging');
+    document.body.classList.add('resizing');
+    
+    function onMouseMove(e) {
+      const maxWidth = window.innerWidth - 200;
+      const width = Math.max(150, Math.min(e.clientX, maxWidth));
+      sidebar.style.width = width + 'px';
+    }
+    
+    function onMouseUp() {
+      resizer.classList.remove('dragging');
+      document.body.classList.remove('resizing');
+      document.removeEventListener('mousemove', onMouseMove);
+      document.removeEventListener('mouseup', onMouseUp);
+      localStorage.setItem('cov2html.sidebarWidth', parseInt(sidebar.style.width, 10));
+    }
+    
+    document.addEventListener('mousemove', onMouseMove);
+    document.addEventListener('mouseup', onMouseUp);
+  });
+  
+  // Double-click restores the default width
+  resizer.addEventListener('dblclick', function() {
+    sidebar.style.width = '';
+    localStorage.removeItem('cov2html.sidebarWidth');
+  });
+}
+
+// Apply line wrapping and tab width to the source views
+function applyViewSettings(wrapLines, tabWidth) {
+  document.body.classList.toggle('wrap-lines', wrapLines);
+  document.documentElement.style.setProperty('--tab-width', tabWidth);
+}
+
+// Set up the line-wrap and tab-width controls
+function setupViewControls() {
+  const wrapToggle = document.getElementById('wrap-toggle');
+  const tabSelect = document.getElementById('tab-width-select');
+  
+  // Generation-time defaults, overridden by the user's last choice
+  const savedWrap = localStorage.getItem('cov2html.wrapLines');
+  const savedTab = parseInt(localStorage.getItem('cov2html.tabWidth'), 10);
+  const wrapLines = savedWrap !== null ? savedWrap === 'true' : reportConfig.wrapLines;
+  const tabWidth = !isNaN(savedTab) ? savedTab : reportConfig.tabWidth;
+  
+  // Make sure a non-standard tab width is selectable
+  if (!tabSelect.querySelector(`option[value="${tabWidth}"]`)) {
+    const option = document.createElement('option');
+    option.value = tabWidth;
+    option.textContent = tabWidth;
+    tabSelect.appendChild(option);
+  }
+  
+  wrapToggle.checked = wrapLines;
+  tabSelect.value = String(tabWidth);
+  applyViewSettings(wrapLines, tabWidth);
+  
+  wrapToggle.addEventListener('change', function() {
+    localStorage.setItem('cov2html.wrapLines', this.checked);
+    applyViewSettings(this.checked, parseInt(tabSelect.value, 10));
+  });
+  
+  tabSelect.addEventListener('change', function() {
+    localStorage.setItem('cov2html.tabWidth', this.value);
+    applyViewSettings(wrapToggle.checked, parseInt(this.value, 10));
+  });
+}
+
+// Initialize when the page loads
+// Function to merge a live coverage update into the report and refresh what is on screen
+function applyLiveUpdate(update) {
+  if (update.reload) {
+    showLiveNotice('New files were covered. ', 'Reload the report');
+    return;
+  }
+  
+  const content = document.getElementById('content');
+  for (const [fileId, change] of Object.entries(update.files)) {
+    const data = fileData[fileId];
+    if (!data) continue;
+    
+    // Chunks that are not loaded yet pick these lines up when they arrive
+    data.liveLines = (data.liveLines || []).concat(change.lines);
+    if (data.covered) {
+      data.covered = Array.from(new Set(data.covered.concat(change.lines)));
+    }
+    data.coveredCount = change.coveredCount;
+    data.totalLines = change.totalLines;
+    data.coveragePct = change.totalLines > 0 ? (change.coveredCount / change.totalLines) * 100.0 : 0.0;
+    
+    const node = findTreeNode(treeData, fileId);
+    if (node) {
+      node.covered = change.coveredCount;
+      node.total = change.totalLines;
+    }
+    
+    // Re-render files that were already displayed, keeping the scroll position
+    const fileContainer = document.getElementById('file_' + fileId);
+    if (fileContainer && fileContainer.innerHTML !== '' && (data.source || reportConfig.sourceUrlTemplate)) {
+      const scrollTop = content.scrollTop;
+      fileContainer.replaceChildren();
+      fileContainer.renderWindow = null;
+      if (reportConfig.sourceUrlTemplate) {
+        renderLinkedFileContent(fileContainer, data);
+      } else {
+        renderFileContent(fileContainer, data);
+      }
+      if (fileContainer.style.display === 'block' && fileContainer.renderWindow) {
+        fileContainer.renderWindow();
+      }
+      content.scrollTop = scrollTop;
+    }
+  }
+  
+  const overallPct = update.total > 0 ? (update.covered / update.total) * 100.0 : 0.0;
+  document.getElementById('overall-summary').innerHTML =
+    `Overall: <span class="${getCoverageClass(overallPct)}">${overallPct.toFixed(1)}%</span> (${update.covered} of ${update.total} lines)`;
+  renderTree(true);
+}
+
+// Function to find the tree node of a file by its id
+function findTreeNode(nodes, fileId) {
+  for (const node of nodes) {
+    if (node.id === fileId) return node;
+    if (node.children) {
+      const found = findTreeNode(node.children, fileId);
+      if (found) return found;
+    }
+  }
+  return null;
+}
+
+// Function to show a notice about the live connection below the sidebar header
+function showLiveNotice(message, linkText) {
+  let notice = document.getElementById('live-notice');
+  if (!notice) {
+    notice = document.createElement('div');
+    notice.id = 'live-notice';
+    notice.className = 'live-notice';
+    document.querySelector('.coverage-header').appendChild(notice);
+  }
+  notice.textContent = message;
+  if (linkText) {
+    const link = document.createElement('a');
+    link.href = '#';
+    link.textContent = linkText;
+    link.addEventListener('click', function(e) {
+      e.preventDefault();
+      location.reload();
+    });
+    notice.appendChild(link);
+  }
+}
+
+// Subscribe to the coverage updates pushed while the fuzzer is running
+function setupLiveUpdates() {
+  if (!reportConfig.live || !window.EventSource) return;
+  
+  const events = new EventSource('events');
+  events.onmessage = function(e) {
+    applyLiveUpdate(JSON.parse(e.data));
+  };
+  events.onerror = function() {
+    showLiveNotice('Live updates disconnected, retrying...');
+  };
+  events.onopen = function() {
+    const notice = document.getElementById('live-notice');
+    if (notice && !notice.querySelector('a')) notice.remove();
+  };
+}
+
+// Keep the pages and data of a served or multi-page report cached for
+// browsing offline; service workers need the report to come over HTTP
+function setupServiceWorker() {
+  if (!reportConfig.serviceWorker || !('serviceWorker' in navigator) || location.protocol === 'file:') return;
+  navigator.serviceWorker.register('sw.js').catch(() => {});
+}
+
+window.onload = function() {
+  setupTree();
+  setupSidebarResizer();
+  setupViewControls();
+  setupVirtualScrolling();
+  setupLiveUpdates();
+  setupServiceWorker();
+};
@@ -0,0 +1,27 @@
+// Service worker of a served or multi-page report: pages, scripts and data
+// chunks come from the network while it is reachable and from the copy kept
+// on the last visit when it is not, so an opened report stays browsable
+// offline or on a flaky VPN
+const CACHE = 'cov2html-report';
+
+self.addEventListener('install', () => self.skipWaiting());
+self.addEventListener('activate', (event) => event.waitUntil(self.clients.claim()));
+
+self.addEventListener('fetch', (event) => {
+  const request = event.request;
+  const url = new URL(request.url);
+  // Live updates are a stream that only makes sense online
+  if (request.method !== 'GET' || url.origin !== self.location.origin || url.pathname.endsWith('/events')) return;
+
+  event.respondWith(
+    fetch(request)
+      .then((response) => {
+        if (response.ok) {
+          const copy = response.clone();
+          event.waitUntil(caches.open(CACHE).then((cache) => cache.put(request, copy)));
+        }
+        return response;
+      })
+      .catch(() => caches.match(request).then((cached) => cached || Response.error()))
+  );
+});